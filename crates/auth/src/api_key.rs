@@ -0,0 +1,212 @@
+use crate::error::{Error, Result};
+use axum::http::{header, HeaderMap};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use rand::{rngs::OsRng, RngCore};
+use std::{fmt, str::FromStr};
+
+/// The prefix every api key starts with, so leaked keys are easy to recognize.
+pub const API_KEY_PREFIX: &str = "lkp";
+
+/// The authorization scheme used to send an api key in the `Authorization` header.
+pub const API_KEY_SCHEME: &str = "ApiKey";
+
+/// The header services use to send an api key directly.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The number of base62 characters in a secret, enough for 256 bits of entropy.
+const SECRET_LENGTH: usize = 43;
+
+/// The number of base62 characters needed for a crc32 checksum.
+const CHECKSUM_LENGTH: usize = 6;
+
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// An api key as it is given to its owner, in the form `lkp_<id>_<secret>_<checksum>`.
+/// The checksum lets secret scanners tell real keys from random strings without asking lockpad.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKeyToken {
+    api_key_id: String,
+    secret: String,
+}
+
+impl ApiKeyToken {
+    /// Create a key for the given id with a random secret
+    pub fn generate(api_key_id: String) -> Self {
+        let mut secret = String::with_capacity(SECRET_LENGTH);
+        let mut bytes = [0u8; 64];
+        while secret.len() < SECRET_LENGTH {
+            OsRng.fill_bytes(&mut bytes);
+            // Rejecting the top of the range keeps every character equally likely.
+            for byte in bytes.iter().filter(|&&byte| byte < 248) {
+                if secret.len() == SECRET_LENGTH {
+                    break;
+                }
+                secret.push(BASE62[(byte % 62) as usize] as char);
+            }
+        }
+
+        Self { api_key_id, secret }
+    }
+
+    pub fn api_key_id(&self) -> &str {
+        &self.api_key_id
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Read the key from an `X-Api-Key: <key>` or `Authorization: ApiKey <key>` header, if the request has one
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>> {
+        if let Some(value) = headers.get(API_KEY_HEADER) {
+            let value = value.to_str().map_err(|_| Error::InvalidApiKey)?;
+            return Ok(Some(value.trim().parse()?));
+        }
+
+        let Some(value) = headers.get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let value = value.to_str().map_err(|_| Error::InvalidApiKey)?;
+
+        match value.split_once(' ') {
+            Some((scheme, key)) if scheme.eq_ignore_ascii_case(API_KEY_SCHEME) => {
+                Ok(Some(key.trim().parse()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn body(&self) -> String {
+        format!("{API_KEY_PREFIX}_{}_{}", self.api_key_id, self.secret)
+    }
+}
+
+fn checksum(body: &str) -> String {
+    let mut value = CRC.checksum(body.as_bytes());
+    let mut encoded = [b'0'; CHECKSUM_LENGTH];
+    for position in encoded.iter_mut().rev() {
+        *position = BASE62[(value % 62) as usize];
+        value /= 62;
+    }
+
+    String::from_utf8_lossy(&encoded).into_owned()
+}
+
+impl FromStr for ApiKeyToken {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut parts = value.split('_');
+        let (Some(API_KEY_PREFIX), Some(api_key_id), Some(secret), Some(crc), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Err(Error::InvalidApiKey);
+        };
+
+        let alphanumeric = |part: &str| part.bytes().all(|byte| byte.is_ascii_alphanumeric());
+        if api_key_id.is_empty()
+            || !alphanumeric(api_key_id)
+            || secret.len() != SECRET_LENGTH
+            || !alphanumeric(secret)
+        {
+            return Err(Error::InvalidApiKey);
+        }
+
+        let token = Self {
+            api_key_id: api_key_id.to_string(),
+            secret: secret.to_string(),
+        };
+        if checksum(&token.body()) != crc {
+            return Err(Error::InvalidApiKey);
+        }
+
+        Ok(token)
+    }
+}
+
+impl fmt::Display for ApiKeyToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.body();
+        write!(f, "{body}_{}", checksum(&body))
+    }
+}
+
+impl fmt::Debug for ApiKeyToken {
+    /// Leaves out the secret so keys don't end up in logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyToken")
+            .field("api_key_id", &self.api_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "01GVF4BCM32TTM19YBFB2ZNE4R";
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let token = ApiKeyToken::generate(ID.to_string());
+        let encoded = token.to_string();
+        assert!(encoded.starts_with("lkp_01GVF4BCM32TTM19YBFB2ZNE4R_"));
+        assert_eq!(
+            encoded.len(),
+            4 + ID.len() + 1 + SECRET_LENGTH + 1 + CHECKSUM_LENGTH
+        );
+
+        let parsed: ApiKeyToken = encoded.parse()?;
+        assert_eq!(parsed, token);
+        assert_ne!(ApiKeyToken::generate(ID.to_string()), token);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_tampered_keys() {
+        let encoded = ApiKeyToken::generate(ID.to_string()).to_string();
+
+        // Change one character of the secret.
+        let mut tampered = encoded.clone().into_bytes();
+        let index = 4 + ID.len() + 1;
+        tampered[index] = if tampered[index] == b'a' { b'b' } else { b'a' };
+        let tampered = String::from_utf8(tampered).unwrap();
+
+        assert!(tampered.parse::<ApiKeyToken>().is_err());
+        assert!(encoded
+            .replacen("lkp", "abc", 1)
+            .parse::<ApiKeyToken>()
+            .is_err());
+        assert!(format!("{encoded}_extra").parse::<ApiKeyToken>().is_err());
+        assert!("lkp_id_secret".parse::<ApiKeyToken>().is_err());
+    }
+
+    #[test]
+    fn reads_authorization_header() -> Result<()> {
+        let token = ApiKeyToken::generate(ID.to_string());
+        let mut headers = HeaderMap::new();
+        assert_eq!(ApiKeyToken::from_headers(&headers)?, None);
+
+        headers.insert(header::AUTHORIZATION, "Bearer abc".parse().unwrap());
+        assert_eq!(ApiKeyToken::from_headers(&headers)?, None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("ApiKey {token}").parse().unwrap(),
+        );
+        assert_eq!(ApiKeyToken::from_headers(&headers)?, Some(token.clone()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, token.to_string().parse().unwrap());
+        assert_eq!(ApiKeyToken::from_headers(&headers)?, Some(token));
+
+        Ok(())
+    }
+}
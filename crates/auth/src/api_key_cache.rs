@@ -0,0 +1,160 @@
+use crate::{api_key::ApiKeyToken, purge::PurgeSchedule};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// What a verified api key is allowed to act as.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiKeyIdentity {
+    pub api_key_id: String,
+    /// The user the key belongs to
+    pub owner_id: String,
+    /// The scopes the key is limited to, empty when it can do everything its owner can
+    pub scopes: Vec<String>,
+    /// When the key stops working, in seconds since the Unix epoch, absent for keys that don't expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl ApiKeyIdentity {
+    /// How much longer the key works, `None` for keys that don't expire
+    pub fn valid_for(&self) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.expires_at
+            .map(|expires_at| Duration::from_secs(expires_at.saturating_sub(now)))
+    }
+}
+
+struct CachedKey {
+    /// Hash of the key's secret, so the cache never holds usable keys
+    secret_digest: [u8; 32],
+    identity: ApiKeyIdentity,
+    cached_until: Instant,
+}
+
+/// Remembers recently verified api keys so their secrets don't have to be checked on every request.
+/// Entries are dropped after a fixed time, so revocations elsewhere take effect within that time.
+#[derive(Clone)]
+pub struct ApiKeyCache {
+    ttl: Duration,
+    entries: Arc<RwLock<HashMap<String, CachedKey>>>,
+    purge: Arc<PurgeSchedule>,
+}
+
+impl ApiKeyCache {
+    /// Create a cache that keeps verified keys for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Default::default(),
+            purge: Default::default(),
+        }
+    }
+
+    fn digest(token: &ApiKeyToken) -> [u8; 32] {
+        Sha256::digest(token.secret().as_bytes()).into()
+    }
+
+    /// Look up a key that was verified recently
+    pub fn get(&self, token: &ApiKeyToken) -> Option<ApiKeyIdentity> {
+        let entries = self.entries.read().unwrap_or_else(|err| err.into_inner());
+        let entry = entries.get(token.api_key_id())?;
+
+        if entry.cached_until <= Instant::now() || entry.secret_digest != Self::digest(token) {
+            return None;
+        }
+
+        Some(entry.identity.clone())
+    }
+
+    /// Remember a verified key, no longer than `valid_for` when the key expires sooner than the cache would forget it
+    pub fn insert(
+        &self,
+        token: &ApiKeyToken,
+        identity: ApiKeyIdentity,
+        valid_for: Option<Duration>,
+    ) {
+        let now = Instant::now();
+        let ttl = valid_for.map_or(self.ttl, |valid_for| valid_for.min(self.ttl));
+
+        let mut entries = self.entries.write().unwrap_or_else(|err| err.into_inner());
+        if self.purge.due() {
+            entries.retain(|_, entry| entry.cached_until > now);
+        }
+        entries.insert(
+            token.api_key_id().to_string(),
+            CachedKey {
+                secret_digest: Self::digest(token),
+                identity,
+                cached_until: now + ttl,
+            },
+        );
+    }
+
+    /// Forget a key, so it is verified again the next time it is used
+    pub fn remove(&self, api_key_id: &str) {
+        let mut entries = self.entries.write().unwrap_or_else(|err| err.into_inner());
+        entries.remove(api_key_id);
+    }
+
+    /// Forget every key belonging to the user
+    pub fn remove_owner(&self, owner_id: &str) {
+        let mut entries = self.entries.write().unwrap_or_else(|err| err.into_inner());
+        entries.retain(|_, entry| entry.identity.owner_id != owner_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(token: &ApiKeyToken) -> ApiKeyIdentity {
+        ApiKeyIdentity {
+            api_key_id: token.api_key_id().to_string(),
+            owner_id: "owner".to_string(),
+            scopes: vec![],
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn only_matching_secrets_hit() {
+        let cache = ApiKeyCache::new(Duration::from_secs(60));
+        let token = ApiKeyToken::generate("01GVF4BCM32TTM19YBFB2ZNE4R".to_string());
+        let other = ApiKeyToken::generate("01GVF4BCM32TTM19YBFB2ZNE4R".to_string());
+
+        assert_eq!(cache.get(&token), None);
+        cache.insert(&token, identity(&token), None);
+        assert_eq!(cache.get(&token), Some(identity(&token)));
+        assert_eq!(cache.get(&other), None);
+
+        cache.remove_owner("owner");
+        assert_eq!(cache.get(&token), None);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = ApiKeyCache::new(Duration::from_secs(60));
+        let token = ApiKeyToken::generate("01GVF4BCM32TTM19YBFB2ZNE4R".to_string());
+
+        cache.insert(&token, identity(&token), Some(Duration::ZERO));
+        assert_eq!(cache.get(&token), None);
+    }
+
+    #[test]
+    fn expired_keys_have_no_validity_left() {
+        let token = ApiKeyToken::generate("01GVF4BCM32TTM19YBFB2ZNE4R".to_string());
+        let mut identity = identity(&token);
+        assert_eq!(identity.valid_for(), None);
+
+        identity.expires_at = Some(1);
+        assert_eq!(identity.valid_for(), Some(Duration::ZERO));
+    }
+}
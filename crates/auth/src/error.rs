@@ -14,6 +14,21 @@ pub enum Error {
     JsonError(#[from] serde_json::Error),
     #[error(transparent)]
     DecodeError(#[from] base64::DecodeError),
+
+    #[error("the token is not a logout token")]
+    NotLogoutToken,
+    #[error("the token was issued to another audience")]
+    WrongAudience,
+    #[error("the api key is malformed")]
+    InvalidApiKey,
+    #[error("an api key is required")]
+    ApiKeyRequired,
+    #[error("the api key is unknown, revoked or expired")]
+    InactiveApiKey,
+
+    #[cfg(feature = "introspection")]
+    #[error(transparent)]
+    Introspection(#[from] reqwest::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -21,7 +36,13 @@ pub type Result<T> = std::result::Result<T, Error>;
 impl axum::response::IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         let status = match self {
-            Error::JwtError(_) => axum::http::StatusCode::UNAUTHORIZED,
+            Error::JwtError(_)
+            | Error::NotLogoutToken
+            | Error::InvalidApiKey
+            | Error::ApiKeyRequired
+            | Error::InactiveApiKey => axum::http::StatusCode::UNAUTHORIZED,
+            // The token is genuine, it just doesn't grant access here.
+            Error::WrongAudience => axum::http::StatusCode::FORBIDDEN,
             _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -0,0 +1,145 @@
+use crate::api_key_cache::ApiKeyIdentity;
+use serde::{Deserialize, Serialize};
+
+/// The answer of lockpad's api key introspection endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiKeyIntrospection {
+    /// Whether the key can currently be used
+    pub active: bool,
+    /// Who the key acts as, only present for active keys
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<ApiKeyIdentity>,
+}
+
+#[cfg(feature = "introspection")]
+pub use client::{ApiKeyIntrospector, IntrospectedApiKey};
+
+#[cfg(feature = "introspection")]
+mod client {
+    use super::ApiKeyIntrospection;
+    use crate::{
+        api_key::{ApiKeyToken, API_KEY_HEADER},
+        api_key_cache::{ApiKeyCache, ApiKeyIdentity},
+        error::{Error, Result},
+    };
+    use axum::{
+        extract::{FromRef, FromRequestParts},
+        http::request::Parts,
+        response::{IntoResponse, Response},
+    };
+    use std::time::Duration;
+
+    /// Verifies api keys for a resource server by asking lockpad about them.
+    /// Answers are cached, so a key is only sent to lockpad once per cache period.
+    #[derive(Clone)]
+    pub struct ApiKeyIntrospector {
+        endpoint: String,
+        client: reqwest::Client,
+        cache: ApiKeyCache,
+    }
+
+    impl ApiKeyIntrospector {
+        /// Create an introspector for lockpad's `/api/introspect` endpoint
+        /// Verified keys are remembered for one minute, or until they expire if that is sooner
+        pub fn new(endpoint: String) -> Self {
+            Self {
+                endpoint,
+                client: reqwest::Client::new(),
+                cache: ApiKeyCache::new(Duration::from_secs(60)),
+            }
+        }
+
+        /// Change how long verified keys are remembered
+        pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+            self.cache = ApiKeyCache::new(ttl);
+            self
+        }
+
+        /// Determine what the key may act as, or `None` if it can't be used
+        pub async fn introspect(&self, token: &ApiKeyToken) -> Result<Option<ApiKeyIdentity>> {
+            if let Some(identity) = self.cache.get(token) {
+                return Ok(Some(identity));
+            }
+
+            let response: ApiKeyIntrospection = self
+                .client
+                .post(&self.endpoint)
+                .header(API_KEY_HEADER, token.to_string())
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            match response {
+                ApiKeyIntrospection {
+                    active: true,
+                    identity: Some(identity),
+                } => {
+                    self.cache
+                        .insert(token, identity.clone(), identity.valid_for());
+                    Ok(Some(identity))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    /// An api key sent in the request's headers, verified through introspection
+    pub struct IntrospectedApiKey(pub ApiKeyIdentity);
+
+    #[axum::async_trait]
+    impl<S> FromRequestParts<S> for IntrospectedApiKey
+    where
+        S: Send + Sync,
+        ApiKeyIntrospector: FromRef<S>,
+    {
+        type Rejection = Response;
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            state: &S,
+        ) -> std::result::Result<Self, Self::Rejection> {
+            let token = ApiKeyToken::from_headers(&parts.headers)
+                .and_then(|token| token.ok_or(Error::ApiKeyRequired))
+                .map_err(|err| err.into_response())?;
+
+            let introspector = ApiKeyIntrospector::from_ref(state);
+            match introspector.introspect(&token).await {
+                Ok(Some(identity)) => Ok(IntrospectedApiKey(identity)),
+                Ok(None) => Err(Error::InactiveApiKey.into_response()),
+                Err(err) => {
+                    tracing::warn!(?err, "api key introspection failed");
+                    Err(err.into_response())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inactive_keys_have_no_identity() -> crate::error::Result<()> {
+        let inactive: ApiKeyIntrospection = serde_json::from_str(r#"{"active":false}"#)?;
+        assert!(!inactive.active);
+        assert!(inactive.identity.is_none());
+
+        let active: ApiKeyIntrospection = serde_json::from_str(
+            r#"{"active":true,"api_key_id":"key","owner_id":"owner","scopes":["read"],"expires_at":1700000000}"#,
+        )?;
+        assert_eq!(
+            active.identity,
+            Some(ApiKeyIdentity {
+                api_key_id: "key".to_string(),
+                owner_id: "owner".to_string(),
+                scopes: vec!["read".to_string()],
+                expires_at: Some(1700000000),
+            })
+        );
+
+        Ok(())
+    }
+}
@@ -31,6 +31,11 @@ impl PublicKey {
         })
     }
 
+    /// The size of the key's modulus in bits
+    pub fn bits(&self) -> usize {
+        self.rsa_key.size() * 8
+    }
+
     pub fn parse_from_jwks(jwks_str: &str) -> Result<Vec<Self>> {
         let jwks: jsonwebtoken::jwk::JwkSet = serde_json::from_str(jwks_str)?;
 
@@ -11,10 +11,19 @@ use axum_extra::{
 use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 
+pub mod api_key;
+pub mod api_key_cache;
 pub mod error;
+pub mod introspection;
 pub mod key;
+pub mod logout;
+pub mod purge;
 
+pub use api_key::ApiKeyToken;
+pub use api_key_cache::{ApiKeyCache, ApiKeyIdentity};
 pub use key::PublicKey;
+pub use logout::LogoutToken;
+pub use purge::PurgeSchedule;
 
 /// The claims of a JWT
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,6 +31,16 @@ pub struct Claims {
     pub sub: String,
     pub exp: usize,
     pub iat: usize,
+    /// The lockpad session the token was issued from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Space delimited list of the scopes the token grants
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// The client id of the application the token was issued to.
+    /// Tokens without an audience are meant for lockpad itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
 }
 
 impl Claims {
@@ -37,7 +56,32 @@ impl Claims {
             .unwrap()
             .as_secs() as usize;
 
-        Self { sub, exp, iat }
+        Self {
+            sub,
+            exp,
+            iat,
+            sid: None,
+            scope: None,
+            aud: None,
+        }
+    }
+
+    /// Associate the claims with the session they were issued from
+    pub fn with_session(mut self, sid: String) -> Self {
+        self.sid = Some(sid);
+        self
+    }
+
+    /// Limit the claims to the given scopes
+    pub fn with_scopes(mut self, scopes: &[String]) -> Self {
+        self.scope = (!scopes.is_empty()).then(|| scopes.join(" "));
+        self
+    }
+
+    /// Issue the claims to the application with the given client id
+    pub fn with_audience(mut self, aud: String) -> Self {
+        self.aud = Some(aud);
+        self
     }
 
     /// Encode the claims into a JWT string
@@ -66,11 +110,34 @@ impl Claims {
     }
 }
 
+/// The audience the [`Claims`] extractor accepts tokens for, taken from the state through [`FromRef`].
+/// Applications accept the tokens issued to their client id, lockpad's own api the ones without an audience.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Audience(Option<String>);
+
+impl Audience {
+    /// Accept the tokens issued to the application with the given client id
+    pub fn application(client_id: String) -> Self {
+        Self(Some(client_id))
+    }
+
+    /// Accept the tokens meant for lockpad itself, which have no audience
+    pub fn lockpad() -> Self {
+        Self(None)
+    }
+
+    /// Whether the claims were issued to this audience
+    pub fn accepts(&self, claims: &Claims) -> bool {
+        self.0 == claims.aud
+    }
+}
+
 #[axum::async_trait]
 impl<S> FromRequestParts<S> for Claims
 where
     S: Send + Sync,
     key::PublicKey: axum::extract::FromRef<S>,
+    Audience: axum::extract::FromRef<S>,
 {
     type Rejection = Response;
 
@@ -91,6 +158,16 @@ where
             .await
             .map_err(|err| err.into_response())?;
 
+        let audience: Audience = FromRef::from_ref(state);
+        if !audience.accepts(&claims) {
+            tracing::debug!(
+                claims.sub,
+                claims.aud,
+                "token was issued to another audience"
+            );
+            return Err(error::Error::WrongAudience.into_response());
+        }
+
         Ok(claims)
     }
 }
@@ -0,0 +1,72 @@
+use crate::error::{Error, Result};
+use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The event identifying a logout token, as defined by OpenID Connect Back-Channel Logout.
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// The claims of a logout token.
+/// These are sent to applications to let them know that a user's session has ended.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LogoutToken {
+    pub iss: String,
+    pub sub: String,
+    /// The application the token is meant for
+    pub aud: String,
+    pub iat: usize,
+    pub exp: usize,
+    pub jti: String,
+    /// The session that was ended
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub events: HashMap<String, serde_json::Value>,
+}
+
+impl LogoutToken {
+    /// Create a logout token for the given subject and audience
+    /// The token is only valid for two minutes, it is meant to be delivered immediately
+    pub fn new(iss: String, sub: String, aud: String, jti: String, sid: Option<String>) -> Self {
+        let now = std::time::SystemTime::now();
+        let iat = now.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as usize;
+        let exp = iat + 60 * 2;
+
+        let events = HashMap::from([(
+            BACKCHANNEL_LOGOUT_EVENT.to_string(),
+            serde_json::Value::Object(Default::default()),
+        )]);
+
+        Self {
+            iss,
+            sub,
+            aud,
+            iat,
+            exp,
+            jti,
+            sid,
+            events,
+        }
+    }
+
+    /// Encode the claims into a JWT string
+    pub async fn encode(&self, key: &EncodingKey) -> Result<String> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.typ = Some("logout+jwt".to_string());
+        let token = encode(&header, self, key)?;
+
+        Ok(token)
+    }
+
+    /// Decode a logout token, checking that it was meant for the given audience
+    pub async fn decode(token: &str, key: &DecodingKey, audience: &str) -> Result<Self> {
+        let mut validation = jsonwebtoken::Validation::new(Algorithm::RS256);
+        validation.set_audience(&[audience]);
+        let claims = jsonwebtoken::decode::<Self>(token, key, &validation)?.claims;
+
+        if !claims.events.contains_key(BACKCHANNEL_LOGOUT_EVENT) {
+            return Err(Error::NotLogoutToken);
+        }
+
+        Ok(claims)
+    }
+}
@@ -0,0 +1,37 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How often in-memory stores forget their expired entries.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Remembers when expired entries were last forgotten, so they are looked for at most once every [`PURGE_INTERVAL`]
+/// rather than on every request.
+pub struct PurgeSchedule {
+    last_purge: Mutex<Instant>,
+}
+
+impl Default for PurgeSchedule {
+    fn default() -> Self {
+        Self {
+            last_purge: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl PurgeSchedule {
+    /// Whether it is time to forget expired entries.
+    pub fn due(&self) -> bool {
+        let mut last_purge = self
+            .last_purge
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if last_purge.elapsed() < PURGE_INTERVAL {
+            return false;
+        }
+
+        *last_purge = Instant::now();
+        true
+    }
+}
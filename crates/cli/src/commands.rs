@@ -1,7 +1,15 @@
+pub(crate) mod audit;
+pub(crate) mod db;
 pub(crate) mod key;
 pub(crate) mod server;
+pub(crate) mod users;
+use audit::AuditCommand;
+use db::DbCommand;
 use key::KeyCommand;
+use lockpad::config::Config;
+use lockpad_models::database::Database;
 use server::ServerCommand;
+use users::UsersCommand;
 
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,4 +24,52 @@ pub(crate) enum Commands {
     Server(ServerCommand),
     /// commands for generating keypairs
     Key(KeyCommand),
+    /// commands for managing users
+    Users(UsersCommand),
+    /// commands for managing the database schema
+    Db(DbCommand),
+    /// commands for reading the audit log
+    Audit(AuditCommand),
+}
+
+/// Connects to the database the configuration points at, sqlite if its url is set and postgres otherwise.
+pub(crate) async fn connect(
+    config: &Config,
+    max_connections: u32,
+) -> Result<Database, Box<dyn std::error::Error>> {
+    if let Some(sqlite_url) = &config.sqlite_url {
+        return connect_sqlite(sqlite_url, max_connections).await;
+    }
+
+    let pg_pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(config.postgres_url()?)
+        .await?;
+
+    Ok(Database::Postgres(pg_pool))
+}
+
+/// Opens the sqlite database, creating the file when needed.
+#[cfg(feature = "sqlite")]
+async fn connect_sqlite(
+    sqlite_url: &str,
+    max_connections: u32,
+) -> Result<Database, Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let options = sqlx::sqlite::SqliteConnectOptions::from_str(sqlite_url)?.create_if_missing(true);
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .connect_with(options)
+        .await?;
+
+    Ok(Database::Sqlite(pool))
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn connect_sqlite(
+    _sqlite_url: &str,
+    _max_connections: u32,
+) -> Result<Database, Box<dyn std::error::Error>> {
+    Err("LOCKPAD_SQLITE_URL is set, but lockpad was built without the sqlite feature".into())
 }
@@ -0,0 +1,61 @@
+use lockpad::config::Config;
+use lockpad_models::{audit_event::AuditFilter, Pagination};
+use lockpad_ulid::Ulid;
+use std::io::Write;
+
+/// The number of events fetched from the database at a time while exporting.
+const EXPORT_BATCH_SIZE: usize = 500;
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct AuditCommand {
+    #[clap(subcommand)]
+    pub command: AuditCommands,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum AuditCommands {
+    /// write the audit log to stdout as JSON lines, oldest first
+    Export {
+        /// only export events after this one, to continue a previous export
+        #[arg(long)]
+        after: Option<Ulid>,
+    },
+}
+
+impl AuditCommand {
+    pub(crate) async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::load()?;
+        let repositories = super::connect(&config, 1).await?.repositories();
+
+        match &self.command {
+            AuditCommands::Export { after } => {
+                let filter = AuditFilter::default();
+                let mut pagination = Pagination {
+                    last_key: *after,
+                    count: EXPORT_BATCH_SIZE,
+                };
+                let mut stdout = std::io::stdout().lock();
+
+                loop {
+                    let (events, next) =
+                        repositories.audit_events.query(&filter, pagination).await?;
+                    for event in &events {
+                        serde_json::to_writer(&mut stdout, event)?;
+                        stdout.write_all(b"\n")?;
+                    }
+
+                    if next.last_key.is_none() {
+                        break;
+                    }
+                    pagination = Pagination {
+                        count: EXPORT_BATCH_SIZE,
+                        ..next
+                    };
+                }
+                stdout.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use lockpad::config::Config;
+use tracing::info;
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct DbCommand {
+    #[clap(subcommand)]
+    pub command: DbCommands,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum DbCommands {
+    /// apply every pending migration
+    Migrate,
+    /// list the migrations and whether they have been applied
+    Status,
+    /// revert the most recently applied migration
+    Rollback,
+}
+
+impl DbCommand {
+    pub(crate) async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::load()?;
+        let database = super::connect(&config, 1).await?;
+
+        match &self.command {
+            DbCommands::Migrate => {
+                let applied = database.migrate().await?;
+                for migration in &applied {
+                    info!(
+                        migration.version,
+                        migration.description, "applied migration"
+                    );
+                }
+                if applied.is_empty() {
+                    info!("the database is up to date");
+                }
+            }
+            DbCommands::Status => {
+                for migration in database.status().await? {
+                    let state = if migration.applied {
+                        "applied"
+                    } else {
+                        "pending"
+                    };
+                    println!("{} {state} {}", migration.version, migration.description);
+                }
+            }
+            DbCommands::Rollback => match database.rollback().await? {
+                Some(migration) => {
+                    info!(
+                        migration.version,
+                        migration.description, "reverted migration"
+                    )
+                }
+                None => info!("no migrations have been applied"),
+            },
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
 use lockpad::config::Config;
+use lockpad_http::{lockout::PostgresLockoutStore, rate_limit::PostgresRateLimitStore};
+use lockpad_models::database::Database;
+use std::sync::Arc;
+use tracing::info;
 
 #[derive(clap::Args, Debug)]
 pub(crate) struct ServerCommand {
@@ -7,6 +11,10 @@ pub(crate) struct ServerCommand {
 
     #[arg(default_value = "0.0.0.0:5000", long, short)]
     pub addr: std::net::SocketAddr,
+
+    /// keep all data in memory instead of a database, for demos. Everything is lost on exit
+    #[arg(long)]
+    pub in_memory: bool,
 }
 
 #[derive(clap::Subcommand, Debug)]
@@ -19,18 +27,68 @@ impl ServerCommand {
     pub(crate) async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
         let config = Config::load()?;
 
-        let pg_pool = sqlx::postgres::PgPoolOptions::new()
-            .max_connections(5)
-            .connect(&config.postgres_url)
-            .await?;
+        let mut builder = lockpad_http::Server::builder().addr(self.addr);
+        if self.in_memory {
+            if config.rate_limit_shared {
+                return Err("shared rate limits need a postgres database".into());
+            }
+            if config.lockout_shared {
+                return Err("shared lockouts need a postgres database".into());
+            }
+            builder = builder.in_memory();
+        } else {
+            let database = super::connect(&config, 5).await?;
+            if config.auto_migrate {
+                for migration in database.migrate().await? {
+                    info!(
+                        migration.version,
+                        migration.description, "applied migration"
+                    );
+                }
+            }
+            if let Err(err) = database.ensure_current().await {
+                return Err(format!(
+                    "{err}, apply them with `lockpad-cli db migrate` or set LOCKPAD_AUTO_MIGRATE=true"
+                )
+                .into());
+            }
+
+            builder = builder.repositories(database.repositories());
+            if config.rate_limit_shared {
+                let store = match &database {
+                    Database::Postgres(pool) => PostgresRateLimitStore::new(pool.clone()),
+                    #[cfg(feature = "sqlite")]
+                    Database::Sqlite(_) => {
+                        return Err("shared rate limits need a postgres database".into())
+                    }
+                };
+                builder = builder.rate_limit_store(Arc::new(store));
+            }
+            if config.lockout_shared {
+                let store = match &database {
+                    Database::Postgres(pool) => PostgresLockoutStore::new(pool.clone()),
+                    #[cfg(feature = "sqlite")]
+                    Database::Sqlite(_) => {
+                        return Err("shared lockouts need a postgres database".into())
+                    }
+                };
+                builder = builder.lockout_store(Arc::new(store));
+            }
+        }
 
-        let server = lockpad_http::Server::builder()
-            .addr(self.addr)
-            .pg_pool(pg_pool)
+        builder = builder
             .jwt_secret(config.secret_key.as_bytes().to_owned())
             .jwt_public(config.public_key.as_bytes().to_owned())
-            .disable_signup(config.disable_signup)
-            .build()?;
+            .trust_forwarded_for(config.trust_forwarded_for)
+            .lockout_policy(config.lockout_policy())
+            .password_policy(config.password_policy()?)
+            .password_hashing(config.password_hashing()?)
+            .rate_limit_policy(config.rate_limit_policy())
+            .disable_signup(config.disable_signup);
+        if let Some(issuer) = config.issuer {
+            builder = builder.issuer(issuer);
+        }
+        let server = builder.build()?;
 
         match self.command {
             ServerCommands::Http => server.run().await?,
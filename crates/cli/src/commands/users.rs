@@ -0,0 +1,236 @@
+use lockpad::config::Config;
+use lockpad_http::password::HashKind;
+use lockpad_models::{
+    audit_event::{AuditAction, AuditEvent},
+    entity::Builder,
+    error::Error,
+    repository::Repositories,
+    user::User,
+    user_data::{self, UserData},
+};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct UsersCommand {
+    #[clap(subcommand)]
+    pub command: UsersCommands,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum UsersCommands {
+    /// make a user an administrator
+    Promote { identifier: String },
+    /// remove a user's administrator role
+    Demote { identifier: String },
+    /// create users from another system, with their passwords hashed by bcrypt, scrypt, PBKDF2 or argon2.
+    /// Users log in with their existing passwords, which are rehashed with argon2 when they do
+    Import {
+        /// a JSON array or JSON lines of users with an identifier, a secret and optionally an email, admin and disabled.
+        /// CSV files need a header row with the same names
+        path: PathBuf,
+        /// the format of the file, guessed from its extension when not given
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>,
+    },
+    /// write everything stored about a user to stdout as JSON, to answer a data access request
+    Export { identifier: String },
+    /// delete a user along with their applications, api keys, sessions, grants and authenticator apps.
+    /// The audit log keeps a tombstone with the user's id. Applications aren't sent back-channel logout notifications
+    Delete { identifier: String },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum ImportFormat {
+    Json,
+    Csv,
+}
+
+/// A user in an import file.
+#[derive(Debug, Deserialize)]
+struct ImportedUser {
+    #[serde(alias = "username")]
+    identifier: String,
+    /// The password hash made by the system the user comes from
+    #[serde(alias = "password_hash")]
+    secret: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    admin: bool,
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl ImportedUser {
+    fn into_user(self) -> Result<User, Box<dyn std::error::Error>> {
+        let identifier = self.identifier.trim();
+        if identifier.is_empty() {
+            return Err("the identifier is empty".into());
+        }
+        if HashKind::detect(&self.secret).is_none() {
+            return Err(
+                format!("the secret of {identifier} is not a supported password hash").into(),
+            );
+        }
+
+        let mut builder = User::builder()
+            .identifier(identifier.to_string())
+            .secret(self.secret);
+        if let Some(email) = self.email.filter(|email| !email.trim().is_empty()) {
+            builder = builder.email(email);
+        }
+        let mut user = builder.build()?;
+        user.admin = self.admin;
+        user.disabled = self.disabled;
+
+        Ok(user)
+    }
+}
+
+impl UsersCommand {
+    pub(crate) async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::load()?;
+
+        let repositories = super::connect(&config, 1).await?.repositories();
+
+        match &self.command {
+            UsersCommands::Promote { identifier } => {
+                set_admin(&repositories, identifier, true).await?;
+                info!(identifier, "promoted user to admin");
+            }
+            UsersCommands::Demote { identifier } => {
+                set_admin(&repositories, identifier, false).await?;
+                info!(identifier, "removed admin role from user");
+            }
+            UsersCommands::Import { path, format } => {
+                let users = read_import(path, *format)?;
+                let mut imported = 0;
+                let mut skipped = 0;
+                for user in &users {
+                    match repositories.users.create(user).await {
+                        Ok(()) => imported += 1,
+                        Err(Error::Conflict(_)) => {
+                            warn!(user.identifier, "skipped user, the identifier is taken");
+                            skipped += 1;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                info!(imported, skipped, "imported users");
+            }
+            UsersCommands::Export { identifier } => {
+                let user = by_identifier(&repositories, identifier).await?;
+                let user_id = user.user_id;
+                let data = UserData::collect(&repositories, user).await?;
+                record(&repositories, AuditAction::UserExport, user_id, None).await?;
+
+                let mut stdout = std::io::stdout().lock();
+                serde_json::to_writer_pretty(&mut stdout, &data)?;
+                stdout.write_all(b"\n")?;
+                stdout.flush()?;
+            }
+            UsersCommands::Delete { identifier } => {
+                let user = by_identifier(&repositories, identifier).await?;
+                let erased = user_data::erase(&repositories, &user).await?;
+                record(
+                    &repositories,
+                    AuditAction::UserDelete,
+                    user.user_id,
+                    Some(erased.to_string()),
+                )
+                .await?;
+                info!(identifier, %erased, "deleted user");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the users of an import file, failing on the first one that can't be imported so nothing is imported partially.
+fn read_import(
+    path: &Path,
+    format: Option<ImportFormat>,
+) -> Result<Vec<User>, Box<dyn std::error::Error>> {
+    let format = match format {
+        Some(format) => format,
+        None => match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => ImportFormat::Csv,
+            Some("json" | "jsonl") => ImportFormat::Json,
+            _ => return Err("can't tell the format from the file name, pass --format".into()),
+        },
+    };
+    let contents = std::fs::read_to_string(path)?;
+
+    let records: Vec<ImportedUser> = match format {
+        ImportFormat::Json if contents.trim_start().starts_with('[') => {
+            serde_json::from_str(&contents)?
+        }
+        ImportFormat::Json => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?,
+        ImportFormat::Csv => csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+    };
+
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            record
+                .into_user()
+                .map_err(|err| format!("user {}: {err}", index + 1).into())
+        })
+        .collect()
+}
+
+async fn by_identifier(
+    repositories: &Repositories,
+    identifier: &str,
+) -> Result<User, Box<dyn std::error::Error>> {
+    let user = repositories
+        .users
+        .by_identifier(identifier)
+        .await?
+        .ok_or_else(|| format!("no user with identifier {identifier}"))?;
+
+    Ok(user)
+}
+
+/// Appends an action taken on the user to the audit log, without an actor since it was taken from the command line.
+async fn record(
+    repositories: &Repositories,
+    action: AuditAction,
+    user_id: lockpad_ulid::Ulid,
+    detail: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let event = AuditEvent::builder()
+        .action(action)
+        .target_id(Some(user_id))
+        .detail(detail)
+        .build()?;
+    repositories.audit_events.create(&event).await?;
+
+    Ok(())
+}
+
+async fn set_admin(
+    repositories: &Repositories,
+    identifier: &str,
+    admin: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut user = by_identifier(repositories, identifier).await?;
+
+    user.admin = admin;
+    repositories.users.update(&user).await?;
+
+    Ok(())
+}
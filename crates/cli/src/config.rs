@@ -1,16 +1,75 @@
+use lockpad_http::{
+    lockout::LockoutPolicy,
+    password::{BreachedPasswords, PasswordHashing, PasswordPolicy},
+    rate_limit::{Quota, RateLimitPolicy},
+};
 use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Config {
-    // database
-    pub postgres_url: String,
+    // database, not needed when the server keeps everything in memory
+    pub postgres_url: Option<String>,
+    /// use sqlite instead of postgres, e.g. `sqlite://lockpad.db`
+    pub sqlite_url: Option<String>,
 
     // jwt keys
     pub secret_key: String,
     pub public_key: String,
+    /// identifies this server in issued tokens
+    pub issuer: Option<String>,
+
+    /// use the last address of the X-Forwarded-For header as the client address, only enable behind a proxy that appends to it
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
 
     #[serde(default)]
     pub disable_signup: bool,
+
+    /// failed logins to one identifier before it is locked out
+    pub lockout_threshold: Option<u32>,
+    /// failed logins from one address before it is locked out
+    pub lockout_ip_threshold: Option<u32>,
+    /// how long a lockout lasts
+    pub lockout_seconds: Option<u64>,
+    /// the wait after a failed login, doubling with every further failure
+    pub login_backoff_seconds: Option<u64>,
+    /// count failed logins in postgres so every server using the database shares them
+    #[serde(default)]
+    pub lockout_shared: bool,
+
+    /// the fewest characters a new password may have
+    pub password_min_length: Option<usize>,
+    /// the lowest zxcvbn score a new password needs, from 0 to 4
+    pub password_min_strength: Option<u8>,
+    /// accept passwords containing the username
+    #[serde(default)]
+    pub password_allow_username: bool,
+    /// a directory of Pwned Passwords range files, passwords found in them are refused
+    pub breached_passwords_dir: Option<PathBuf>,
+
+    /// the argon2 variant new password hashes use: argon2id, argon2i or argon2d
+    pub password_hash_algorithm: Option<String>,
+    /// the memory new password hashes use, in KiB
+    pub password_hash_memory_kib: Option<u32>,
+    /// the number of passes over the memory
+    pub password_hash_iterations: Option<u32>,
+    /// the number of lanes the memory is split into
+    pub password_hash_parallelism: Option<u32>,
+
+    /// requests a minute from one address, 0 disables the limit
+    pub rate_limit_per_ip: Option<u32>,
+    /// requests a minute naming one application as their client_id, 0 disables the limit
+    pub rate_limit_per_client: Option<u32>,
+    /// requests a minute with one api key, 0 disables the limit
+    pub rate_limit_per_api_key: Option<u32>,
+    /// keep rate limits in postgres so every server using the database shares them
+    #[serde(default)]
+    pub rate_limit_shared: bool,
+
+    /// apply pending migrations when the server starts, instead of refusing to start
+    #[serde(default)]
+    pub auto_migrate: bool,
 }
 
 impl Config {
@@ -21,4 +80,100 @@ impl Config {
 
         config.try_deserialize()
     }
+
+    /// How failed logins are throttled, the defaults filled in for what isn't configured.
+    pub fn lockout_policy(&self) -> LockoutPolicy {
+        let default = LockoutPolicy::default();
+
+        LockoutPolicy {
+            max_failures: self.lockout_threshold.unwrap_or(default.max_failures),
+            max_failures_per_ip: self
+                .lockout_ip_threshold
+                .unwrap_or(default.max_failures_per_ip),
+            backoff: self
+                .login_backoff_seconds
+                .map_or(default.backoff, Duration::from_secs),
+            lockout: self
+                .lockout_seconds
+                .map_or(default.lockout, Duration::from_secs),
+        }
+    }
+
+    /// The rules new passwords have to follow, the defaults filled in for what isn't configured.
+    pub fn password_policy(&self) -> Result<PasswordPolicy, config::ConfigError> {
+        let default = PasswordPolicy::default();
+
+        let min_strength = self.password_min_strength.unwrap_or(default.min_strength);
+        if min_strength > 4 {
+            return Err(config::ConfigError::Message(format!(
+                "LOCKPAD_PASSWORD_MIN_STRENGTH must be between 0 and 4, not {min_strength}"
+            )));
+        }
+        let breached_passwords = match &self.breached_passwords_dir {
+            Some(directory) if !directory.is_dir() => {
+                return Err(config::ConfigError::Message(format!(
+                    "LOCKPAD_BREACHED_PASSWORDS_DIR {} is not a directory",
+                    directory.display()
+                )))
+            }
+            Some(directory) => Some(BreachedPasswords::new(directory)),
+            None => None,
+        };
+
+        Ok(PasswordPolicy {
+            min_length: self.password_min_length.unwrap_or(default.min_length),
+            min_strength,
+            disallow_username: !self.password_allow_username,
+            breached_passwords,
+        })
+    }
+
+    /// How new password hashes are made, the argon2 defaults filled in for what isn't configured.
+    pub fn password_hashing(&self) -> Result<PasswordHashing, config::ConfigError> {
+        let algorithm = match &self.password_hash_algorithm {
+            Some(algorithm) => algorithm.parse().map_err(|_| {
+                config::ConfigError::Message(format!(
+                    "LOCKPAD_PASSWORD_HASH_ALGORITHM must be argon2id, argon2i or argon2d, not {algorithm}"
+                ))
+            })?,
+            None => argon2::Algorithm::default(),
+        };
+
+        PasswordHashing::new(
+            algorithm,
+            self.password_hash_memory_kib
+                .unwrap_or(argon2::Params::DEFAULT_M_COST),
+            self.password_hash_iterations
+                .unwrap_or(argon2::Params::DEFAULT_T_COST),
+            self.password_hash_parallelism
+                .unwrap_or(argon2::Params::DEFAULT_P_COST),
+        )
+        .map_err(|err| {
+            config::ConfigError::Message(format!("invalid password hash parameters: {err}"))
+        })
+    }
+
+    /// How many requests clients may make, the defaults filled in for what isn't configured.
+    pub fn rate_limit_policy(&self) -> RateLimitPolicy {
+        let default = RateLimitPolicy::default();
+
+        RateLimitPolicy {
+            per_ip: self
+                .rate_limit_per_ip
+                .map_or(default.per_ip, Quota::per_minute),
+            per_client: self
+                .rate_limit_per_client
+                .map_or(default.per_client, Quota::per_minute),
+            per_api_key: self
+                .rate_limit_per_api_key
+                .map_or(default.per_api_key, Quota::per_minute),
+        }
+    }
+
+    /// The postgres url, for commands that can't run without a database.
+    pub fn postgres_url(&self) -> Result<&str, config::ConfigError> {
+        self.postgres_url
+            .as_deref()
+            .ok_or_else(|| config::ConfigError::NotFound("LOCKPAD_POSTGRES_URL".to_string()))
+    }
 }
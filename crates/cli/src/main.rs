@@ -11,6 +11,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     match args.command {
         Commands::Key(key) => key.run().await?,
         Commands::Server(server) => server.run().await?,
+        Commands::Users(users) => users.run().await?,
+        Commands::Db(db) => db.run().await?,
+        Commands::Audit(audit) => audit.run().await?,
     }
 
     Ok(())
@@ -0,0 +1,186 @@
+use crate::{
+    error::{Error, Result},
+    handlers::auth::verify_api_key,
+    ServerState,
+};
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use lockpad_auth::{ApiKeyIdentity, ApiKeyToken};
+use lockpad_models::{api_key::ApiKey, repository::Repositories};
+use lockpad_ulid::Ulid;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use time::OffsetDateTime;
+
+/// How long a verified api key is trusted before its secret is checked again.
+/// Revoking a key through this server takes effect immediately, this bounds how long other changes take.
+pub(crate) const API_KEY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How often the uses of api keys counted in memory are written to the database.
+const API_KEY_USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts the uses of api keys in memory, so requests made with a key don't each write to the database.
+/// The counts are written every [`API_KEY_USAGE_FLUSH_INTERVAL`] by [`ApiKeyUsage::flush_periodically`].
+#[derive(Clone, Default)]
+pub struct ApiKeyUsage {
+    /// The uses of each key since the last flush, and the time of the last one
+    pending: Arc<Mutex<HashMap<Ulid, (i64, OffsetDateTime)>>>,
+}
+
+impl ApiKeyUsage {
+    fn pending(&self) -> MutexGuard<'_, HashMap<Ulid, (i64, OffsetDateTime)>> {
+        self.pending.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Counts a use of the key, written to the database with the next flush.
+    pub(crate) fn record(&self, api_key_id: Ulid) {
+        let now = OffsetDateTime::now_utc();
+        let mut pending = self.pending();
+        let (uses, used_at) = pending.entry(api_key_id).or_insert((0, now));
+        *uses += 1;
+        *used_at = now;
+    }
+
+    /// Writes the uses counted since the last flush.
+    /// Uses that fail to be written are dropped, so a deleted key doesn't fail every flush.
+    pub(crate) async fn flush(&self, repositories: &Repositories) {
+        let pending = std::mem::take(&mut *self.pending());
+        for (api_key_id, (uses, used_at)) in pending {
+            if let Err(err) = repositories
+                .api_keys
+                .record_uses_by_id(&api_key_id, uses, used_at)
+                .await
+            {
+                tracing::warn!(?err, ?api_key_id, uses, "failed to record api key uses");
+            }
+        }
+    }
+
+    /// Flushes the counted uses every [`API_KEY_USAGE_FLUSH_INTERVAL`], for as long as the server runs.
+    pub(crate) async fn flush_periodically(self, repositories: Repositories) {
+        let mut interval = tokio::time::interval(API_KEY_USAGE_FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.flush(&repositories).await;
+        }
+    }
+}
+
+/// Verifies the api key sent in the request's `X-Api-Key` or `Authorization: ApiKey` header.
+/// Keys are checked against the cache first, so argon2 only runs once per key and cache period.
+pub(crate) async fn authenticate_api_key(
+    state: &ServerState,
+    headers: &HeaderMap,
+) -> Result<ApiKeyIdentity> {
+    let token = ApiKeyToken::from_headers(headers)?.ok_or(Error::Unauthorized)?;
+    let api_key_id = Ulid::from_str(token.api_key_id()).map_err(|_| Error::Unauthorized)?;
+
+    let identity = match state.api_key_cache.get(&token) {
+        Some(identity) => identity,
+        None => {
+            let api_key = verify_api_key(&state.repositories, &api_key_id, token.secret()).await?;
+            let identity = identity(&api_key);
+
+            state
+                .api_key_cache
+                .insert(&token, identity.clone(), identity.valid_for());
+
+            identity
+        }
+    };
+
+    state.api_key_usage.record(api_key_id);
+
+    Ok(identity)
+}
+
+pub(crate) fn identity(api_key: &ApiKey) -> ApiKeyIdentity {
+    ApiKeyIdentity {
+        api_key_id: api_key.api_key_id.to_string(),
+        owner_id: api_key.owner_id.to_string(),
+        scopes: api_key.scopes.clone(),
+        expires_at: api_key
+            .expires_at
+            .map(|expires_at| u64::try_from(expires_at.unix_timestamp()).unwrap_or_default()),
+    }
+}
+
+/// The api key the request was made with.
+/// Behind [`require_api_key`] the key verified by the layer is used, otherwise it is verified here.
+pub struct ApiKeyAuth(pub ApiKeyIdentity);
+
+#[async_trait::async_trait]
+impl FromRequestParts<ServerState> for ApiKeyAuth {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        if let Some(identity) = parts.extensions.get::<ApiKeyIdentity>() {
+            return Ok(ApiKeyAuth(identity.clone()));
+        }
+
+        let identity = authenticate_api_key(state, &parts.headers).await?;
+        Ok(ApiKeyAuth(identity))
+    }
+}
+
+/// Middleware rejecting requests without a valid api key, for use with
+/// [`axum::middleware::from_fn_with_state`].
+pub async fn require_api_key(
+    State(state): State<ServerState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    let identity = authenticate_api_key(&state, request.headers()).await?;
+    request.extensions_mut().insert(identity);
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lockpad_models::{entity::Builder, user::User};
+
+    #[tokio::test]
+    async fn uses_are_written_when_flushed() {
+        let repositories = Repositories::memory();
+        let owner = User::builder()
+            .identifier("owner".to_string())
+            .secret("hash".to_string())
+            .build()
+            .unwrap();
+        repositories.users.create(&owner).await.unwrap();
+        let api_key = ApiKey::builder()
+            .owner_id(owner.user_id)
+            .name("key".to_string())
+            .secret("hash".to_string())
+            .build()
+            .unwrap();
+        repositories.api_keys.create(&api_key).await.unwrap();
+        let usage = ApiKeyUsage::default();
+
+        usage.record(api_key.api_key_id);
+        usage.record(api_key.api_key_id);
+        let stored = repositories.api_keys.by_id(&api_key.api_key_id).await;
+        assert_eq!(stored.unwrap().unwrap().use_count, 0);
+
+        usage.flush(&repositories).await;
+        usage.flush(&repositories).await;
+        let stored = repositories.api_keys.by_id(&api_key.api_key_id).await;
+        let stored = stored.unwrap().unwrap();
+        assert_eq!(stored.use_count, 2);
+        assert!(stored.last_used_at.is_some());
+    }
+}
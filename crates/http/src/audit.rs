@@ -0,0 +1,19 @@
+use crate::{client::ClientInfo, error::Result};
+use lockpad_models::{audit_event, entity::Builder, repository::Repositories};
+
+/// Appends the event to the audit log, along with where the request came from.
+pub(crate) async fn record(
+    repositories: &Repositories,
+    client: &ClientInfo,
+    event: audit_event::Builder,
+) -> Result<()> {
+    let event = event
+        .ip_address(client.ip_string())
+        .user_agent(client.user_agent.clone())
+        .build()?;
+
+    tracing::debug!(?event.action, ?event.outcome, "recording audit event");
+    repositories.audit_events.create(&event).await?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::ServerState;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts, Extensions, HeaderMap},
+};
+use std::{convert::Infallible, net::IpAddr, net::SocketAddr};
+
+/// Information about the client making a request.
+/// This is recorded alongside sessions so users can recognize their devices.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub(crate) fn from_parts(parts: &Parts, trust_forwarded_for: bool) -> Self {
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Self {
+            ip_address: client_ip(&parts.headers, &parts.extensions, trust_forwarded_for),
+            user_agent,
+        }
+    }
+
+    pub(crate) fn ip_string(&self) -> Option<String> {
+        self.ip_address.map(|ip| ip.to_string())
+    }
+}
+
+/// The address of the client, taken from `X-Forwarded-For` when it is trusted and the connection otherwise.
+/// Only the last address in the header is used, the one appended by the proxy in front of lockpad.
+/// The ones before it were sent by the client, which can make them up.
+pub(crate) fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_forwarded_for: bool,
+) -> Option<IpAddr> {
+    let forwarded_for = trust_forwarded_for
+        .then(|| headers.get_all("x-forwarded-for").iter().next_back())
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|value| value.trim().parse().ok());
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    forwarded_for.or(peer)
+}
+
+#[async_trait::async_trait]
+impl FromRequestParts<ServerState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts, state.trust_forwarded_for))
+    }
+}
+
+/// Produces a short, human readable description of a user agent, such as "Firefox on Linux".
+pub(crate) fn describe_user_agent(user_agent: Option<&str>) -> String {
+    let parsed = user_agent.and_then(|user_agent| woothee::parser::Parser::new().parse(user_agent));
+
+    match parsed {
+        Some(parsed) if parsed.name != woothee::woothee::VALUE_UNKNOWN => {
+            if parsed.os == woothee::woothee::VALUE_UNKNOWN {
+                parsed.name.to_string()
+            } else {
+                format!("{} on {}", parsed.name, parsed.os)
+            }
+        }
+        _ => match user_agent {
+            Some(user_agent) if !user_agent.is_empty() => user_agent.to_string(),
+            _ => "Unknown device".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_browsers() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0";
+        assert_eq!(describe_user_agent(Some(firefox)), "Firefox on Linux");
+
+        assert_eq!(
+            describe_user_agent(Some("lockpad-test/1.0")),
+            "lockpad-test/1.0"
+        );
+        assert_eq!(describe_user_agent(None), "Unknown device");
+    }
+
+    #[test]
+    fn forwarded_for_uses_the_address_the_proxy_added() {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 443))));
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "x-forwarded-for",
+            "203.0.113.7, 198.51.100.1".parse().unwrap(),
+        );
+        headers.append("x-forwarded-for", "192.0.2.44".parse().unwrap());
+
+        assert_eq!(
+            client_ip(&headers, &extensions, true),
+            Some(IpAddr::from([192, 0, 2, 44]))
+        );
+        assert_eq!(
+            client_ip(&headers, &extensions, false),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+
+        headers.insert(
+            "x-forwarded-for",
+            "203.0.113.7, 198.51.100.1".parse().unwrap(),
+        );
+        assert_eq!(
+            client_ip(&headers, &extensions, true),
+            Some(IpAddr::from([198, 51, 100, 1]))
+        );
+    }
+}
@@ -12,7 +12,7 @@ pub enum Error {
     Time(#[from] std::time::SystemTimeError),
 
     #[error(transparent)]
-    LockpadModels(#[from] lockpad_models::error::Error),
+    LockpadModels(lockpad_models::error::Error),
     #[error(transparent)]
     LockpadAuth(#[from] lockpad_auth::error::Error),
     #[error(transparent)]
@@ -23,8 +23,21 @@ pub enum Error {
 
     #[error("unauthorized")]
     Unauthorized,
+    /// The password was right, but the user has an authenticator app and no code was sent
+    #[error("a one-time code is required")]
+    MfaRequired,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("{0}")]
+    BadRequest(&'static str),
     #[error("not found")]
     NotFound,
+    /// The value of a unique field is already used by another record
+    #[error("{0} is already taken")]
+    Conflict(&'static str),
+    /// The client has to wait before trying again
+    #[error("too many requests, try again in {} seconds", retry_after_secs(.0))]
+    TooManyRequests(std::time::Duration),
 
     #[error(transparent)]
     AxumFormRejection(#[from] axum::extract::rejection::FormRejection),
@@ -34,12 +47,50 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl From<lockpad_models::error::Error> for Error {
+    fn from(err: lockpad_models::error::Error) -> Self {
+        match err {
+            lockpad_models::error::Error::Conflict(field) => Error::Conflict(field),
+            err => Error::LockpadModels(err),
+        }
+    }
+}
+
+/// Whole seconds for a `Retry-After` header, rounded up so clients don't retry too early.
+pub(crate) fn retry_after_secs(wait: &std::time::Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
 impl axum::response::IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         tracing::warn!(?self, "error response");
         let status = match self {
-            Error::Unauthorized => axum::http::StatusCode::UNAUTHORIZED,
+            Error::Unauthorized | Error::MfaRequired => axum::http::StatusCode::UNAUTHORIZED,
+            Error::Forbidden => axum::http::StatusCode::FORBIDDEN,
+            Error::BadRequest(_) => axum::http::StatusCode::BAD_REQUEST,
             Error::NotFound => axum::http::StatusCode::NOT_FOUND,
+            Error::LockpadAuth(
+                lockpad_auth::error::Error::InvalidApiKey
+                | lockpad_auth::error::Error::ApiKeyRequired
+                | lockpad_auth::error::Error::InactiveApiKey,
+            ) => axum::http::StatusCode::UNAUTHORIZED,
+
+            Error::Conflict(field) => {
+                let body = serde_json::json!({
+                    "error": "conflict",
+                    "field": field,
+                    "message": self.to_string(),
+                });
+                return (axum::http::StatusCode::CONFLICT, axum::Json(body)).into_response();
+            }
+            Error::TooManyRequests(wait) => {
+                let retry_after = [(
+                    axum::http::header::RETRY_AFTER,
+                    retry_after_secs(&wait).to_string(),
+                )];
+                let status = axum::http::StatusCode::TOO_MANY_REQUESTS;
+                return (status, retry_after, self.to_string()).into_response();
+            }
 
             Error::AxumFormRejection(_) => axum::http::StatusCode::BAD_REQUEST,
             Error::ValidationError(_) => {
@@ -0,0 +1,396 @@
+use std::str::FromStr;
+
+use crate::{
+    audit,
+    client::ClientInfo,
+    error::{Error, Result},
+    handlers::{
+        auth::hash_string,
+        logout::notify_backchannel,
+        session::revoke_others,
+        user::{export, UserResponse},
+    },
+    pagination::{Page, PageQuery},
+    token_auth::AccountClaims,
+    ServerState,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{FromRequestParts, Path, Query, State},
+    http::{request::Parts, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::Engine;
+use lockpad_models::{
+    audit_event::{AuditAction, AuditEvent, AuditFilter},
+    user::User,
+    user_data,
+};
+use lockpad_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+
+/// An authenticated administrator.
+/// Rejects requests whose token does not belong to an enabled admin user.
+pub(crate) struct Admin(pub User);
+
+#[axum::async_trait]
+impl FromRequestParts<ServerState> for Admin {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let AccountClaims(claims) = AccountClaims::from_request_parts(parts, state).await?;
+
+        let user_id =
+            Ulid::from_str(&claims.sub).map_err(|err| Error::from(err).into_response())?;
+        let user = state
+            .repositories
+            .users
+            .by_id(&user_id)
+            .await
+            .map_err(|err| Error::from(err).into_response())?;
+
+        match user {
+            Some(user) if user.admin && !user.disabled => Ok(Admin(user)),
+            _ => {
+                tracing::debug!(?user_id, "not an admin");
+                Err(Error::Forbidden.into_response())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UserSearch {
+    /// Matches against the identifier and email
+    q: Option<String>,
+}
+
+pub(crate) async fn list_users(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    Admin(_admin): Admin,
+    uri: Uri,
+    Query(search): Query<UserSearch>,
+    Query(page): Query<PageQuery>,
+) -> Result<Page<UserResponse>> {
+    let term = search.q.as_deref().unwrap_or_default();
+    let (users, pagination) = repositories.users.search(term, page.pagination()).await?;
+
+    Ok(Page::new(&uri, users, pagination).map(UserResponse::from))
+}
+
+pub(crate) async fn get_user(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    Admin(_admin): Admin,
+    Path(user_id): Path<Ulid>,
+) -> Result<Json<UserResponse>> {
+    let user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(Json(user.into()))
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UpdateUser {
+    /// An empty string removes the email
+    email: Option<String>,
+    admin: Option<bool>,
+    disabled: Option<bool>,
+}
+
+pub(crate) async fn update_user(
+    State(state): State<ServerState>,
+    Admin(admin): Admin,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+    Json(payload): Json<UpdateUser>,
+) -> Result<Json<UserResponse>> {
+    let mut user = state
+        .repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if user.user_id == admin.user_id
+        && (payload.admin == Some(false) || payload.disabled == Some(true))
+    {
+        return Err(Error::BadRequest(
+            "admins cannot demote or disable themselves",
+        ));
+    }
+
+    let changed: Vec<&str> = [
+        payload.email.as_ref().map(|_| "email"),
+        payload.admin.map(|_| "admin"),
+        payload.disabled.map(|_| "disabled"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if let Some(email) = payload.email {
+        let email = email.trim();
+        user.email = (!email.is_empty()).then(|| email.to_string());
+    }
+    if let Some(is_admin) = payload.admin {
+        user.admin = is_admin;
+    }
+    if let Some(disabled) = payload.disabled {
+        user.disabled = disabled;
+    }
+    state.repositories.users.update(&user).await?;
+    audit::record(
+        &state.repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::UserUpdate)
+            .actor_id(Some(admin.user_id))
+            .target_id(Some(user.user_id))
+            .detail(Some(changed.join(","))),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "user updated by admin");
+
+    if user.disabled {
+        revoke_others(&state, &user.user_id, None).await?;
+        state
+            .repositories
+            .users
+            .revoke_tokens(&user.user_id)
+            .await?;
+        state.api_key_cache.remove_owner(&user.user_id.to_string());
+    }
+
+    Ok(Json(user.into()))
+}
+
+/// Deletes the user along with everything belonging to them.
+/// The audit log keeps a tombstone with the user's id and what was deleted,
+/// their earlier events lose the identifier, addresses and user agents that would tie them to the person.
+pub(crate) async fn delete_user(
+    State(state): State<ServerState>,
+    Admin(admin): Admin,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<StatusCode> {
+    if user_id == admin.user_id {
+        return Err(Error::BadRequest("admins cannot delete themselves"));
+    }
+
+    let user = state
+        .repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    for session in state.repositories.sessions.by_user_id(&user_id).await? {
+        notify_backchannel(&state, &session).await?;
+    }
+    let erased = user_data::erase(&state.repositories, &user).await?;
+    state.api_key_cache.remove_owner(&user.user_id.to_string());
+    audit::record(
+        &state.repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::UserDelete)
+            .actor_id(Some(admin.user_id))
+            .target_id(Some(user.user_id))
+            .detail(Some(erased.to_string())),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "user deleted by admin");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Downloads everything stored about the user, to answer a data access request.
+pub(crate) async fn export_user(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    Admin(admin): Admin,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<Response> {
+    let user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    export(&repositories, &client, admin.user_id, user).await
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PasswordResetResponse {
+    /// The new password, the user should change it after logging in
+    temporary_password: String,
+}
+
+/// Replaces the user's password with a random one and signs them out everywhere, refusing the tokens issued to them so far.
+/// The reset is meant for users who lost access, so their lockout is lifted and their authenticator apps are removed as well.
+pub(crate) async fn reset_password(
+    State(state): State<ServerState>,
+    Admin(admin): Admin,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<Json<PasswordResetResponse>> {
+    let mut user = state
+        .repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let mut bytes = [0u8; 18];
+    OsRng.fill_bytes(&mut bytes);
+    let temporary_password = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+
+    user.secret = hash_string(&state.password_hashing, temporary_password.as_bytes()).await?;
+    state.repositories.users.update(&user).await?;
+    revoke_others(&state, &user.user_id, None).await?;
+    state
+        .repositories
+        .users
+        .revoke_tokens(&user.user_id)
+        .await?;
+    state.login_throttle.unlock(&user.identifier).await?;
+    for factor in state
+        .repositories
+        .mfa_factors
+        .by_user_id(&user.user_id)
+        .await?
+    {
+        state
+            .repositories
+            .mfa_factors
+            .delete(&user.user_id, &factor.mfa_factor_id)
+            .await?;
+    }
+    audit::record(
+        &state.repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::PasswordReset)
+            .actor_id(Some(admin.user_id))
+            .target_id(Some(user.user_id)),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "password reset by admin");
+
+    Ok(Json(PasswordResetResponse { temporary_password }))
+}
+
+/// Lifts a lockout caused by failed logins to the user's account.
+/// Lockouts of the addresses the logins came from are left to expire.
+pub(crate) async fn unlock_user(
+    State(state): State<ServerState>,
+    Admin(admin): Admin,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<StatusCode> {
+    let user = state
+        .repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    state.login_throttle.unlock(&user.identifier).await?;
+    audit::record(
+        &state.repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::Unlock)
+            .actor_id(Some(admin.user_id))
+            .target_id(Some(user.user_id)),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "user unlocked by admin");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists the audit log, oldest first.
+/// Filters on the actor, target, action, outcome and time are taken from the query string.
+pub(crate) async fn list_audit_events(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    Admin(_admin): Admin,
+    uri: Uri,
+    Query(filter): Query<AuditFilter>,
+    Query(page): Query<PageQuery>,
+) -> Result<Page<AuditEvent>> {
+    let (events, pagination) = repositories
+        .audit_events
+        .query(&filter, page.pagination())
+        .await?;
+
+    Ok(Page::new(&uri, events, pagination))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{app, json, register, send, PASSWORD};
+    use axum::http::StatusCode;
+    use lockpad_models::{mfa_factor::MfaFactor, repository::Repositories};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn password_reset_lifts_the_lockout_and_removes_authenticator_apps() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        let admin_token = register(&app, "admin").await;
+        let mut admin = repositories
+            .users
+            .by_identifier("admin")
+            .await
+            .unwrap()
+            .unwrap();
+        admin.admin = true;
+        repositories.users.update(&admin).await.unwrap();
+        register(&app, "forgetful").await;
+        let user = repositories
+            .users
+            .by_identifier("forgetful")
+            .await
+            .unwrap()
+            .unwrap();
+        let factor = MfaFactor::new(
+            user.user_id,
+            "lost phone".to_string(),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(),
+            0,
+        );
+        repositories.mfa_factors.create(&factor).await.unwrap();
+        for _ in 0..5 {
+            let credentials = json!({ "username": "forgetful", "password": "wrong" });
+            send(&app, json("POST", "/api/authorize", None, credentials)).await;
+        }
+        let credentials = json!({ "username": "forgetful", "password": PASSWORD });
+        let request = json("POST", "/api/authorize", None, credentials);
+        assert_eq!(send(&app, request).await.0, StatusCode::TOO_MANY_REQUESTS);
+
+        let uri = format!("/admin/users/{}/password-reset", user.user_id);
+        let (status, _, body) =
+            send(&app, json("POST", &uri, Some(&admin_token), json!(null))).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let reset: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let password = reset["temporary_password"].as_str().unwrap();
+
+        assert!(repositories
+            .mfa_factors
+            .by_user_id(&user.user_id)
+            .await
+            .unwrap()
+            .is_empty());
+        let credentials = json!({ "username": "forgetful", "password": password });
+        let (status, _, body) = send(&app, json("POST", "/api/authorize", None, credentials)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+}
@@ -1,75 +1,300 @@
 use std::str::FromStr;
 
 use crate::{
+    api_key_auth::{authenticate_api_key, ApiKeyAuth},
+    audit,
+    client::ClientInfo,
     error::{Error, Result},
     handlers::auth::hash_string,
+    pagination::{Page, PageQuery},
+    password::PasswordHashing,
+    token_auth::AccountClaims,
     ServerState,
 };
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, Uri},
+    Json,
+};
+use lockpad_auth::{introspection::ApiKeyIntrospection, ApiKeyIdentity, ApiKeyToken};
 use lockpad_models::{
     api_key::{ApiKey, Builder as ApiKeyBuilder},
+    audit_event::{AuditAction, AuditEvent},
     entity::Builder,
+    repository::Repositories,
 };
+use lockpad_ulid::Ulid;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// An api key as it is shown to its owner, without the secret's hash.
+#[derive(Debug, Serialize)]
+pub(crate) struct ApiKeyResponse {
+    api_key_id: Ulid,
+    owner_id: Ulid,
+    name: String,
+    scopes: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    revoked_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_used_at: Option<OffsetDateTime>,
+    use_count: i64,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            api_key_id: api_key.api_key_id,
+            owner_id: api_key.owner_id,
+            name: api_key.name,
+            scopes: api_key.scopes,
+            created_at: api_key.created_at,
+            expires_at: api_key.expires_at,
+            revoked_at: api_key.revoked_at,
+            last_used_at: api_key.last_used_at,
+            use_count: api_key.use_count,
+        }
+    }
+}
+
+/// A newly created api key along with the full key, which is only ever shown here.
+#[derive(Debug, Serialize)]
+pub(crate) struct CreatedApiKey {
+    #[serde(flatten)]
+    api_key: ApiKeyResponse,
+    /// The `lkp_...` key to authenticate with
+    secret: String,
+}
 
 pub(crate) async fn list_api_keys(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
-) -> Result<Json<Vec<ApiKey>>> {
-    let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
-    let pagination = lockpad_models::Pagination {
-        last_key: None,
-        count: 10,
-    };
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    uri: Uri,
+    Query(page): Query<PageQuery>,
+) -> Result<Page<ApiKeyResponse>> {
+    let owner_id = Ulid::from_str(&claims.sub)?;
 
-    let query = ApiKey::query(&pg_pool, owner_id, pagination).await?;
-    let (items, _pagination) = query;
+    let (items, pagination) = repositories
+        .api_keys
+        .query(owner_id, page.pagination())
+        .await?;
 
-    Ok(Json(items))
+    Ok(Page::new(&uri, items, pagination).map(ApiKeyResponse::from))
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct CreateApiKey {
     pub name: String,
+    /// Limits what tokens minted from the key can do
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
 }
 
 pub(crate) async fn create_api_key(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
+    State(ServerState {
+        repositories,
+        password_hashing,
+        ..
+    }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
     payload: axum::extract::Json<CreateApiKey>,
-) -> Result<Json<ApiKey>> {
-    let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
+) -> Result<Json<CreatedApiKey>> {
+    let owner_id = Ulid::from_str(&claims.sub)?;
 
-    let secret = lockpad_ulid::Ulid::generate().to_string();
-    let secret_hash = hash_string(secret.as_bytes()).await?;
+    let payload = payload.0;
+    if payload
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc())
+    {
+        return Err(Error::BadRequest("expires_at must be in the future"));
+    }
+
+    let (item, token) = generate_api_key(
+        &repositories,
+        &password_hashing,
+        &client,
+        owner_id,
+        payload.name,
+        payload.scopes,
+        payload.expires_at,
+    )
+    .await?;
+
+    Ok(Json(CreatedApiKey {
+        api_key: item.into(),
+        secret: token.to_string(),
+    }))
+}
 
-    let mut item = ApiKeyBuilder::default()
-        .name(payload.0.name.to_owned())
+/// Creates a new api key with a random secret.
+/// The returned token is the only time the full `lkp_...` key is available.
+pub(crate) async fn generate_api_key(
+    repositories: &Repositories,
+    hashing: &PasswordHashing,
+    client: &ClientInfo,
+    owner_id: Ulid,
+    name: String,
+    scopes: Vec<String>,
+    expires_at: Option<OffsetDateTime>,
+) -> Result<(ApiKey, ApiKeyToken)> {
+    let api_key_id = Ulid::generate();
+    let token = ApiKeyToken::generate(api_key_id.to_string());
+    let secret_hash = hash_string(hashing, token.secret().as_bytes()).await?;
+
+    let item = ApiKeyBuilder::default()
+        .api_key_id(api_key_id)
+        .name(name)
         .owner_id(owner_id)
         .secret(secret_hash)
+        .scopes(scopes)
+        .expires_at(expires_at)
         .build()?;
 
-    item.create(&pg_pool).await?;
+    repositories.api_keys.create(&item).await?;
+    audit::record(
+        repositories,
+        client,
+        AuditEvent::builder()
+            .action(AuditAction::ApiKeyCreate)
+            .actor_id(Some(owner_id))
+            .target_id(Some(api_key_id)),
+    )
+    .await?;
+
+    tracing::debug!(?item.api_key_id, "created api_key");
+    Ok((item, token))
+}
+
+/// Finds an api key belonging to the caller.
+/// Keys owned by someone else are reported as missing.
+async fn owned_api_key(
+    repositories: &Repositories,
+    claims: &lockpad_auth::Claims,
+    api_key_id: &Ulid,
+) -> Result<ApiKey> {
+    let owner_id = Ulid::from_str(&claims.sub)?;
 
-    // Overwrite the secret with the unhashed version. This is the only time we will return the secret.
-    item.secret = secret;
+    let item = repositories
+        .api_keys
+        .by_owner_and_id(&owner_id, api_key_id)
+        .await?;
 
-    tracing::debug!(?item, "created api_key");
-    Ok(Json(item))
+    item.ok_or(Error::NotFound)
 }
 
 pub(crate) async fn get_api_key(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
-    api_key_id: axum::extract::Path<lockpad_ulid::Ulid>,
-) -> Result<Json<ApiKey>> {
-    let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    api_key_id: axum::extract::Path<Ulid>,
+) -> Result<Json<ApiKeyResponse>> {
+    let item = owned_api_key(&repositories, &claims, &api_key_id).await?;
 
-    let item = ApiKey::by_id(&pg_pool, &api_key_id).await?;
-    let item = item.ok_or(Error::NotFound)?;
+    Ok(Json(item.into()))
+}
+
+/// Revokes the api key, no more tokens can be minted from it.
+/// Tokens minted before stay valid until they expire.
+pub(crate) async fn revoke_api_key(
+    State(state): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
+    api_key_id: axum::extract::Path<Ulid>,
+) -> Result<StatusCode> {
+    let mut item = owned_api_key(&state.repositories, &claims, &api_key_id).await?;
+
+    state.repositories.api_keys.revoke(&mut item).await?;
+    state.api_key_cache.remove(&item.api_key_id.to_string());
+    audit::record(
+        &state.repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::ApiKeyRevoke)
+            .actor_id(Some(item.owner_id))
+            .target_id(Some(item.api_key_id)),
+    )
+    .await?;
+
+    tracing::debug!(?item.api_key_id, "revoked api_key");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Tells a resource server whether the api key in the request's headers can be used, and who it acts as.
+/// Unusable keys are reported as inactive rather than rejected.
+pub(crate) async fn introspect(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiKeyIntrospection>> {
+    let introspection = match authenticate_api_key(&state, &headers).await {
+        Ok(identity) => ApiKeyIntrospection {
+            active: true,
+            identity: Some(identity),
+        },
+        Err(Error::Unauthorized | Error::LockpadAuth(_)) => ApiKeyIntrospection {
+            active: false,
+            identity: None,
+        },
+        Err(err) => return Err(err),
+    };
+
+    Ok(Json(introspection))
+}
 
-    if item.owner_id != owner_id {
-        return Err(Error::NotFound);
+/// Describes the api key the request was made with.
+pub(crate) async fn current_api_key(ApiKeyAuth(identity): ApiKeyAuth) -> Json<ApiKeyIdentity> {
+    Json(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_leave_out_the_secret_hash() -> Result<()> {
+        let api_key = ApiKeyBuilder::default()
+            .owner_id(Ulid::generate())
+            .name("test".to_string())
+            .secret("$argon2id$hash".to_string())
+            .build()?;
+
+        let json = serde_json::to_string(&ApiKeyResponse::from(api_key)).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(!json.contains("argon2"));
+
+        Ok(())
     }
 
-    Ok(Json(item))
+    #[tokio::test]
+    async fn introspection_reports_when_the_key_expires() {
+        use crate::testing::{app, json, register, send};
+        use lockpad_models::repository::Repositories;
+        use serde_json::json;
+
+        let app = app(Repositories::memory());
+        let token = register(&app, "expiring-keys").await;
+        let key = json!({ "name": "ci", "expires_at": "2100-01-01T00:00:00Z" });
+        let (_, _, body) = send(&app, json("POST", "/api-keys", Some(&token), key)).await;
+        let created: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let secret = created["secret"].as_str().unwrap();
+
+        // The second answer comes from the cache, and has to know the expiry as well.
+        for _ in 0..2 {
+            let mut request = json("POST", "/api/introspect", None, json!(null));
+            request
+                .headers_mut()
+                .insert("x-api-key", secret.parse().unwrap());
+            let (status, _, body) = send(&app, request).await;
+            assert_eq!(status, StatusCode::OK, "{body}");
+            let introspection: ApiKeyIntrospection = serde_json::from_str(&body).unwrap();
+            assert!(introspection.active);
+            let identity = introspection.identity.unwrap();
+            assert_eq!(identity.expires_at, Some(4102444800));
+        }
+    }
 }
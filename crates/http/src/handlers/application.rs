@@ -1,29 +1,40 @@
 use std::str::FromStr;
 
 use crate::{
+    audit,
+    client::ClientInfo,
     error::{Error, Result},
+    pagination::{Page, PageQuery},
+    token_auth::AccountClaims,
+    validation::validate_application,
     ServerState,
 };
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, Uri},
+    Json,
+};
 use lockpad_models::{
     application::{Application, Builder as ApplicationBuilder},
+    audit_event::{AuditAction, AuditEvent},
     entity::Builder,
+    repository::Repositories,
 };
 
 pub(crate) async fn list_applications(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
-) -> Result<Json<Vec<Application>>> {
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    uri: Uri,
+    Query(page): Query<PageQuery>,
+) -> Result<Page<Application>> {
     let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
-    let pagination = lockpad_models::Pagination {
-        last_key: None,
-        count: 10,
-    };
 
-    let query = Application::query(&pg_pool, owner_id, pagination).await?;
-    let (items, _pagination) = query;
+    let (items, pagination) = repositories
+        .applications
+        .query(owner_id, page.pagination())
+        .await?;
 
-    Ok(Json(items))
+    Ok(Page::new(&uri, items, pagination))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -31,11 +42,15 @@ pub struct CreateApplication {
     pub name: String,
     pub allowed_origins: Vec<String>,
     pub allowed_callback_urls: Vec<String>,
+    #[serde(default)]
+    pub allowed_logout_urls: Vec<String>,
+    pub backchannel_logout_uri: Option<String>,
 }
 
 pub(crate) async fn create_application(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
     payload: axum::extract::Json<CreateApplication>,
 ) -> Result<Json<Application>> {
     let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
@@ -45,27 +60,167 @@ pub(crate) async fn create_application(
         .owner_id(owner_id)
         .allowed_origins(payload.0.allowed_origins)
         .allowed_callback_urls(payload.0.allowed_callback_urls)
+        .allowed_logout_urls(payload.0.allowed_logout_urls)
+        .backchannel_logout_uri(payload.0.backchannel_logout_uri)
         .build()?;
+    validate_application(&item)?;
 
-    item.create(&pg_pool).await?;
+    repositories.applications.create(&item).await?;
+    record_change(
+        &repositories,
+        &client,
+        AuditAction::ApplicationCreate,
+        &item,
+    )
+    .await?;
 
     tracing::debug!(?item, "created application");
     Ok(Json(item))
 }
 
+/// Records a change the owner made to their application in the audit log.
+pub(crate) async fn record_change(
+    repositories: &Repositories,
+    client: &ClientInfo,
+    action: AuditAction,
+    application: &Application,
+) -> Result<()> {
+    let event = AuditEvent::builder()
+        .action(action)
+        .actor_id(Some(application.owner_id))
+        .target_id(Some(application.application_id));
+
+    audit::record(repositories, client, event).await
+}
+
+/// Finds an application belonging to the caller.
+/// Applications owned by someone else are reported as missing.
+async fn owned_application(
+    repositories: &Repositories,
+    claims: &lockpad_auth::Claims,
+    application_id: &lockpad_ulid::Ulid,
+) -> Result<Application> {
+    let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
+
+    let item = repositories
+        .applications
+        .by_owner_and_id(&owner_id, application_id)
+        .await?;
+
+    item.ok_or(Error::NotFound)
+}
+
 pub(crate) async fn get_application(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-    claims: lockpad_auth::Claims,
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
     application_id: axum::extract::Path<lockpad_ulid::Ulid>,
 ) -> Result<Json<Application>> {
-    let owner_id = lockpad_ulid::Ulid::from_str(&claims.sub)?;
+    let item = owned_application(&repositories, &claims, &application_id).await?;
 
-    let item = Application::by_id(&pg_pool, &application_id).await?;
-    let item = item.ok_or(Error::NotFound)?;
+    Ok(Json(item))
+}
 
-    if item.owner_id != owner_id {
-        return Err(Error::NotFound);
+/// Replaces all of the application's settings.
+pub(crate) async fn replace_application(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
+    application_id: axum::extract::Path<lockpad_ulid::Ulid>,
+    payload: axum::extract::Json<CreateApplication>,
+) -> Result<Json<Application>> {
+    let mut item = owned_application(&repositories, &claims, &application_id).await?;
+
+    item.name = payload.0.name;
+    item.allowed_origins = payload.0.allowed_origins;
+    item.allowed_callback_urls = payload.0.allowed_callback_urls;
+    item.allowed_logout_urls = payload.0.allowed_logout_urls;
+    item.backchannel_logout_uri = payload.0.backchannel_logout_uri;
+    validate_application(&item)?;
+
+    repositories.applications.update(&item).await?;
+    record_change(
+        &repositories,
+        &client,
+        AuditAction::ApplicationUpdate,
+        &item,
+    )
+    .await?;
+
+    tracing::debug!(?item, "replaced application");
+    Ok(Json(item))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateApplication {
+    pub name: Option<String>,
+    pub allowed_origins: Option<Vec<String>>,
+    pub allowed_callback_urls: Option<Vec<String>>,
+    pub allowed_logout_urls: Option<Vec<String>>,
+    /// An empty string removes the uri
+    pub backchannel_logout_uri: Option<String>,
+}
+
+/// Changes the application's settings that are present in the request.
+pub(crate) async fn update_application(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
+    application_id: axum::extract::Path<lockpad_ulid::Ulid>,
+    payload: axum::extract::Json<UpdateApplication>,
+) -> Result<Json<Application>> {
+    let mut item = owned_application(&repositories, &claims, &application_id).await?;
+    let payload = payload.0;
+
+    if let Some(name) = payload.name {
+        item.name = name;
+    }
+    if let Some(allowed_origins) = payload.allowed_origins {
+        item.allowed_origins = allowed_origins;
     }
+    if let Some(allowed_callback_urls) = payload.allowed_callback_urls {
+        item.allowed_callback_urls = allowed_callback_urls;
+    }
+    if let Some(allowed_logout_urls) = payload.allowed_logout_urls {
+        item.allowed_logout_urls = allowed_logout_urls;
+    }
+    if let Some(backchannel_logout_uri) = payload.backchannel_logout_uri {
+        item.backchannel_logout_uri =
+            (!backchannel_logout_uri.is_empty()).then_some(backchannel_logout_uri);
+    }
+    validate_application(&item)?;
 
+    repositories.applications.update(&item).await?;
+    record_change(
+        &repositories,
+        &client,
+        AuditAction::ApplicationUpdate,
+        &item,
+    )
+    .await?;
+
+    tracing::debug!(?item, "updated application");
     Ok(Json(item))
 }
+
+/// Deletes the application along with the grants users gave it.
+/// Tokens already issued to it stay valid until they expire.
+pub(crate) async fn delete_application(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    client: ClientInfo,
+    application_id: axum::extract::Path<lockpad_ulid::Ulid>,
+) -> Result<StatusCode> {
+    let item = owned_application(&repositories, &claims, &application_id).await?;
+
+    repositories.applications.delete(&item).await?;
+    record_change(
+        &repositories,
+        &client,
+        AuditAction::ApplicationDelete,
+        &item,
+    )
+    .await?;
+
+    tracing::debug!(?item.application_id, "deleted application");
+    Ok(StatusCode::NO_CONTENT)
+}
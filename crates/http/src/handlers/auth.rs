@@ -1,36 +1,81 @@
 use std::str::FromStr;
 
 use crate::{
+    audit,
+    client::ClientInfo,
     error::{Error, Result},
-    ServerState,
-};
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    handlers::{
+        pages::{login_form_page, register_form, FieldErrors, LoginScreenQuery},
+        user::UserResponse,
+    },
+    lockout::{LockoutKey, LoginThrottle},
+    password::{self, PasswordHashing, PasswordPolicy},
+    session::{start_session, CurrentSession},
+    totp, ServerState,
 };
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString};
 use axum::{
-    extract::State,
-    http::HeaderValue,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue},
     response::{IntoResponse, Response},
     Form,
 };
+use axum_extra::extract::cookie::CookieJar;
 use hyper::{header, StatusCode};
 use jsonwebtoken::EncodingKey;
-use lockpad_auth::Claims;
-use lockpad_models::{api_key::ApiKey, entity::Builder, user::User};
+use lockpad_auth::{ApiKeyToken, Claims};
+use lockpad_models::{
+    api_key::ApiKey,
+    audit_event::{AuditAction, AuditEvent, AuditOutcome},
+    entity::Builder,
+    repository::Repositories,
+    user::User,
+};
 use lockpad_ulid::Ulid;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use validator::{Validate, ValidationError, ValidationErrors};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub(crate) struct UserCredentials {
+    #[validate(custom(function = "not_blank", message = "Choose a username."))]
     username: String,
+    #[validate(length(min = 1, message = "Choose a password."))]
     password: String,
+    /// The one-time code of an authenticator app, for users who added one
+    #[serde(default)]
+    code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub(crate) struct ApiKeyCredentials {
-    api_key_id: String,
-    api_secret: String,
+#[serde(untagged)]
+pub(crate) enum ApiKeyCredentials {
+    /// A key in the `lkp_<id>_<secret>_<checksum>` format
+    Key { api_key: String },
+    /// The id and secret of a key sent separately, as keys created before the prefixed format require
+    Legacy {
+        api_key_id: String,
+        api_secret: String,
+    },
+}
+
+impl ApiKeyCredentials {
+    /// Splits the credentials into the key's id and secret.
+    fn into_parts(self) -> Result<(Ulid, String)> {
+        let (api_key_id, secret) = match self {
+            ApiKeyCredentials::Key { api_key } => {
+                let token = api_key.parse::<ApiKeyToken>()?;
+                (token.api_key_id().to_string(), token.secret().to_string())
+            }
+            ApiKeyCredentials::Legacy {
+                api_key_id,
+                api_secret,
+            } => (api_key_id, api_secret),
+        };
+        let api_key_id = Ulid::from_str(&api_key_id).map_err(|_| Error::Unauthorized)?;
+
+        Ok((api_key_id, secret))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,78 +90,232 @@ pub(crate) struct AuthorizeResponse {
     token: String,
 }
 
-/// Hashes a string using argon2.
+/// Hashes a string using argon2, with the configured algorithm and parameters.
 /// This is  performed on any password before it is stored in the database.
-pub(crate) async fn hash_string(data: &[u8]) -> Result<String> {
+pub(crate) async fn hash_string(hashing: &PasswordHashing, data: &[u8]) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2.hash_password(data, &salt)?.to_string();
+    let password_hash = hashing.argon2().hash_password(data, &salt)?.to_string();
 
     Ok(password_hash)
 }
 
-async fn validate_hash(data: &[u8], secret: &str) -> Result<()> {
-    let password_hash = PasswordHash::new(secret).unwrap();
-    Argon2::default()
-        .verify_password(data, &password_hash)
-        .map_err(|_| {
-            tracing::debug!("Password verification failed");
-            Error::Unauthorized
-        })?;
+/// Checks data against a hash, using the algorithm and parameters stored in the hash.
+/// Besides argon2, the bcrypt, scrypt and PBKDF2 hashes of imported users are accepted.
+pub(crate) async fn validate_hash(data: &[u8], secret: &str) -> Result<()> {
+    if !password::verify_hash(data, secret) {
+        tracing::debug!("Password verification failed");
+        return Err(Error::Unauthorized);
+    }
 
     Ok(())
 }
 
+impl UserCredentials {
+    /// Checks the fields of a registration against the password policy, before looking up whether the username is taken.
+    async fn validate_registration(
+        &self,
+        password_policy: &PasswordPolicy,
+    ) -> std::result::Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        // A missing password is already reported, the policy's rules would only repeat it.
+        if !self.password.is_empty() {
+            password_policy
+                .validate(&mut errors, "password", &self.username, &self.password)
+                .await;
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+fn not_blank(value: &str) -> std::result::Result<(), ValidationError> {
+    match value.trim().is_empty() {
+        true => Err(ValidationError::new("required")),
+        false => Ok(()),
+    }
+}
+
+/// Creates an account with the credentials, recording the registration in the audit log.
+/// Fails with [`Error::Conflict`] when the username is taken.
+async fn create_user(
+    payload: UserCredentials,
+    repositories: &Repositories,
+    hashing: &PasswordHashing,
+    client: &ClientInfo,
+) -> Result<User> {
+    // Checked up front to skip hashing, the unique constraint still catches concurrent registrations.
+    if repositories
+        .users
+        .by_identifier(&payload.username)
+        .await?
+        .is_some()
+    {
+        return Err(Error::Conflict("identifier"));
+    }
+
+    let password_hash = hash_string(hashing, &payload.password.into_bytes()).await?;
+
+    let user = User::builder()
+        .identifier(payload.username)
+        .secret(password_hash)
+        .build()?;
+
+    tracing::debug!(?user, "creating user");
+    repositories.users.create(&user).await?;
+    audit::record(
+        repositories,
+        client,
+        AuditEvent::builder()
+            .action(AuditAction::Register)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(user.user_id)),
+    )
+    .await?;
+
+    Ok(user)
+}
+
 /// Performs the signup process.
 /// This is where the user's credentials are added to the database.
-/// If the credentials are unique, the acount is created and a token is sent to the user.
+/// If the credentials are unique, the acount is created and the user is logged in.
+/// Otherwise the form is shown again, with the problems next to their fields.
 pub(crate) async fn register(
     State(ServerState {
-        encoding_key,
-        pg_pool,
+        repositories,
+        password_policy,
+        password_hashing,
         ..
     }): State<ServerState>,
+    client: ClientInfo,
+    jar: CookieJar,
     Form(payload): Form<UserCredentials>,
-) -> Result<impl IntoResponse> {
-    // TODO: Check against database to see if the username is already taken.
+) -> Result<Response> {
+    if let Err(errors) = payload.validate_registration(&password_policy).await {
+        let page = register_form(payload.username, FieldErrors::from(&errors));
+        return Ok((StatusCode::BAD_REQUEST, page).into_response());
+    }
 
-    let password_hash = hash_string(&payload.password.into_bytes()).await?;
+    let username = payload.username.clone();
+    let user = match create_user(payload, &repositories, &password_hashing, &client).await {
+        Ok(user) => user,
+        Err(Error::Conflict(_)) => {
+            let errors = FieldErrors {
+                username: Some("That username is already taken.".to_string()),
+                ..Default::default()
+            };
+            let page = register_form(username, errors);
+            return Ok((StatusCode::CONFLICT, page).into_response());
+        }
+        Err(err) => return Err(err),
+    };
 
-    let user = User::builder()
-        .identifier(payload.username)
-        .secret(password_hash)
-        .build()?;
+    let (jar, _session) = start_session(&repositories, jar, user.user_id, &client).await?;
 
-    tracing::debug!(?user, "creating user");
-    user.create(&pg_pool).await?;
+    // The user registered with lockpad directly, so send them to their account.
+    Ok((jar, Redirect::found("/account")?).into_response())
+}
+
+/// Performs the signup process, but with JSON request bodies.
+/// No session is started, the client logs in through `/api/authorize` afterwards.
+pub(crate) async fn register_json(
+    State(ServerState {
+        repositories,
+        password_policy,
+        password_hashing,
+        ..
+    }): State<ServerState>,
+    client: ClientInfo,
+    axum::extract::Json(payload): axum::extract::Json<UserCredentials>,
+) -> Result<(StatusCode, axum::response::Json<UserResponse>)> {
+    payload.validate_registration(&password_policy).await?;
 
-    let user_id = user.user_id.to_string();
-    let token = Claims::new(user_id).encode(&encoding_key).await?;
+    let user = create_user(payload, &repositories, &password_hashing, &client).await?;
 
-    // for now, return a dummy token
-    Ok(Redirect::found(&format!(
-        "http://localhost:3000/login-callback?token={token}"
-    )))
+    Ok((StatusCode::CREATED, axum::response::Json(user.into())))
 }
 
 /// Performs the authorization process.
 /// This is where the user's credentials are checked against the database.
-/// If the credentials are valid, a token is generated and sent to the user.
+/// If the credentials are valid, a session is started.
+/// When the login screen was reached from an application, the user is sent back to it to continue with their new session.
 pub(crate) async fn authorize(
     State(ServerState {
-        encoding_key,
-        pg_pool,
+        repositories,
+        login_throttle,
+        password_hashing,
         ..
     }): State<ServerState>,
+    query: Option<Query<LoginScreenQuery>>,
+    CurrentSession(previous_session): CurrentSession,
+    client: ClientInfo,
+    jar: CookieJar,
     Form(payload): Form<Credentials>,
-) -> Result<impl IntoResponse> {
-    // If we're here, the user is authorized.
+) -> Result<Response> {
     match payload {
-        Credentials::User(_) => {
-            let token = payload.authorize(&encoding_key, &pg_pool).await?;
-            Ok(Redirect::found(&format!(
-                "http://localhost:3000/login-callback?token={token}"
-            )))
+        Credentials::User(payload) => {
+            if let Some(Query(params)) = &query {
+                params
+                    .application(&repositories)
+                    .await
+                    .ok_or(Error::Unauthorized)?;
+            }
+
+            let user = match authenticate_user(
+                &payload,
+                &repositories,
+                &login_throttle,
+                &password_hashing,
+                &client,
+            )
+            .await
+            {
+                Ok(user) => user,
+                Err(Error::MfaRequired) => {
+                    let errors = FieldErrors {
+                        code: Some("Enter the code from your authenticator app.".to_string()),
+                        ..Default::default()
+                    };
+                    let page = login_form_page(query.as_deref(), payload.username, errors);
+                    return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+                }
+                // A wrong code is refused like a wrong password, so the message doesn't tell which one it was.
+                Err(Error::Unauthorized) => {
+                    let errors = match payload.code.filter(|code| !code.trim().is_empty()) {
+                        Some(_) => FieldErrors {
+                            code: Some(
+                                "The username, password or one-time code is incorrect.".to_string(),
+                            ),
+                            ..Default::default()
+                        },
+                        None => FieldErrors {
+                            password: Some("The username or password is incorrect.".to_string()),
+                            ..Default::default()
+                        },
+                    };
+                    let page = login_form_page(query.as_deref(), payload.username, errors);
+                    return Ok((StatusCode::UNAUTHORIZED, page).into_response());
+                }
+                Err(err) => return Err(err),
+            };
+
+            // Logging in again replaces the existing session.
+            if let Some(previous_session) = previous_session {
+                repositories.sessions.delete(&previous_session).await?;
+            }
+            // If we're here, the user is authorized.
+            let (jar, _session) = start_session(&repositories, jar, user.user_id, &client).await?;
+
+            let next = match query {
+                // The login screen asks for consent if needed, then redirects with a token.
+                Some(Query(params)) => format!("/login?{}", params.query_string()),
+                // Without an application, the user is logging in to manage their account.
+                None => "/account".to_string(),
+            };
+
+            Ok((jar, Redirect::found(&next)?).into_response())
         }
         Credentials::ApiKey(_) => {
             todo!()
@@ -125,152 +324,350 @@ pub(crate) async fn authorize(
 }
 
 /// Performs the authorization process, but with JSON request bodies.
+/// An api key can also be sent in an `Authorization: ApiKey <key>` header instead of the body.
 pub(crate) async fn authorize_json(
     State(ServerState {
         encoding_key,
-        pg_pool,
+        repositories,
+        login_throttle,
+        password_hashing,
         ..
     }): State<ServerState>,
-    payload: axum::extract::Json<Credentials>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    payload: Option<axum::extract::Json<Credentials>>,
 ) -> Result<axum::response::Json<AuthorizeResponse>> {
-    match payload.0 {
-        Credentials::User(payload) => authorize_user(payload, &encoding_key, &pg_pool).await,
-        Credentials::ApiKey(payload) => authorize_api_key(payload, &encoding_key, &pg_pool).await,
+    if let Some(token) = ApiKeyToken::from_headers(&headers)? {
+        let payload = ApiKeyCredentials::Key {
+            api_key: token.to_string(),
+        };
+        return authorize_api_key(payload, &encoding_key, &repositories, &client).await;
+    }
+
+    match payload.ok_or(Error::BadRequest("missing credentials"))?.0 {
+        Credentials::User(payload) => {
+            authorize_user(
+                payload,
+                &encoding_key,
+                &repositories,
+                &login_throttle,
+                &password_hashing,
+                &client,
+            )
+            .await
+        }
+        Credentials::ApiKey(payload) => {
+            authorize_api_key(payload, &encoding_key, &repositories, &client).await
+        }
     }
 }
 
-async fn authorize_user(
-    payload: UserCredentials,
-    encoding_key: &EncodingKey,
-    pg_pool: &sqlx::PgPool,
-) -> Result<axum::response::Json<AuthorizeResponse>> {
-    let user = User::by_identifier(pg_pool, &payload.username).await?;
+/// Checks the user's credentials against the database, recording the attempt in the audit log.
+/// Clients that failed too often recently are turned away without checking the password.
+/// Users with an authenticator app also need one of its codes.
+/// A missing code is not counted as a failure, so clients can ask for it after the password was accepted.
+/// Hashes made with outdated parameters are replaced once the password is verified.
+async fn authenticate_user(
+    payload: &UserCredentials,
+    repositories: &Repositories,
+    throttle: &LoginThrottle,
+    hashing: &PasswordHashing,
+    client: &ClientInfo,
+) -> Result<User> {
+    if let Some(wait) = throttle
+        .retry_after(&payload.username, client.ip_address)
+        .await?
+    {
+        tracing::debug!(?wait, "login attempt throttled");
+        return Err(Error::TooManyRequests(wait));
+    }
 
-    match user {
+    let user = repositories.users.by_identifier(&payload.username).await?;
+
+    let mut second_factor = SecondFactor::Passed;
+    let authenticated = match &user {
         None => {
             tracing::debug!("user not found");
-
-            Err(Error::Unauthorized)
+            false
         }
         Some(user) => {
             tracing::debug!(?user.user_id, "user found");
 
-            validate_hash(payload.password.as_bytes(), &user.secret).await?;
-            tracing::debug!("password verified");
+            let verified = validate_hash(payload.password.as_bytes(), &user.secret)
+                .await
+                .is_ok();
+            if verified && user.disabled {
+                tracing::debug!(?user.user_id, "user is disabled");
+            }
+            if verified && !user.disabled {
+                second_factor =
+                    verify_second_factor(repositories, user, payload.code.as_deref()).await?;
+            }
 
-            let token = Claims::new(user.user_id.to_string())
-                .encode(encoding_key)
-                .await?;
-            Ok(axum::response::Json(AuthorizeResponse { token }))
+            verified && !user.disabled && second_factor == SecondFactor::Passed
         }
+    };
+
+    if second_factor == SecondFactor::Missing {
+        return Err(Error::MfaRequired);
     }
-}
 
-async fn authorize_api_key(
-    payload: ApiKeyCredentials,
-    encoding_key: &EncodingKey,
-    pg_pool: &sqlx::PgPool,
-) -> Result<axum::response::Json<AuthorizeResponse>> {
-    let api_key = Ulid::from_str(&payload.api_key_id).map_err(|_| Error::Unauthorized)?;
-    let api_key = ApiKey::by_id(pg_pool, &api_key).await?;
+    let event = AuditEvent::builder()
+        .action(AuditAction::Login)
+        .target_id(user.as_ref().map(|user| user.user_id));
+    match user {
+        Some(mut user) if authenticated => {
+            tracing::debug!("password verified");
+            throttle.record_success(&payload.username).await?;
+            if let Err(err) =
+                rehash_if_outdated(&mut user, &payload.password, repositories, hashing).await
+            {
+                tracing::warn!(?err, ?user.user_id, "failed to rehash password");
+            }
+            let event = event.actor_id(Some(user.user_id));
+            audit::record(repositories, client, event).await?;
 
-    match api_key {
-        None => {
-            tracing::debug!("user not found");
+            Ok(user)
+        }
+        user => {
+            let event = event
+                .outcome(AuditOutcome::Failure)
+                .detail(Some(payload.username.clone()));
+            audit::record(repositories, client, event).await?;
+
+            let user_id = user.as_ref().map(|user| user.user_id);
+            record_failure(&payload.username, user_id, repositories, throttle, client).await?;
 
             Err(Error::Unauthorized)
         }
-        Some(api_key) => {
-            let owner_id = api_key.owner_id.to_string();
+    }
+}
+
+/// Counts a wrong password against the identifier and the client's address.
+/// Each lockout this causes is recorded in the audit log, against the user when the identifier belongs to one.
+async fn record_failure(
+    identifier: &str,
+    user_id: Option<Ulid>,
+    repositories: &Repositories,
+    throttle: &LoginThrottle,
+    client: &ClientInfo,
+) -> Result<()> {
+    for key in throttle
+        .record_failure(identifier, client.ip_address)
+        .await?
+    {
+        tracing::info!(%key, "locked out after too many failed logins");
+        let target_id = match key {
+            LockoutKey::Identifier(_) => user_id,
+            LockoutKey::Ip(_) => None,
+        };
+        let event = AuditEvent::builder()
+            .action(AuditAction::Lockout)
+            .target_id(target_id)
+            .detail(Some(key.to_string()));
+        audit::record(repositories, client, event).await?;
+    }
+
+    Ok(())
+}
 
-            tracing::debug!(owner_id, "user found");
+/// Checks the password of a logged in user before a change to their account.
+/// It counts towards the same limits as logging in, so a stolen session can't be used to guess the password.
+pub(crate) async fn confirm_password(
+    user: &User,
+    password: &str,
+    repositories: &Repositories,
+    throttle: &LoginThrottle,
+    client: &ClientInfo,
+) -> Result<()> {
+    if let Some(wait) = throttle
+        .retry_after(&user.identifier, client.ip_address)
+        .await?
+    {
+        tracing::debug!(?wait, "password confirmation throttled");
+        return Err(Error::TooManyRequests(wait));
+    }
+
+    if let Err(err) = validate_hash(password.as_bytes(), &user.secret).await {
+        record_failure(
+            &user.identifier,
+            Some(user.user_id),
+            repositories,
+            throttle,
+            client,
+        )
+        .await?;
+        return Err(err);
+    }
+    throttle.record_success(&user.identifier).await?;
+
+    Ok(())
+}
 
-            validate_hash(payload.api_secret.as_bytes(), &api_key.secret).await?;
+#[derive(Debug, PartialEq, Eq)]
+enum SecondFactor {
+    /// The code matched one of the user's authenticator apps, or they don't have one
+    Passed,
+    Missing,
+    Failed,
+}
 
-            let token = Claims::new(owner_id.to_string())
-                .encode(encoding_key)
-                .await?;
-            Ok(axum::response::Json(AuthorizeResponse { token }))
+/// Checks the one-time code of a user whose password was verified.
+/// Each code is accepted once, so one seen over someone's shoulder can't be used again.
+async fn verify_second_factor(
+    repositories: &Repositories,
+    user: &User,
+    code: Option<&str>,
+) -> Result<SecondFactor> {
+    let factors = repositories.mfa_factors.by_user_id(&user.user_id).await?;
+    if factors.is_empty() {
+        return Ok(SecondFactor::Passed);
+    }
+    let Some(code) = code.filter(|code| !code.trim().is_empty()) else {
+        tracing::debug!(?user.user_id, "one-time code required");
+        return Ok(SecondFactor::Missing);
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    for factor in factors {
+        let Some(step) = totp::verify(&factor.secret, code, now) else {
+            continue;
+        };
+        if repositories
+            .mfa_factors
+            .record_use(&factor.mfa_factor_id, step)
+            .await?
+        {
+            return Ok(SecondFactor::Passed);
         }
+        tracing::debug!(?factor.mfa_factor_id, "one-time code was used before");
     }
+
+    Ok(SecondFactor::Failed)
 }
 
-impl Credentials {
-    async fn authorize(self, encoding_key: &EncodingKey, pg_pool: &sqlx::PgPool) -> Result<String> {
-        match self {
-            Credentials::User(payload) => {
-                let user = User::by_identifier(pg_pool, &payload.username).await?;
-
-                match user {
-                    None => {
-                        tracing::debug!("user not found");
-
-                        Err(Error::Unauthorized)
-                    }
-                    Some(user) => {
-                        tracing::debug!(?user.user_id, "user found");
-                        tracing::debug!(?user.user_id, "user found");
-
-                        validate_hash(payload.password.as_bytes(), &user.secret).await?;
-                        tracing::debug!("password verified");
-
-                        let token = Claims::new(user.user_id.to_string())
-                            .encode(encoding_key)
-                            .await?;
-                        Ok(token)
-                    }
-                }
-            }
-            Credentials::ApiKey(payload) => {
-                let api_key =
-                    Ulid::from_str(&payload.api_key_id).map_err(|_| Error::Unauthorized)?;
-                let api_key = ApiKey::by_id(pg_pool, &api_key).await?;
+/// Replaces the user's password hash when it was made with other parameters than the configured ones.
+/// Only possible right after a login, when the password is known.
+async fn rehash_if_outdated(
+    user: &mut User,
+    password: &str,
+    repositories: &Repositories,
+    hashing: &PasswordHashing,
+) -> Result<()> {
+    let current = PasswordHash::new(&user.secret).is_ok_and(|hash| hashing.is_current(&hash));
+    if current {
+        return Ok(());
+    }
 
-                match api_key {
-                    None => {
-                        tracing::debug!("user not found");
+    user.secret = hash_string(hashing, password.as_bytes()).await?;
+    repositories.users.update(user).await?;
+    tracing::debug!(?user.user_id, "rehashed password with current parameters");
 
-                        Err(Error::Unauthorized)
-                    }
-                    Some(api_key) => {
-                        let owner_id = api_key.owner_id.to_string();
+    Ok(())
+}
+
+async fn authorize_user(
+    payload: UserCredentials,
+    encoding_key: &EncodingKey,
+    repositories: &Repositories,
+    throttle: &LoginThrottle,
+    hashing: &PasswordHashing,
+    client: &ClientInfo,
+) -> Result<axum::response::Json<AuthorizeResponse>> {
+    let user = authenticate_user(&payload, repositories, throttle, hashing, client).await?;
 
-                        tracing::debug!(owner_id, "user found");
+    let token = Claims::new(user.user_id.to_string())
+        .encode(encoding_key)
+        .await?;
+    Ok(axum::response::Json(AuthorizeResponse { token }))
+}
 
-                        validate_hash(payload.api_secret.as_bytes(), &api_key.secret).await?;
+/// Checks an api key's secret against the database.
+/// Only keys that are active and belong to an enabled user are accepted.
+pub(crate) async fn verify_api_key(
+    repositories: &Repositories,
+    api_key_id: &Ulid,
+    api_secret: &str,
+) -> Result<ApiKey> {
+    let Some(api_key) = repositories.api_keys.by_id(api_key_id).await? else {
+        tracing::debug!("api key not found");
+        return Err(Error::Unauthorized);
+    };
+
+    validate_hash(api_secret.as_bytes(), &api_key.secret).await?;
+
+    if !api_key.is_active() {
+        tracing::debug!(?api_key.api_key_id, "api key is revoked or expired");
+        return Err(Error::Unauthorized);
+    }
 
-                        let token = Claims::new(owner_id.to_string())
-                            .encode(encoding_key)
-                            .await?;
-                        Ok(token)
-                    }
-                }
-            }
-        }
+    let owner = repositories.users.by_id(&api_key.owner_id).await?;
+    if owner.is_none_or(|owner| owner.disabled) {
+        tracing::debug!(?api_key.owner_id, "owner is disabled");
+        return Err(Error::Unauthorized);
     }
+
+    Ok(api_key)
+}
+
+async fn authorize_api_key(
+    payload: ApiKeyCredentials,
+    encoding_key: &EncodingKey,
+    repositories: &Repositories,
+    client: &ClientInfo,
+) -> Result<axum::response::Json<AuthorizeResponse>> {
+    let (api_key_id, api_secret) = payload.into_parts()?;
+    let event = AuditEvent::builder()
+        .action(AuditAction::ApiKeyLogin)
+        .target_id(Some(api_key_id));
+    let mut api_key = match verify_api_key(repositories, &api_key_id, &api_secret).await {
+        Ok(api_key) => api_key,
+        Err(Error::Unauthorized) => {
+            let event = event.outcome(AuditOutcome::Failure);
+            audit::record(repositories, client, event).await?;
+            return Err(Error::Unauthorized);
+        }
+        Err(err) => return Err(err),
+    };
+    let event = event.actor_id(Some(api_key.owner_id));
+    audit::record(repositories, client, event).await?;
+
+    repositories.api_keys.record_use(&mut api_key).await?;
+
+    let token = Claims::new(api_key.owner_id.to_string())
+        .with_scopes(&api_key.scopes)
+        .encode(encoding_key)
+        .await?;
+    Ok(axum::response::Json(AuthorizeResponse { token }))
 }
 
 // TODO: Get this upstreamed
-struct Redirect {
+pub(crate) struct Redirect {
     status_code: StatusCode,
     location: HeaderValue,
 }
 
 impl Redirect {
-    fn found(uri: &str) -> Self {
+    /// Fails with [`Error::BadRequest`] when the uri can't be sent in a header, such as one carrying a line break.
+    pub(crate) fn found(uri: &str) -> Result<Self> {
         Self::with_status_code(StatusCode::FOUND, uri)
     }
 
-    fn with_status_code(status_code: StatusCode, uri: &str) -> Self {
+    fn with_status_code(status_code: StatusCode, uri: &str) -> Result<Self> {
         assert!(
             status_code.is_redirection(),
             "not a redirection status code"
         );
 
-        Self {
+        let location = HeaderValue::try_from(uri).map_err(|_| {
+            tracing::debug!(uri, "redirect uri isn't a valid header value");
+            Error::BadRequest("invalid redirect uri")
+        })?;
+
+        Ok(Self {
             status_code,
-            location: HeaderValue::try_from(uri).expect("URI isn't a valid header value"),
-        }
+            location,
+        })
     }
 }
 
@@ -279,3 +676,40 @@ impl IntoResponse for Redirect {
         (self.status_code, [(header::LOCATION, self.location)]).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Redirect;
+    use crate::{
+        error::Error,
+        testing::{app, form, send},
+    };
+    use axum::http::StatusCode;
+    use lockpad_models::repository::Repositories;
+
+    #[tokio::test]
+    async fn registration_errors_are_shown_next_to_their_fields() {
+        let app = app(Repositories::memory());
+
+        let body = serde_urlencoded::to_string([("username", " "), ("password", "abc")]).unwrap();
+        let (status, _, body) = send(&app, form("/forms/register", None, &body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Choose a username."), "{body}");
+        assert!(body.contains("Use at least 8 characters."), "{body}");
+
+        let body = serde_urlencoded::to_string([("username", "alice"), ("password", "")]).unwrap();
+        let (status, _, body) = send(&app, form("/forms/register", None, &body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Choose a password."), "{body}");
+        assert!(!body.contains("Use at least"), "the policy isn't repeated");
+    }
+
+    #[test]
+    fn redirects_refuse_uris_that_cant_be_headers() {
+        assert!(Redirect::found("https://example.com/callback?token=a").is_ok());
+        assert!(matches!(
+            Redirect::found("https://example.com/\r\nSet-Cookie: a=b"),
+            Err(Error::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use std::str::FromStr;
+
+use crate::{
+    error::Result,
+    handlers::{auth::Redirect, pages::HtmlPage},
+    session::{end_session, CurrentSession},
+    token_auth::AccountClaims,
+    validation::url_matches,
+    ServerState,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use lockpad_auth::LogoutToken;
+use lockpad_models::{repository::Repositories, session::Session};
+use lockpad_ulid::Ulid;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct LogoutQuery {
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    /// Passed back to the application when redirecting.
+    pub state: Option<String>,
+}
+
+impl LogoutQuery {
+    /// Determines where to send the user after logging out.
+    /// The uri must be one of the application's allowed logout urls.
+    async fn redirect_uri(&self, repositories: &Repositories) -> Option<String> {
+        let redirect_uri = self.post_logout_redirect_uri.as_ref()?;
+        let app_id = Ulid::from_str(self.client_id.as_ref()?).ok()?;
+        let application = repositories.applications.by_id(&app_id).await.ok()??;
+
+        if !application
+            .allowed_logout_urls
+            .iter()
+            .any(|allowed| url_matches(allowed, redirect_uri))
+        {
+            tracing::debug!(?redirect_uri, "post logout redirect uri not allowed");
+            return None;
+        }
+
+        match &self.state {
+            None => Some(redirect_uri.to_owned()),
+            Some(state) => {
+                let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+                let params = serde_urlencoded::to_string([("state", state)]).ok()?;
+                Some(format!("{redirect_uri}{separator}{params}"))
+            }
+        }
+    }
+}
+
+/// Ends the user's session.
+/// Following OpenID Connect RP-Initiated Logout, an application can ask for the user to be sent back to it afterwards.
+pub(crate) async fn logout(
+    State(state): State<ServerState>,
+    Query(query): Query<LogoutQuery>,
+    CurrentSession(session): CurrentSession,
+    jar: CookieJar,
+) -> Result<Response> {
+    let redirect_uri = query.redirect_uri(&state.repositories).await;
+
+    let jar = match session {
+        None => jar,
+        Some(session) => {
+            notify_backchannel(&state, &session).await?;
+            end_session(&state.repositories, jar, &session).await?
+        }
+    };
+
+    match redirect_uri {
+        Some(redirect_uri) => Ok((jar, Redirect::found(&redirect_uri)?).into_response()),
+        None => Ok((jar, HtmlPage::LoggedOut).into_response()),
+    }
+}
+
+/// Ends all of the user's sessions, signing them out on every device.
+/// The tokens issued to the user so far are refused by lockpad's api from then on.
+pub(crate) async fn sign_out_everywhere(
+    State(state): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+) -> Result<StatusCode> {
+    let user_id = Ulid::from_str(&claims.sub)?;
+
+    let sessions = state.repositories.sessions.by_user_id(&user_id).await?;
+    for session in &sessions {
+        notify_backchannel(&state, session).await?;
+    }
+    state
+        .repositories
+        .sessions
+        .delete_by_user_id(&user_id)
+        .await?;
+    state.repositories.users.revoke_tokens(&user_id).await?;
+    tracing::debug!(?user_id, count = sessions.len(), "signed out everywhere");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends OpenID Connect back-channel logout notifications to the applications the session was used with.
+/// Delivery happens in the background, a failure to notify an application does not prevent logging out.
+pub(crate) async fn notify_backchannel(state: &ServerState, session: &Session) -> Result<()> {
+    let applications = state
+        .repositories
+        .applications
+        .backchannel_logout_targets(&session.session_id)
+        .await?;
+
+    for application in applications {
+        let Some(uri) = application.backchannel_logout_uri else {
+            continue;
+        };
+
+        let token = LogoutToken::new(
+            state.issuer.clone(),
+            session.user_id.to_string(),
+            application.application_id.to_string(),
+            Ulid::generate().to_string(),
+            Some(session.session_id.to_string()),
+        )
+        .encode(&state.encoding_key)
+        .await?;
+
+        let client = state.http_client.clone();
+        tokio::spawn(async move {
+            let result = client
+                .post(&uri)
+                .form(&[("logout_token", token)])
+                .timeout(std::time::Duration::from_secs(5))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => tracing::debug!(uri, "sent back-channel logout"),
+                Err(error) => tracing::warn!(uri, ?error, "failed to send back-channel logout"),
+            }
+        });
+    }
+
+    Ok(())
+}
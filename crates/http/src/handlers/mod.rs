@@ -1,7 +1,10 @@
+pub mod admin;
 pub mod api_key;
 pub mod application;
 pub mod auth;
 pub mod health;
 pub mod jwks;
+pub mod logout;
 pub mod pages;
+pub mod session;
 pub mod user;
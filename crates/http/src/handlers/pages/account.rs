@@ -0,0 +1,1192 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_extra::extract::cookie::CookieJar;
+use dioxus::prelude::*;
+use lockpad_models::{
+    api_key::ApiKey,
+    application::Builder as ApplicationBuilder,
+    audit_event::{AuditAction, AuditEvent},
+    entity::Builder,
+    mfa_factor::MfaFactor,
+    repository::Repositories,
+    session::Session,
+    user::User,
+    user_data::{self, all_pages},
+};
+use lockpad_ulid::Ulid;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use validator::{Validate, ValidationErrors};
+
+use super::{sessions::format_timestamp, HtmlPage};
+use crate::{
+    audit,
+    client::ClientInfo,
+    error::{retry_after_secs, Error, Result},
+    handlers::{
+        api_key::generate_api_key,
+        application::record_change,
+        auth::{confirm_password, hash_string},
+        logout::notify_backchannel,
+        session::revoke_others,
+        user::export,
+    },
+    lockout::LoginThrottle,
+    session::{end_session, CurrentSession},
+    totp,
+    validation::validate_application,
+    ServerState,
+};
+
+/// Everything displayed on the account page.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AccountView {
+    identifier: String,
+    email: String,
+    admin: bool,
+    api_keys: Vec<ApiKeyView>,
+    applications: Vec<ApplicationView>,
+    grants: Vec<GrantView>,
+    mfa_factors: Vec<MfaFactorView>,
+    /// A fresh secret offered for adding an authenticator app, and the link apps import it from
+    new_mfa_secret: String,
+    new_mfa_uri: String,
+    /// The outcome of the last action taken on the page
+    message: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ApiKeyView {
+    api_key_id: String,
+    name: String,
+    scopes: String,
+    /// Whether the key is revoked or expired, and when it was last used
+    status: String,
+    active: bool,
+}
+
+impl From<ApiKey> for ApiKeyView {
+    fn from(api_key: ApiKey) -> Self {
+        let mut status = match (api_key.revoked_at, api_key.expires_at) {
+            (Some(revoked_at), _) => format!("revoked {}", format_timestamp(revoked_at)),
+            (None, Some(expires_at)) if !api_key.is_active() => {
+                format!("expired {}", format_timestamp(expires_at))
+            }
+            (None, Some(expires_at)) => format!("expires {}", format_timestamp(expires_at)),
+            (None, None) => "does not expire".to_string(),
+        };
+        match api_key.last_used_at {
+            Some(last_used_at) => status.push_str(&format!(
+                ", used {} times, last {}",
+                api_key.use_count,
+                format_timestamp(last_used_at)
+            )),
+            None => status.push_str(", never used"),
+        }
+
+        Self {
+            api_key_id: api_key.api_key_id.to_string(),
+            name: api_key.name.clone(),
+            scopes: match api_key.scopes.is_empty() {
+                true => "all scopes".to_string(),
+                false => api_key.scopes.join(", "),
+            },
+            active: api_key.is_active(),
+            status,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ApplicationView {
+    application_id: String,
+    name: String,
+    allowed_callback_urls: Vec<String>,
+}
+
+/// An application the user has allowed access to their account.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct GrantView {
+    application_id: String,
+    application_name: String,
+    scopes: String,
+}
+
+/// An authenticator app the user asked for on login.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MfaFactorView {
+    mfa_factor_id: String,
+    name: String,
+    created: String,
+}
+
+/// The name authenticator apps list the account under.
+const MFA_ISSUER: &str = "lockpad";
+
+impl AccountView {
+    async fn load(
+        repositories: &Repositories,
+        user: &User,
+        message: Option<String>,
+    ) -> Result<Self> {
+        let api_keys =
+            all_pages(|pagination| repositories.api_keys.query(user.user_id, pagination)).await?;
+        let api_keys = api_keys.into_iter().map(ApiKeyView::from).collect();
+
+        let applications =
+            all_pages(|pagination| repositories.applications.query(user.user_id, pagination))
+                .await?;
+        let applications = applications
+            .into_iter()
+            .map(|application| ApplicationView {
+                application_id: application.application_id.to_string(),
+                name: application.name,
+                allowed_callback_urls: application.allowed_callback_urls,
+            })
+            .collect();
+
+        let mut grants = Vec::new();
+        for grant in repositories.grants.by_user_id(&user.user_id).await? {
+            let application = repositories
+                .applications
+                .by_id(&grant.application_id)
+                .await?;
+            grants.push(GrantView {
+                application_id: grant.application_id.to_string(),
+                application_name: application
+                    .map(|application| application.name)
+                    .unwrap_or_default(),
+                scopes: grant.scopes.join(", "),
+            });
+        }
+
+        let mfa_factors = repositories
+            .mfa_factors
+            .by_user_id(&user.user_id)
+            .await?
+            .into_iter()
+            .map(|factor| MfaFactorView {
+                mfa_factor_id: factor.mfa_factor_id.to_string(),
+                name: factor.name,
+                created: format_timestamp(factor.created_at),
+            })
+            .collect();
+
+        let mut view = Self {
+            identifier: user.identifier.clone(),
+            email: user.email.clone().unwrap_or_default(),
+            admin: user.admin,
+            api_keys,
+            applications,
+            grants,
+            mfa_factors,
+            new_mfa_secret: String::new(),
+            new_mfa_uri: String::new(),
+            message,
+        };
+        view.offer_mfa_secret(totp::generate_secret());
+
+        Ok(view)
+    }
+
+    fn offer_mfa_secret(&mut self, secret: String) {
+        self.new_mfa_uri = totp::provisioning_uri(MFA_ISSUER, &self.identifier, &secret);
+        self.new_mfa_secret = secret;
+    }
+}
+
+/// Finds the user the session belongs to.
+async fn session_user(repositories: &Repositories, session: &Session) -> Result<User> {
+    repositories
+        .users
+        .by_id(&session.user_id)
+        .await?
+        .ok_or(Error::NotFound)
+}
+
+/// Renders the account page with the outcome of an action.
+async fn account_page(
+    repositories: &Repositories,
+    user: &User,
+    message: String,
+) -> Result<Response> {
+    let view = AccountView::load(repositories, user, Some(message)).await?;
+
+    Ok(HtmlPage::Account(view).into_response())
+}
+
+/// Sends a page where the user can manage their account.
+pub(crate) async fn account_screen(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    let view = AccountView::load(&repositories, &user, None).await?;
+    Ok(HtmlPage::Account(view).into_response())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct ChangePasswordForm {
+    current_password: String,
+    #[validate(length(min = 1, message = "the new password must not be empty"))]
+    new_password: String,
+}
+
+/// Replaces the user's password after checking the current one.
+/// Every other session is logged out and the tokens issued so far are refused, so whoever may have known the old password loses access.
+pub(crate) async fn change_password_form(
+    State(state): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Form(payload): Form<ChangePasswordForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let ServerState {
+        repositories,
+        login_throttle,
+        password_policy,
+        password_hashing,
+        ..
+    } = &state;
+    let mut user = session_user(repositories, &session).await?;
+
+    if let Some(message) = password_refused(
+        repositories,
+        login_throttle,
+        &client,
+        &user,
+        &payload.current_password,
+        "The current password is incorrect.",
+    )
+    .await?
+    {
+        return account_page(repositories, &user, message).await;
+    }
+    if let Err(errors) = payload.validate() {
+        return account_page(repositories, &user, errors.to_string()).await;
+    }
+    let mut errors = ValidationErrors::new();
+    password_policy
+        .validate(
+            &mut errors,
+            "new_password",
+            &user.identifier,
+            &payload.new_password,
+        )
+        .await;
+    if !errors.is_empty() {
+        return account_page(repositories, &user, errors.to_string()).await;
+    }
+
+    user.secret = hash_string(password_hashing, payload.new_password.as_bytes()).await?;
+    repositories.users.update(&user).await?;
+    revoke_others(&state, &user.user_id, Some(&session.session_id)).await?;
+    repositories.users.revoke_tokens(&user.user_id).await?;
+    audit::record(
+        repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::PasswordChange)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(user.user_id)),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, "changed password");
+
+    account_page(
+        repositories,
+        &user,
+        "Your password has been changed and your other sessions were logged out.".into(),
+    )
+    .await
+}
+
+/// Confirms the user's password before a change to their account, returning the message to show when it is refused.
+async fn password_refused(
+    repositories: &Repositories,
+    throttle: &LoginThrottle,
+    client: &ClientInfo,
+    user: &User,
+    password: &str,
+    incorrect: &str,
+) -> Result<Option<String>> {
+    match confirm_password(user, password, repositories, throttle, client).await {
+        Ok(()) => Ok(None),
+        Err(Error::Unauthorized) => Ok(Some(incorrect.to_string())),
+        Err(Error::TooManyRequests(wait)) => Ok(Some(format!(
+            "Too many incorrect passwords, try again in {} seconds.",
+            retry_after_secs(&wait)
+        ))),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmailForm {
+    email: String,
+}
+
+pub(crate) async fn change_email_form(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Form(payload): Form<EmailForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let mut user = session_user(&repositories, &session).await?;
+
+    // An empty address removes the email from the account.
+    let email = payload.email.trim();
+    if !email.is_empty() && !validator::validate_email(email) {
+        return account_page(
+            &repositories,
+            &user,
+            format!("{email} is not a valid email."),
+        )
+        .await;
+    }
+
+    user.email = (!email.is_empty()).then(|| email.to_string());
+    repositories.users.update(&user).await?;
+    audit::record(
+        &repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::EmailChange)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(user.user_id)),
+    )
+    .await?;
+
+    account_page(&repositories, &user, "Your email has been updated.".into()).await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct CreateApiKeyForm {
+    #[validate(length(min = 1, message = "the name must not be empty"))]
+    name: String,
+    /// Space delimited, empty for a key that can do everything the user can
+    #[serde(default)]
+    scopes: String,
+    /// Empty for a key that does not expire
+    #[serde(default)]
+    expires_in_days: String,
+}
+
+pub(crate) async fn create_api_key_form(
+    State(ServerState {
+        repositories,
+        password_hashing,
+        ..
+    }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Form(payload): Form<CreateApiKeyForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    if let Err(errors) = payload.validate() {
+        return account_page(&repositories, &user, errors.to_string()).await;
+    }
+
+    let expires_at = match payload.expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<u16>() {
+            Ok(days) if days > 0 => {
+                Some(OffsetDateTime::now_utc() + time::Duration::days(days.into()))
+            }
+            _ => {
+                let message = format!("{days} is not a valid number of days.");
+                return account_page(&repositories, &user, message).await;
+            }
+        },
+    };
+    let scopes = payload
+        .scopes
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect();
+
+    let (api_key, token) = generate_api_key(
+        &repositories,
+        &password_hashing,
+        &client,
+        user.user_id,
+        payload.name,
+        scopes,
+        expires_at,
+    )
+    .await?;
+    let message = format!(
+        "Created api key {}. The key is {token}, it will not be shown again.",
+        api_key.api_key_id
+    );
+
+    account_page(&repositories, &user, message).await
+}
+
+pub(crate) async fn revoke_api_key_form(
+    State(ServerState {
+        repositories,
+        api_key_cache,
+        ..
+    }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Path(api_key_id): Path<Ulid>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    let mut api_key = repositories
+        .api_keys
+        .by_owner_and_id(&user.user_id, &api_key_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    repositories.api_keys.revoke(&mut api_key).await?;
+    api_key_cache.remove(&api_key_id.to_string());
+    audit::record(
+        &repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::ApiKeyRevoke)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(api_key_id)),
+    )
+    .await?;
+    tracing::debug!(?api_key_id, "revoked api key");
+
+    account_page(&repositories, &user, "The api key has been revoked.".into()).await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct CreateApplicationForm {
+    #[validate(length(min = 1, message = "the name must not be empty"))]
+    name: String,
+    /// One url per line
+    allowed_callback_urls: String,
+    /// One origin per line
+    allowed_origins: String,
+}
+
+fn split_lines(value: &str) -> Vec<String> {
+    value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+pub(crate) async fn create_application_form(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Form(payload): Form<CreateApplicationForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    if let Err(errors) = payload.validate() {
+        return account_page(&repositories, &user, errors.to_string()).await;
+    }
+
+    let application = ApplicationBuilder::default()
+        .name(payload.name)
+        .owner_id(user.user_id)
+        .allowed_callback_urls(split_lines(&payload.allowed_callback_urls))
+        .allowed_origins(split_lines(&payload.allowed_origins))
+        .build()?;
+    if let Err(errors) = validate_application(&application) {
+        return account_page(&repositories, &user, errors.to_string()).await;
+    }
+    repositories.applications.create(&application).await?;
+    record_change(
+        &repositories,
+        &client,
+        AuditAction::ApplicationCreate,
+        &application,
+    )
+    .await?;
+    tracing::debug!(?application, "created application");
+
+    let message = format!("Created application {}.", application.application_id);
+    account_page(&repositories, &user, message).await
+}
+
+/// Removes an application's access to the user's account.
+/// The user will be asked for consent again the next time they use the application.
+pub(crate) async fn revoke_grant_form(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    Path(application_id): Path<Ulid>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    repositories
+        .grants
+        .delete(&user.user_id, &application_id)
+        .await?;
+    tracing::debug!(?application_id, "revoked grant");
+
+    account_page(
+        &repositories,
+        &user,
+        "The application's access has been revoked.".into(),
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct AddMfaFactorForm {
+    #[validate(length(min = 1, message = "the name must not be empty"))]
+    name: String,
+    /// The secret offered on the account page, which the user added to their app
+    secret: String,
+    /// The code the app shows, proving it was set up with the secret
+    code: String,
+}
+
+/// Adds an authenticator app once the user proves it works by entering one of its codes.
+/// From then on, logging in needs a code from it besides the password.
+pub(crate) async fn add_mfa_factor_form(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Form(payload): Form<AddMfaFactorForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    let message = match payload.validate() {
+        Err(errors) => errors.to_string(),
+        Ok(()) => {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            match totp::verify(&payload.secret, &payload.code, now) {
+                Some(step) => {
+                    let factor = MfaFactor::new(user.user_id, payload.name, payload.secret, step);
+                    repositories.mfa_factors.create(&factor).await?;
+                    audit::record(
+                        &repositories,
+                        &client,
+                        AuditEvent::builder()
+                            .action(AuditAction::MfaFactorCreate)
+                            .actor_id(Some(user.user_id))
+                            .target_id(Some(factor.mfa_factor_id)),
+                    )
+                    .await?;
+                    tracing::debug!(?factor.mfa_factor_id, "added authenticator app");
+
+                    return account_page(
+                        &repositories,
+                        &user,
+                        "The authenticator app has been added, logging in now needs one of its codes."
+                            .into(),
+                    )
+                    .await;
+                }
+                None => "The code does not match, check the time on your device and try again."
+                    .to_string(),
+            }
+        }
+    };
+
+    // The user keeps the secret they already added to their app for the next try.
+    let mut view = AccountView::load(&repositories, &user, Some(message)).await?;
+    view.offer_mfa_secret(payload.secret);
+    Ok(HtmlPage::Account(view).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeleteMfaFactorForm {
+    password: String,
+}
+
+/// Removes an authenticator app after confirming the user's password.
+pub(crate) async fn delete_mfa_factor_form(
+    State(ServerState {
+        repositories,
+        login_throttle,
+        ..
+    }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    Path(mfa_factor_id): Path<Ulid>,
+    Form(payload): Form<DeleteMfaFactorForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    if let Some(message) = password_refused(
+        &repositories,
+        &login_throttle,
+        &client,
+        &user,
+        &payload.password,
+        "The password is incorrect.",
+    )
+    .await?
+    {
+        return account_page(&repositories, &user, message).await;
+    }
+
+    repositories
+        .mfa_factors
+        .delete(&user.user_id, &mfa_factor_id)
+        .await?;
+    audit::record(
+        &repositories,
+        &client,
+        AuditEvent::builder()
+            .action(AuditAction::MfaFactorDelete)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(mfa_factor_id)),
+    )
+    .await?;
+    tracing::debug!(?mfa_factor_id, "removed authenticator app");
+
+    account_page(
+        &repositories,
+        &user,
+        "The authenticator app has been removed.".into(),
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeleteAccountForm {
+    password: String,
+}
+
+/// Downloads everything stored about the logged in user.
+pub(crate) async fn export_account(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&repositories, &session).await?;
+
+    export(&repositories, &client, user.user_id, user).await
+}
+
+/// Deletes the user's account after confirming their password, leaving a tombstone in the audit log.
+pub(crate) async fn delete_account_form(
+    State(state): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    client: ClientInfo,
+    jar: CookieJar,
+    Form(payload): Form<DeleteAccountForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let user = session_user(&state.repositories, &session).await?;
+
+    if let Some(message) = password_refused(
+        &state.repositories,
+        &state.login_throttle,
+        &client,
+        &user,
+        &payload.password,
+        "The password is incorrect.",
+    )
+    .await?
+    {
+        return account_page(&state.repositories, &user, message).await;
+    }
+
+    for session in state
+        .repositories
+        .sessions
+        .by_user_id(&user.user_id)
+        .await?
+    {
+        notify_backchannel(&state, &session).await?;
+    }
+    let erased = user_data::erase(&state.repositories, &user).await?;
+    let jar = end_session(&state.repositories, jar, &session).await?;
+    state.api_key_cache.remove_owner(&user.user_id.to_string());
+    // The tombstone is performed by the deleted user, so it leaves out where they deleted it from.
+    audit::record(
+        &state.repositories,
+        &ClientInfo::default(),
+        AuditEvent::builder()
+            .action(AuditAction::UserDelete)
+            .actor_id(Some(user.user_id))
+            .target_id(Some(user.user_id))
+            .detail(Some(erased.to_string())),
+    )
+    .await?;
+    tracing::debug!(?user.user_id, "deleted account");
+
+    Ok((jar, HtmlPage::AccountDeleted).into_response())
+}
+
+#[component]
+pub(crate) fn account_overview(account: AccountView) -> Element {
+    rsx!(
+        h1 { "account" }
+        p {
+            text_align: "center",
+            "Logged in as "
+            strong { {account.identifier} }
+        }
+        if let Some(message) = account.message {
+            p {
+                text_align: "center",
+                strong { {message} }
+            }
+        }
+
+        if account.admin {
+            p {
+                text_align: "center",
+                a {
+                    href: "/dashboard",
+                    "admin dashboard"
+                }
+            }
+        }
+
+        h2 { "email" }
+        form {
+            action: "/account/email",
+            method: "POST",
+            input {
+                r#type: "text",
+                name: "email",
+                placeholder: "email",
+                value: account.email,
+            }
+            input {
+                r#type: "submit",
+                value: "Save",
+            }
+        }
+
+        h2 { "password" }
+        form {
+            action: "/account/password",
+            method: "POST",
+            input {
+                r#type: "password",
+                name: "current_password",
+                placeholder: "current password",
+            }
+            input {
+                r#type: "password",
+                name: "new_password",
+                placeholder: "new password",
+            }
+            input {
+                r#type: "submit",
+                value: "Change",
+            }
+        }
+
+        h2 { "two-factor authentication" }
+        ul {
+            for factor in account.mfa_factors {
+                li {
+                    strong { {factor.name} }
+                    " added {factor.created}"
+                    form {
+                        action: "/account/mfa/{factor.mfa_factor_id}/delete",
+                        method: "POST",
+                        input {
+                            r#type: "password",
+                            name: "password",
+                            placeholder: "password",
+                        }
+                        input {
+                            r#type: "submit",
+                            value: "Remove",
+                        }
+                    }
+                }
+            }
+        }
+        p {
+            "Add this secret to an authenticator app, or open "
+            a {
+                href: account.new_mfa_uri,
+                "this link"
+            }
+            " on the device with the app: "
+            code { {account.new_mfa_secret.clone()} }
+        }
+        form {
+            action: "/account/mfa",
+            method: "POST",
+            input {
+                r#type: "hidden",
+                name: "secret",
+                value: account.new_mfa_secret,
+            }
+            input {
+                r#type: "text",
+                name: "name",
+                placeholder: "name, e.g. phone",
+            }
+            input {
+                r#type: "text",
+                name: "code",
+                placeholder: "code the app shows",
+                autocomplete: "one-time-code",
+                inputmode: "numeric",
+            }
+            input {
+                r#type: "submit",
+                value: "Add",
+            }
+        }
+
+        h2 { "sessions" }
+        a {
+            href: "/account/sessions",
+            "manage the places you are logged in"
+        }
+
+        h2 { "api keys" }
+        ul {
+            for api_key in account.api_keys {
+                li {
+                    strong { {api_key.name} }
+                    " {api_key.api_key_id}"
+                    br {}
+                    "{api_key.scopes}, {api_key.status}"
+                    if api_key.active {
+                        form {
+                            action: "/account/api-keys/{api_key.api_key_id}/revoke",
+                            method: "POST",
+                            input {
+                                r#type: "submit",
+                                value: "Revoke",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        form {
+            action: "/account/api-keys",
+            method: "POST",
+            input {
+                r#type: "text",
+                name: "name",
+                placeholder: "name",
+            }
+            input {
+                r#type: "text",
+                name: "scopes",
+                placeholder: "scopes, space separated",
+            }
+            input {
+                r#type: "text",
+                name: "expires_in_days",
+                placeholder: "expires in days",
+            }
+            input {
+                r#type: "submit",
+                value: "Create",
+            }
+        }
+
+        h2 { "applications" }
+        ul {
+            for application in account.applications {
+                li {
+                    strong { {application.name} }
+                    " {application.application_id}"
+                    ul {
+                        for url in application.allowed_callback_urls {
+                            li { {url} }
+                        }
+                    }
+                }
+            }
+        }
+        form {
+            action: "/account/applications",
+            method: "POST",
+            input {
+                r#type: "text",
+                name: "name",
+                placeholder: "name",
+            }
+            textarea {
+                name: "allowed_callback_urls",
+                placeholder: "callback urls, one per line",
+            }
+            textarea {
+                name: "allowed_origins",
+                placeholder: "origins, one per line",
+            }
+            input {
+                r#type: "submit",
+                value: "Create",
+            }
+        }
+
+        h2 { "authorized applications" }
+        ul {
+            for grant in account.grants {
+                li {
+                    strong { {grant.application_name} }
+                    " {grant.scopes}"
+                    form {
+                        action: "/account/grants/{grant.application_id}/revoke",
+                        method: "POST",
+                        input {
+                            r#type: "submit",
+                            value: "Revoke",
+                        }
+                    }
+                }
+            }
+        }
+
+        h2 { "your data" }
+        p {
+            a {
+                href: "/account/export",
+                "download my data"
+            }
+        }
+
+        h2 { "delete account" }
+        form {
+            action: "/account/delete",
+            method: "POST",
+            input {
+                r#type: "password",
+                name: "password",
+                placeholder: "password",
+            }
+            input {
+                r#type: "submit",
+                value: "Delete",
+            }
+        }
+
+        a {
+            href: "/logout",
+            "log out"
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        pagination::MAX_PAGE_SIZE,
+        testing::{app, form, json, login, register, send, PASSWORD},
+        totp,
+    };
+    use axum::http::{header, Request, StatusCode};
+    use lockpad_models::{
+        api_key::ApiKey,
+        audit_event::{AuditAction, AuditFilter},
+        entity::Builder,
+        mfa_factor::MfaFactor,
+        repository::Repositories,
+        Pagination,
+    };
+    use serde_json::json;
+    use time::OffsetDateTime;
+
+    async fn account_page(app: &axum::Router, cookie: &str) -> String {
+        let request = Request::builder()
+            .uri("/account")
+            .header(header::COOKIE, cookie)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        send(app, request).await.2
+    }
+
+    #[tokio::test]
+    async fn changing_the_password_logs_out_other_sessions() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        let token = register(&app, "alice").await;
+        let laptop = login(&app, "alice", PASSWORD).await;
+        let phone = login(&app, "alice", PASSWORD).await;
+
+        let body = serde_urlencoded::to_string([
+            ("current_password", PASSWORD),
+            ("new_password", "another correct horse battery staple"),
+        ])
+        .unwrap();
+        let (status, _, body) = send(&app, form("/account/password", Some(&laptop), &body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("has been changed"), "{body}");
+        let filter = AuditFilter {
+            action: Some(AuditAction::PasswordChange),
+            ..Default::default()
+        };
+        let (events, _) = repositories
+            .audit_events
+            .query(&filter, Pagination::first(10))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1, "the change is audited");
+
+        assert!(account_page(&app, &laptop).await.contains("alice"));
+        assert!(!account_page(&app, &phone).await.contains("alice"));
+        let request = json("GET", "/me", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn confirming_the_password_is_throttled_like_logging_in() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        register(&app, "alice").await;
+        let cookie = login(&app, "alice", PASSWORD).await;
+
+        let body = serde_urlencoded::to_string([("password", "wrong")]).unwrap();
+        let (_, _, body) = send(&app, form("/account/delete", Some(&cookie), &body)).await;
+        assert!(body.contains("The password is incorrect."), "{body}");
+
+        let body = serde_urlencoded::to_string([("password", PASSWORD)]).unwrap();
+        let (_, _, body) = send(&app, form("/account/delete", Some(&cookie), &body)).await;
+        assert!(body.contains("Too many incorrect passwords"), "{body}");
+        let user = repositories.users.by_identifier("alice").await.unwrap();
+        assert!(user.is_some(), "the account is kept");
+
+        let credentials = json!({ "username": "alice", "password": PASSWORD });
+        let request = json("POST", "/api/authorize", None, credentials);
+        assert_eq!(send(&app, request).await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn logging_in_needs_a_code_once_an_authenticator_app_is_added() {
+        let app = app(Repositories::memory());
+        register(&app, "alice").await;
+        let cookie = login(&app, "alice", PASSWORD).await;
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let body = serde_urlencoded::to_string([
+            ("name", "phone"),
+            ("secret", secret),
+            ("code", "000000"),
+        ])
+        .unwrap();
+        let (_, _, body) = send(&app, form("/account/mfa", Some(&cookie), &body)).await;
+        assert!(body.contains("does not match"), "{body}");
+        assert!(body.contains(secret), "the secret is offered again");
+
+        let code = totp::code_at(secret, now);
+        let body =
+            serde_urlencoded::to_string([("name", "phone"), ("secret", secret), ("code", &code)])
+                .unwrap();
+        let (_, _, body) = send(&app, form("/account/mfa", Some(&cookie), &body)).await;
+        assert!(body.contains("has been added"), "{body}");
+
+        let credentials = json!({ "username": "alice", "password": PASSWORD });
+        let (status, _, body) = send(&app, json("POST", "/api/authorize", None, credentials)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, "a one-time code is required");
+        let body =
+            serde_urlencoded::to_string([("username", "alice"), ("password", PASSWORD)]).unwrap();
+        let (status, _, body) = send(&app, form("/forms/authorize", None, &body)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(
+            body.contains("Enter the code from your authenticator app."),
+            "{body}"
+        );
+        assert!(body.contains("value=\"alice\""), "the username is kept");
+
+        // the code used to add the app was used up, the app shows the next one soon after
+        let code = totp::code_at(secret, now + 30);
+        let credentials = json!({ "username": "alice", "password": PASSWORD, "code": code });
+        let request = json("POST", "/api/authorize", None, credentials.clone());
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+        let (status, _, body) = send(&app, json("POST", "/api/authorize", None, credentials)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, "unauthorized", "codes can't be used twice");
+    }
+
+    #[tokio::test]
+    async fn the_login_page_shows_a_wrong_code() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        register(&app, "alice").await;
+        let user = repositories
+            .users
+            .by_identifier("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string();
+        let factor = MfaFactor::new(user.user_id, "phone".to_string(), secret, 0);
+        repositories.mfa_factors.create(&factor).await.unwrap();
+
+        let body = serde_urlencoded::to_string([
+            ("username", "alice"),
+            ("password", PASSWORD),
+            ("code", "not a code"),
+        ])
+        .unwrap();
+        let (status, _, body) = send(&app, form("/forms/authorize", None, &body)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body.contains("one-time code is incorrect"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn lists_more_api_keys_than_fit_on_a_page() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        register(&app, "alice").await;
+        let cookie = login(&app, "alice", PASSWORD).await;
+        let user = repositories
+            .users
+            .by_identifier("alice")
+            .await
+            .unwrap()
+            .unwrap();
+
+        for i in 0..=MAX_PAGE_SIZE {
+            let api_key = ApiKey::builder()
+                .owner_id(user.user_id)
+                .name(format!("key-{i}"))
+                .secret("hash".to_string())
+                .build()
+                .unwrap();
+            repositories.api_keys.create(&api_key).await.unwrap();
+        }
+
+        let body = account_page(&app, &cookie).await;
+        assert!(body.contains("key-0<"), "{body}");
+        assert!(body.contains(&format!("key-{MAX_PAGE_SIZE}<")));
+    }
+}
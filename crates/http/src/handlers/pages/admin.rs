@@ -0,0 +1,516 @@
+use axum::{
+    extract::{FromRequestParts, Path, Query, State},
+    http::{request::Parts, Uri},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use dioxus::prelude::*;
+use lockpad_auth::Claims;
+use lockpad_models::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditAction, AuditEvent},
+    repository::Repositories,
+    user::User,
+    user_data::all_pages,
+};
+use lockpad_ulid::Ulid;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{sessions::format_timestamp, HtmlPage};
+use crate::{
+    audit,
+    client::ClientInfo,
+    error::{Error, Result},
+    handlers::{auth::Redirect, session::revoke_others},
+    pagination::{next_uri, PageQuery},
+    session::CurrentSession,
+    ServerState,
+};
+
+/// The administrator using the dashboard.
+/// Visitors without a session are asked to log in, users who are not admins are turned away.
+pub(crate) struct AdminSession(pub User);
+
+#[async_trait::async_trait]
+impl FromRequestParts<ServerState> for AdminSession {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let CurrentSession(session) = CurrentSession::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Some(session) = session else {
+            return Err(HtmlPage::NotLoggedIn.into_response());
+        };
+
+        let user = state
+            .repositories
+            .users
+            .by_id(&session.user_id)
+            .await
+            .map_err(|err| Error::from(err).into_response())?;
+        match user {
+            Some(user) if user.admin && !user.disabled => Ok(AdminSession(user)),
+            _ => Err(HtmlPage::Forbidden.into_response()),
+        }
+    }
+}
+
+/// The dashboard sections.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum AdminView {
+    Users {
+        search: String,
+        users: Vec<UserView>,
+        /// Link to the next page of users, if there is one
+        next: Option<String>,
+    },
+    User {
+        user: UserView,
+        sessions: usize,
+        applications: Vec<ApplicationView>,
+        api_keys: Vec<ApiKeyView>,
+    },
+    Applications(Vec<ApplicationView>),
+    ApiKeys(Vec<ApiKeyView>),
+    SigningKey(SigningKeyView),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UserView {
+    user_id: String,
+    identifier: String,
+    email: String,
+    admin: bool,
+    disabled: bool,
+}
+
+impl From<User> for UserView {
+    fn from(user: User) -> Self {
+        Self {
+            user_id: user.user_id.to_string(),
+            identifier: user.identifier,
+            email: user.email.unwrap_or_default(),
+            admin: user.admin,
+            disabled: user.disabled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ApplicationView {
+    application_id: String,
+    owner_id: String,
+    name: String,
+    allowed_callback_urls: Vec<String>,
+}
+
+impl From<Application> for ApplicationView {
+    fn from(application: Application) -> Self {
+        Self {
+            application_id: application.application_id.to_string(),
+            owner_id: application.owner_id.to_string(),
+            name: application.name,
+            allowed_callback_urls: application.allowed_callback_urls,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ApiKeyView {
+    api_key_id: String,
+    owner_id: String,
+    name: String,
+}
+
+impl From<ApiKey> for ApiKeyView {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            api_key_id: api_key.api_key_id.to_string(),
+            owner_id: api_key.owner_id.to_string(),
+            name: api_key.name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SigningKeyView {
+    issuer: String,
+    algorithm: String,
+    key_id: String,
+    bits: usize,
+    /// SHA-256 of the public key's PEM encoding
+    fingerprint: String,
+    /// Whether a token signed with the private key verifies against the public key
+    verified: bool,
+    checked_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UserSearch {
+    #[serde(default)]
+    q: String,
+}
+
+pub(crate) async fn dashboard_users(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AdminSession(_admin): AdminSession,
+    uri: Uri,
+    Query(search): Query<UserSearch>,
+    Query(page): Query<PageQuery>,
+) -> Result<Response> {
+    let (users, pagination) = repositories
+        .users
+        .search(search.q.trim(), page.pagination())
+        .await?;
+    let next = pagination
+        .last_key
+        .map(|next| next_uri(&uri, &next, pagination.count));
+
+    Ok(HtmlPage::Admin(AdminView::Users {
+        search: search.q,
+        users: users.into_iter().map(UserView::from).collect(),
+        next,
+    })
+    .into_response())
+}
+
+pub(crate) async fn dashboard_user(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AdminSession(_admin): AdminSession,
+    Path(user_id): Path<Ulid>,
+) -> Result<Response> {
+    let user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let sessions = repositories.sessions.by_user_id(&user_id).await?;
+    let applications =
+        all_pages(|pagination| repositories.applications.query(user_id, pagination)).await?;
+    let applications = applications
+        .into_iter()
+        .map(ApplicationView::from)
+        .collect();
+    let api_keys = all_pages(|pagination| repositories.api_keys.query(user_id, pagination)).await?;
+    let api_keys = api_keys.into_iter().map(ApiKeyView::from).collect();
+
+    Ok(HtmlPage::Admin(AdminView::User {
+        user: user.into(),
+        sessions: sessions.len(),
+        applications,
+        api_keys,
+    })
+    .into_response())
+}
+
+/// Disables the account and ends its sessions.
+pub(crate) async fn dashboard_disable_user(
+    State(state): State<ServerState>,
+    AdminSession(admin): AdminSession,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<Response> {
+    if user_id == admin.user_id {
+        return Err(Error::BadRequest("admins cannot disable themselves"));
+    }
+    let mut user = state
+        .repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    user.disabled = true;
+    state.repositories.users.update(&user).await?;
+    revoke_others(&state, &user.user_id, None).await?;
+    state
+        .repositories
+        .users
+        .revoke_tokens(&user.user_id)
+        .await?;
+    state.api_key_cache.remove_owner(&user.user_id.to_string());
+    record_user_update(&state.repositories, &client, &admin, &user).await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "user disabled by admin");
+
+    Ok(Redirect::found(&format!("/dashboard/users/{user_id}"))?.into_response())
+}
+
+pub(crate) async fn dashboard_enable_user(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AdminSession(admin): AdminSession,
+    client: ClientInfo,
+    Path(user_id): Path<Ulid>,
+) -> Result<Response> {
+    let mut user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    user.disabled = false;
+    repositories.users.update(&user).await?;
+    record_user_update(&repositories, &client, &admin, &user).await?;
+    tracing::debug!(?user.user_id, ?admin.user_id, "user enabled by admin");
+
+    Ok(Redirect::found(&format!("/dashboard/users/{user_id}"))?.into_response())
+}
+
+/// Records that the admin disabled or enabled the user, both change the `disabled` field.
+async fn record_user_update(
+    repositories: &Repositories,
+    client: &ClientInfo,
+    admin: &User,
+    user: &User,
+) -> Result<()> {
+    let event = AuditEvent::builder()
+        .action(AuditAction::UserUpdate)
+        .actor_id(Some(admin.user_id))
+        .target_id(Some(user.user_id))
+        .detail(Some("disabled".to_string()));
+
+    audit::record(repositories, client, event).await
+}
+
+pub(crate) async fn dashboard_applications(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AdminSession(_admin): AdminSession,
+) -> Result<Response> {
+    let applications = repositories.applications.all().await?;
+
+    Ok(HtmlPage::Admin(AdminView::Applications(
+        applications
+            .into_iter()
+            .map(ApplicationView::from)
+            .collect(),
+    ))
+    .into_response())
+}
+
+pub(crate) async fn dashboard_api_keys(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AdminSession(_admin): AdminSession,
+) -> Result<Response> {
+    let api_keys = repositories.api_keys.all().await?;
+
+    Ok(HtmlPage::Admin(AdminView::ApiKeys(
+        api_keys.into_iter().map(ApiKeyView::from).collect(),
+    ))
+    .into_response())
+}
+
+/// Shows which key tokens are signed with, and checks that the configured pair belongs together.
+pub(crate) async fn dashboard_signing_key(
+    State(ServerState {
+        encoding_key,
+        public_key,
+        issuer,
+        ..
+    }): State<ServerState>,
+    AdminSession(_admin): AdminSession,
+) -> Result<Response> {
+    let token = Claims::new("signing-key-check".to_string())
+        .encode(&encoding_key)
+        .await?;
+    let decoding_key = axum::extract::FromRef::from_ref(&public_key);
+    let verified = Claims::decode(&token, &decoding_key).await.is_ok();
+
+    let jwk: jsonwebtoken::jwk::Jwk = public_key.clone().into();
+    let fingerprint = base64::engine::general_purpose::STANDARD_NO_PAD
+        .encode(Sha256::digest(public_key.as_ref()));
+
+    Ok(HtmlPage::Admin(AdminView::SigningKey(SigningKeyView {
+        issuer,
+        algorithm: jwk
+            .common
+            .algorithm
+            .map(|algorithm| format!("{algorithm:?}"))
+            .unwrap_or_default(),
+        key_id: jwk.common.key_id.unwrap_or_default(),
+        bits: public_key.bits(),
+        fingerprint,
+        verified,
+        checked_at: format_timestamp(time::OffsetDateTime::now_utc()),
+    }))
+    .into_response())
+}
+
+#[component]
+fn admin_nav() -> Element {
+    rsx!(
+        h1 { "dashboard" }
+        p {
+            text_align: "center",
+            a { href: "/dashboard", "users" }
+            " · "
+            a { href: "/dashboard/applications", "applications" }
+            " · "
+            a { href: "/dashboard/api-keys", "api keys" }
+            " · "
+            a { href: "/dashboard/signing-key", "signing key" }
+        }
+    )
+}
+
+#[component]
+pub(crate) fn admin_dashboard(view: AdminView) -> Element {
+    let section = match view {
+        AdminView::Users {
+            search,
+            users,
+            next,
+        } => rsx!(
+            h2 { "users" }
+            form {
+                action: "/dashboard",
+                method: "GET",
+                input {
+                    r#type: "text",
+                    name: "q",
+                    placeholder: "identifier or email",
+                    value: search,
+                }
+                input {
+                    r#type: "submit",
+                    value: "Search",
+                }
+            }
+            ul {
+                for user in users {
+                    li {
+                        a {
+                            href: "/dashboard/users/{user.user_id}",
+                            strong { {user.identifier} }
+                        }
+                        " {user.email}"
+                        if user.admin {
+                            " (admin)"
+                        }
+                        if user.disabled {
+                            " (disabled)"
+                        }
+                    }
+                }
+            }
+            if let Some(next) = next {
+                p {
+                    a { href: "{next}", "next page" }
+                }
+            }
+        ),
+        AdminView::User {
+            user,
+            sessions,
+            applications,
+            api_keys,
+        } => rsx!(
+            h2 { {user.identifier} }
+            ul {
+                li { "id: {user.user_id}" }
+                li { "email: {user.email}" }
+                li { "admin: {user.admin}" }
+                li { "disabled: {user.disabled}" }
+                li { "active sessions: {sessions}" }
+            }
+            if user.disabled {
+                form {
+                    action: "/dashboard/users/{user.user_id}/enable",
+                    method: "POST",
+                    input {
+                        r#type: "submit",
+                        value: "Enable",
+                    }
+                }
+            } else {
+                form {
+                    action: "/dashboard/users/{user.user_id}/disable",
+                    method: "POST",
+                    input {
+                        r#type: "submit",
+                        value: "Disable",
+                    }
+                }
+            }
+            h2 { "applications" }
+            application_list { applications: applications }
+            h2 { "api keys" }
+            api_key_list { api_keys: api_keys }
+        ),
+        AdminView::Applications(applications) => rsx!(
+            h2 { "applications" }
+            application_list { applications: applications }
+        ),
+        AdminView::ApiKeys(api_keys) => rsx!(
+            h2 { "api keys" }
+            api_key_list { api_keys: api_keys }
+        ),
+        AdminView::SigningKey(key) => rsx!(
+            h2 { "signing key" }
+            ul {
+                li { "issuer: {key.issuer}" }
+                li { "algorithm: {key.algorithm}" }
+                li { "key id: {key.key_id}" }
+                li { "size: {key.bits} bits" }
+                li { "fingerprint: SHA256:{key.fingerprint}" }
+                li {
+                    if key.verified {
+                        "status: the private and public keys match (checked {key.checked_at})"
+                    } else {
+                        strong { "status: tokens signed with the private key do not verify against the public key" }
+                    }
+                }
+            }
+        ),
+    };
+
+    rsx!(
+        admin_nav {}
+        {section}
+    )
+}
+
+#[component]
+fn application_list(applications: Vec<ApplicationView>) -> Element {
+    rsx!(
+        ul {
+            for application in applications {
+                li {
+                    strong { {application.name} }
+                    " {application.application_id}, owned by "
+                    a {
+                        href: "/dashboard/users/{application.owner_id}",
+                        {application.owner_id.clone()}
+                    }
+                    ul {
+                        for url in application.allowed_callback_urls {
+                            li { {url} }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+#[component]
+fn api_key_list(api_keys: Vec<ApiKeyView>) -> Element {
+    rsx!(
+        ul {
+            for api_key in api_keys {
+                li {
+                    strong { {api_key.name} }
+                    " {api_key.api_key_id}, owned by "
+                    a {
+                        href: "/dashboard/users/{api_key.owner_id}",
+                        {api_key.owner_id.clone()}
+                    }
+                }
+            }
+        }
+    )
+}
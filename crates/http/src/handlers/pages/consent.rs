@@ -0,0 +1,241 @@
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use dioxus::prelude::*;
+use lockpad_models::{application::Application, grant::Grant, repository::Repositories};
+use lockpad_ulid::Ulid;
+use serde::Deserialize;
+
+use super::{complete_login, HtmlPage, LoginScreenQuery};
+use crate::{
+    error::{Error, Result},
+    handlers::auth::Redirect,
+    session::CurrentSession,
+    ServerState,
+};
+
+/// Determines whether the user has to approve the application's request.
+/// Applications owned by the user are trusted, others need the requested scopes to have been granted.
+pub(crate) async fn needs_consent(
+    repositories: &Repositories,
+    params: &LoginScreenQuery,
+    application: &Application,
+    user_id: &Ulid,
+) -> Result<bool> {
+    if &application.owner_id == user_id {
+        return Ok(false);
+    }
+    if params.has_prompt("consent") {
+        return Ok(true);
+    }
+
+    let grant = repositories
+        .grants
+        .by_user_and_application(user_id, &application.application_id)
+        .await?;
+    match grant {
+        None => Ok(true),
+        Some(grant) => Ok(!grant.covers(&params.scopes())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ConsentDecision {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ConsentForm {
+    decision: ConsentDecision,
+}
+
+/// Records the user's answer to the consent screen.
+/// If they allowed access, the grant is remembered and the login completes.
+pub(crate) async fn consent(
+    State(ServerState {
+        repositories,
+        encoding_key,
+        ..
+    }): State<ServerState>,
+    Query(params): Query<LoginScreenQuery>,
+    CurrentSession(session): CurrentSession,
+    Form(payload): Form<ConsentForm>,
+) -> Result<Response> {
+    let Some(session) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+    let application = params
+        .application(&repositories)
+        .await
+        .ok_or(Error::Unauthorized)?;
+
+    match payload.decision {
+        ConsentDecision::Deny => {
+            tracing::debug!(?application.application_id, "consent denied");
+            let callback_url = params.callback_url(&[("error", "access_denied")]);
+            Ok(Redirect::found(&callback_url)?.into_response())
+        }
+        ConsentDecision::Allow => {
+            let existing = repositories
+                .grants
+                .by_user_and_application(&session.user_id, &application.application_id)
+                .await?;
+
+            // Keep whatever was granted before, the application may ask for less than it has.
+            let mut scopes = existing.map(|grant| grant.scopes).unwrap_or_default();
+            for scope in params.scopes() {
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+
+            let grant = Grant::new(session.user_id, application.application_id, scopes);
+            repositories.grants.save(&grant).await?;
+            tracing::debug!(?application.application_id, "consent granted");
+
+            complete_login(
+                &repositories,
+                &encoding_key,
+                &params,
+                &application,
+                &session,
+            )
+            .await
+        }
+    }
+}
+
+#[component]
+pub(crate) fn consent_prompt(
+    application_name: String,
+    scopes: Vec<String>,
+    submit_uri: String,
+) -> Element {
+    rsx!(
+        h1 { "authorize {application_name}" }
+        p {
+            text_align: "center",
+            strong { {application_name.clone()} }
+            " would like to access your account."
+        }
+        if !scopes.is_empty() {
+            p { "It is requesting the following permissions:" }
+            ul {
+                for scope in scopes {
+                    li { strong { {scope} } }
+                }
+            }
+        }
+        form {
+            action: submit_uri.clone(),
+            method: "POST",
+            input {
+                r#type: "hidden",
+                name: "decision",
+                value: "allow",
+            }
+            input {
+                r#type: "submit",
+                value: "Allow",
+            }
+        }
+        form {
+            action: submit_uri,
+            method: "POST",
+            input {
+                r#type: "hidden",
+                name: "decision",
+                value: "deny",
+            }
+            input {
+                r#type: "submit",
+                value: "Deny",
+            }
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{app, json, register, send, PASSWORD};
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, Request, StatusCode},
+    };
+    use lockpad_models::{grant::Grant, repository::Repositories};
+    use lockpad_ulid::Ulid;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    const CALLBACK: &str = "https://app.example/callback";
+
+    fn location(headers: &HeaderMap) -> String {
+        headers[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn prompt_consent_survives_the_login_form() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+
+        let developer = register(&app, "developer").await;
+        let application = json!({
+            "name": "third party",
+            "allowed_origins": [],
+            "allowed_callback_urls": [CALLBACK],
+        });
+        let request = json("POST", "/applications", Some(&developer), application);
+        let (_, _, body) = send(&app, request).await;
+        let application: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let client_id = application["application_id"].as_str().unwrap().to_string();
+
+        let token = register(&app, "alice").await;
+        let (_, _, body) = send(&app, json("GET", "/me", Some(&token), json!(null))).await;
+        let user: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let user_id = Ulid::from_str(user["user_id"].as_str().unwrap()).unwrap();
+        let grant = Grant::new(
+            user_id,
+            Ulid::from_str(&client_id).unwrap(),
+            vec!["read".to_string()],
+        );
+        repositories.grants.save(&grant).await.unwrap();
+
+        for (prompt, asks_consent) in [(Some("consent"), true), (None, false)] {
+            let mut params = vec![
+                ("client_id", client_id.as_str()),
+                ("redirect_uri", CALLBACK),
+                ("scope", "read"),
+            ];
+            params.extend(prompt.map(|prompt| ("prompt", prompt)));
+            let query = serde_urlencoded::to_string(params).unwrap();
+            let request = Request::builder()
+                .method("POST")
+                .uri(format!("/forms/authorize?{query}"))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("username=alice&password={PASSWORD}")))
+                .unwrap();
+            let (status, headers, _) = send(&app, request).await;
+            assert_eq!(status, StatusCode::FOUND);
+            let cookie = headers[header::SET_COOKIE].to_str().unwrap();
+            let cookie = cookie.split(';').next().unwrap().to_string();
+
+            let request = Request::builder()
+                .uri(location(&headers))
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+            let (status, headers, body) = send(&app, request).await;
+            if asks_consent {
+                assert_eq!(status, StatusCode::OK);
+                assert!(body.contains("would like to access your account"));
+            } else {
+                assert_eq!(status, StatusCode::FOUND);
+                assert!(location(&headers).starts_with(CALLBACK));
+            }
+        }
+    }
+}
@@ -1,72 +1,255 @@
 use axum::{
-    extract::{Query, State},
-    response::IntoResponse,
+    extract::{Query, RawQuery, State},
+    response::{IntoResponse, Response},
 };
 use dioxus::prelude::*;
-use lockpad_models::application::Application;
+use jsonwebtoken::EncodingKey;
+use lockpad_auth::Claims;
+use lockpad_models::{application::Application, repository::Repositories, session::Session};
 use lockpad_ulid::Ulid;
 use serde::Deserialize;
 use std::str::FromStr;
+use time::Duration;
+use validator::ValidationErrors;
 
-use crate::ServerState;
+use crate::{
+    error::Result, handlers::auth::Redirect, session::CurrentSession, validation::url_matches,
+    ServerState,
+};
+
+pub mod account;
+pub mod admin;
+pub mod consent;
+pub mod sessions;
 
-pub(crate) async fn root() -> impl IntoResponse {
-    HtmlPage::Default
+pub(crate) async fn root(CurrentSession(session): CurrentSession) -> impl IntoResponse {
+    HtmlPage::Default {
+        logged_in: session.is_some(),
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct LoginScreenQuery {
     pub redirect_uri: String,
     pub client_id: String,
+    /// Space delimited list of prompts, as in OpenID Connect.
+    /// `login` forces the user to provide credentials even if they have a session.
+    /// `none` fails instead of displaying the login form.
+    /// `consent` asks the user to approve the application even if they did before.
+    pub prompt: Option<String>,
+    /// The maximum number of seconds since the user last provided credentials.
+    pub max_age: Option<i64>,
+    /// Space delimited list of scopes the application is requesting.
+    pub scope: Option<String>,
+}
+
+impl LoginScreenQuery {
+    /// Lookup the client_id as the application_id and determine if the redirect_uri is valid.
+    pub(crate) async fn application(&self, repositories: &Repositories) -> Option<Application> {
+        let app_id = Ulid::from_str(&self.client_id).ok()?;
+        let application = match repositories.applications.by_id(&app_id).await {
+            Err(_) => return None,
+            Ok(None) => return None,
+            Ok(Some(application)) => application,
+        };
+        tracing::debug!("application: {:?}", application);
+
+        // compare the redirect_uri to application.redirect_uris
+        if !application
+            .allowed_callback_urls
+            .iter()
+            .any(|allowed| url_matches(allowed, &self.redirect_uri))
+        {
+            return None;
+        }
+
+        // TODO: compare the origin to application.allowed_origins
+
+        Some(application)
+    }
+
+    /// The requested scopes, without duplicates.
+    pub(crate) fn scopes(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = Vec::new();
+        for scope in self.scope.as_deref().unwrap_or_default().split_whitespace() {
+            if !scopes.iter().any(|s| s == scope) {
+                scopes.push(scope.to_string());
+            }
+        }
+
+        scopes
+    }
+
+    /// Encodes the parameters that identify the request, for continuing it on another page.
+    /// The `login` and `none` prompts are left out since they only apply to the initial request,
+    /// `consent` is kept so the user is still asked after logging in.
+    pub(crate) fn query_string(&self) -> String {
+        let mut params = vec![
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+        ];
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+        if self.has_prompt("consent") {
+            params.push(("prompt", "consent"));
+        }
+
+        serde_urlencoded::to_string(params).unwrap_or_default()
+    }
+
+    pub(crate) fn has_prompt(&self, value: &str) -> bool {
+        self.prompt
+            .as_deref()
+            .is_some_and(|prompt| prompt.split(' ').any(|p| p == value))
+    }
+
+    /// Determines whether the session may be used instead of asking for credentials.
+    fn accepts_session(&self, session: &Session) -> bool {
+        if self.has_prompt("login") {
+            return false;
+        }
+
+        match self.max_age {
+            None => true,
+            Some(max_age) => session.authenticated_within(Duration::seconds(max_age)),
+        }
+    }
+
+    /// Builds the uri the user is sent back to, with the given parameters appended.
+    pub(crate) fn callback_url(&self, params: &[(&str, &str)]) -> String {
+        let separator = if self.redirect_uri.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let params = serde_urlencoded::to_string(params).unwrap_or_default();
+
+        format!("{}{separator}{params}", self.redirect_uri)
+    }
 }
 
 /// Sends a screen that asks the user to provide credentials.
+/// If the user already has a session that satisfies the request, they are sent back to the application instead.
 pub(crate) async fn login_screen(
     query: Option<Query<LoginScreenQuery>>,
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-) -> impl IntoResponse {
+    RawQuery(raw_query): RawQuery,
+    State(ServerState {
+        repositories,
+        encoding_key,
+        ..
+    }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+) -> Result<Response> {
+    // Without an application, the user is logging in to manage their account.
     let params = match query {
-        None => return HtmlPage::NoParams,
+        None if raw_query.is_some() => return Ok(HtmlPage::NoParams.into_response()),
+        None if session.is_some() => return Ok(Redirect::found("/account")?.into_response()),
+        None => {
+            return Ok(login_form_page(None, String::new(), FieldErrors::default()).into_response())
+        }
         Some(query) => query.0,
     };
     tracing::debug!("login screen query: {:?}", params);
 
-    // Lookup the client_id as the application_id and determine if the redirect_uri and origins are valid.
-    // If the client_id is not valid, return an error.
-    // If the redirect_uri is not valid, return an error.
-    let app_id = Ulid::from_str(&params.client_id).unwrap();
-    let application = match Application::by_id(&pg_pool, &app_id).await {
-        Err(_) => return HtmlPage::InvalidParams,
-        Ok(None) => return HtmlPage::InvalidParams,
-        Ok(Some(application)) => application,
+    let application = match params.application(&repositories).await {
+        None => return Ok(HtmlPage::InvalidParams.into_response()),
+        Some(application) => application,
     };
-    tracing::debug!("application: {:?}", application);
-
-    // compare the redirect_uri to application.redirect_uris
-    if !application
-        .allowed_callback_urls
-        .contains(&params.redirect_uri)
-    {
-        return HtmlPage::InvalidParams;
+
+    if let Some(session) = session.filter(|session| params.accepts_session(session)) {
+        if consent::needs_consent(&repositories, &params, &application, &session.user_id).await? {
+            if params.has_prompt("none") {
+                let callback_url = params.callback_url(&[("error", "consent_required")]);
+                return Ok(Redirect::found(&callback_url)?.into_response());
+            }
+
+            return Ok(HtmlPage::Consent {
+                application_name: application.name,
+                scopes: params.scopes(),
+                submit_uri: format!("/forms/consent?{}", params.query_string()),
+            }
+            .into_response());
+        }
+
+        return complete_login(
+            &repositories,
+            &encoding_key,
+            &params,
+            &application,
+            &session,
+        )
+        .await;
+    }
+
+    if params.has_prompt("none") {
+        let callback_url = params.callback_url(&[("error", "login_required")]);
+        return Ok(Redirect::found(&callback_url)?.into_response());
     }
 
-    // TODO: compare the origin to application.allowed_origins
+    Ok(login_form_page(Some(&params), String::new(), FieldErrors::default()).into_response())
+}
+
+/// The login form, filled in with what the user submitted and the problems found with it.
+/// The form is sent back with the application parameters, so the user can continue to it once logged in.
+pub(crate) fn login_form_page(
+    params: Option<&LoginScreenQuery>,
+    username: String,
+    errors: FieldErrors,
+) -> HtmlPage {
+    let submit_uri = match params {
+        Some(params) => format!("/forms/authorize?{}", params.query_string()),
+        None => "/forms/authorize".to_string(),
+    };
 
     HtmlPage::CredentialsForm {
         form_type: HtmlFormType::Login,
-        submit_uri: "/forms/authorize".to_string(),
+        submit_uri,
+        username,
+        errors,
     }
 }
 
+/// Sends the user back to the application with a token issued from their session.
+pub(crate) async fn complete_login(
+    repositories: &Repositories,
+    encoding_key: &EncodingKey,
+    params: &LoginScreenQuery,
+    application: &Application,
+    session: &Session,
+) -> Result<Response> {
+    tracing::debug!(?session.session_id, "completing login from session");
+    repositories
+        .sessions
+        .add_application(session, &application.application_id)
+        .await?;
+
+    let token = Claims::new(session.user_id.to_string())
+        .with_session(session.session_id.to_string())
+        .with_scopes(&params.scopes())
+        .with_audience(application.application_id.to_string())
+        .encode(encoding_key)
+        .await?;
+
+    Ok(Redirect::found(&params.callback_url(&[("token", &token)]))?.into_response())
+}
+
 /// Sends a screen that asks the user to provide credentials.
 /// These credentials will be used to create a new account.
 /// This closely follows the login screen.
 pub(crate) async fn register_screen() -> impl IntoResponse {
+    register_form(String::new(), FieldErrors::default())
+}
+
+/// The registration form, filled in with what the user submitted and the problems found with it.
+pub(crate) fn register_form(username: String, errors: FieldErrors) -> HtmlPage {
     // Keep this really simple for now.
     // Later this could be its own dedicated page, but for now it's just a simple form.
     HtmlPage::CredentialsForm {
         form_type: HtmlFormType::Register,
         submit_uri: "/forms/register".to_string(),
+        username,
+        errors,
     }
 }
 
@@ -76,7 +259,7 @@ pub(crate) async fn disabled_register_screen() -> impl IntoResponse {
 }
 
 /// A response that sends an HTML page
-enum HtmlPage {
+pub(crate) enum HtmlPage {
     /// There was no origin provided in the request.
     #[allow(dead_code)]
     NoOrigin,
@@ -88,11 +271,36 @@ enum HtmlPage {
     CredentialsForm {
         form_type: HtmlFormType,
         submit_uri: String,
+        /// The username to fill in, after a submission was refused
+        username: String,
+        errors: FieldErrors,
     },
     /// The default page
-    Default,
+    Default { logged_in: bool },
     /// The registration page is disabled
     RegisterDisabled,
+    /// The user's session has ended
+    LoggedOut,
+    /// The page requires a session, but the user does not have one
+    NotLoggedIn,
+    /// The user is logged in, but not allowed to view the page
+    Forbidden,
+    /// The places the user is logged in
+    Sessions {
+        sessions: Vec<sessions::SessionView>,
+    },
+    /// The user's account settings
+    Account(account::AccountView),
+    /// The user's account has been deleted
+    AccountDeleted,
+    /// A section of the admin dashboard
+    Admin(admin::AdminView),
+    /// Ask the user to allow an application access to their account
+    Consent {
+        application_name: String,
+        scopes: Vec<String>,
+        submit_uri: String,
+    },
 }
 
 const STYLE: &str = include_str!("style.css");
@@ -103,9 +311,13 @@ impl axum::response::IntoResponse for HtmlPage {
             HtmlPage::CredentialsForm {
                 form_type,
                 submit_uri,
+                username,
+                errors,
             } => rsx!(login_form {
                 form_type: form_type,
                 submit_uri: submit_uri,
+                username: username,
+                errors: errors,
             }),
             HtmlPage::NoOrigin => rsx!(
                 div {
@@ -135,7 +347,21 @@ impl axum::response::IntoResponse for HtmlPage {
                     }
                 }
             ),
-            HtmlPage::Default => rsx!(
+            HtmlPage::Default { logged_in: true } => rsx!(
+                div {
+                    class: "container",
+                    h1 { "lockpad" }
+                    a {
+                        href: "/account",
+                        "account"
+                    }
+                    a {
+                        href: "/logout",
+                        "log out"
+                    }
+                }
+            ),
+            HtmlPage::Default { logged_in: false } => rsx!(
                 div {
                     class: "container",
                     h1 { "lockpad" }
@@ -149,6 +375,82 @@ impl axum::response::IntoResponse for HtmlPage {
                     }
                 }
             ),
+            HtmlPage::LoggedOut => rsx!(
+                div {
+                    class: "container",
+                    h1 { "lockpad" }
+                    p {
+                        text_align: "center",
+                        "You have been logged out."
+                    }
+                }
+            ),
+            HtmlPage::NotLoggedIn => rsx!(
+                div {
+                    class: "container",
+                    h1 { "lockpad" }
+                    p {
+                        text_align: "center",
+                        "You need to be logged in to view this page."
+                    }
+                    a {
+                        href: "/login",
+                        "log in"
+                    }
+                }
+            ),
+            HtmlPage::Forbidden => rsx!(
+                div {
+                    class: "container",
+                    h1 { "lockpad" }
+                    p {
+                        text_align: "center",
+                        "You do not have access to this page."
+                    }
+                }
+            ),
+            HtmlPage::Sessions { sessions } => rsx!(
+                div {
+                    class: "container",
+                    sessions::session_list { sessions: sessions }
+                }
+            ),
+            HtmlPage::Account(account) => rsx!(
+                div {
+                    class: "container",
+                    account::account_overview { account: account }
+                }
+            ),
+            HtmlPage::Admin(view) => rsx!(
+                div {
+                    class: "container",
+                    admin::admin_dashboard { view: view }
+                }
+            ),
+            HtmlPage::AccountDeleted => rsx!(
+                div {
+                    class: "container",
+                    h1 { "lockpad" }
+                    p {
+                        text_align: "center",
+                        "Your account has been deleted."
+                    }
+                }
+            ),
+            HtmlPage::Consent {
+                application_name,
+                scopes,
+                submit_uri,
+            } => rsx!(
+                div {
+                    class: "container",
+                    consent::consent_prompt {
+                        application_name: application_name,
+                        scopes: scopes,
+                        submit_uri: submit_uri,
+                    }
+                }
+            ),
             HtmlPage::RegisterDisabled => rsx!(
                 div {
                     class: "container",
@@ -170,15 +472,49 @@ impl axum::response::IntoResponse for HtmlPage {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum HtmlFormType {
+pub(crate) enum HtmlFormType {
     /// Allow for user account creation
     Register,
     /// Allows for user login
     Login,
 }
 
+/// Problems with the values submitted in a credentials form, shown next to their fields.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct FieldErrors {
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) code: Option<String>,
+}
+
+impl From<&ValidationErrors> for FieldErrors {
+    /// Collects the messages of each field, the ones without a message are left out.
+    fn from(errors: &ValidationErrors) -> Self {
+        let messages = |field: &str| {
+            let messages: Vec<String> = errors
+                .field_errors()
+                .get(field)?
+                .iter()
+                .filter_map(|error| error.message.as_ref().map(ToString::to_string))
+                .collect();
+            (!messages.is_empty()).then(|| messages.join(" "))
+        };
+
+        Self {
+            username: messages("username"),
+            password: messages("password"),
+            code: messages("code"),
+        }
+    }
+}
+
 #[component]
-fn login_form(form_type: HtmlFormType, submit_uri: String) -> Element {
+fn login_form(
+    form_type: HtmlFormType,
+    submit_uri: String,
+    username: String,
+    errors: FieldErrors,
+) -> Element {
     let form_name = match form_type {
         HtmlFormType::Register => "register-form",
         HtmlFormType::Login => "login-form",
@@ -199,6 +535,10 @@ fn login_form(form_type: HtmlFormType, submit_uri: String) -> Element {
                 id: "username",
                 name: "username",
                 placeholder: "username",
+                value: username,
+            }
+            if let Some(error) = errors.username {
+                span { class: "field-error", {error} }
             }
             input {
                 r#type: "password",
@@ -206,6 +546,22 @@ fn login_form(form_type: HtmlFormType, submit_uri: String) -> Element {
                 name: "password",
                 placeholder: "password",
             }
+            if let Some(error) = errors.password {
+                span { class: "field-error", {error} }
+            }
+            if form_type == HtmlFormType::Login {
+                input {
+                    r#type: "text",
+                    id: "code",
+                    name: "code",
+                    placeholder: "one-time code, if enabled",
+                    autocomplete: "one-time-code",
+                    inputmode: "numeric",
+                }
+                if let Some(error) = errors.code {
+                    span { class: "field-error", {error} }
+                }
+            }
             input {
                 r#type: "submit",
                 value: type_display,
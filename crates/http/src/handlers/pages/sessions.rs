@@ -0,0 +1,138 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::*;
+use lockpad_models::session::Session;
+use lockpad_ulid::Ulid;
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+
+use super::HtmlPage;
+use crate::{
+    client::describe_user_agent,
+    error::Result,
+    handlers::{
+        auth::Redirect,
+        session::{revoke, revoke_others},
+    },
+    session::CurrentSession,
+    ServerState,
+};
+
+/// A session as it is displayed to the user.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SessionView {
+    session_id: String,
+    device: String,
+    ip_address: String,
+    created_at: String,
+    last_seen_at: String,
+    current: bool,
+}
+
+impl SessionView {
+    fn new(session: &Session, current_session_id: &Ulid) -> Self {
+        Self {
+            session_id: session.session_id.to_string(),
+            device: describe_user_agent(session.user_agent.as_deref()),
+            ip_address: session
+                .ip_address
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()),
+            created_at: format_timestamp(session.created_at),
+            last_seen_at: format_timestamp(session.last_seen_at),
+            current: &session.session_id == current_session_id,
+        }
+    }
+}
+
+pub(crate) fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    timestamp
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute] UTC"
+        ))
+        .unwrap_or_default()
+}
+
+/// Lists the places the user is logged in.
+pub(crate) async fn sessions_screen(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+) -> Result<Response> {
+    let Some(current) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+
+    let sessions = repositories.sessions.by_user_id(&current.user_id).await?;
+    let sessions = sessions
+        .iter()
+        .map(|session| SessionView::new(session, &current.session_id))
+        .collect();
+
+    Ok(HtmlPage::Sessions { sessions }.into_response())
+}
+
+pub(crate) async fn revoke_session_form(
+    State(state): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+    Path(session_id): Path<Ulid>,
+) -> Result<Response> {
+    let Some(current) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+
+    revoke(&state, &current.user_id, &session_id).await?;
+
+    Ok(Redirect::found("/account/sessions")?.into_response())
+}
+
+pub(crate) async fn revoke_other_sessions_form(
+    State(state): State<ServerState>,
+    CurrentSession(session): CurrentSession,
+) -> Result<Response> {
+    let Some(current) = session else {
+        return Ok(HtmlPage::NotLoggedIn.into_response());
+    };
+
+    revoke_others(&state, &current.user_id, Some(&current.session_id)).await?;
+
+    Ok(Redirect::found("/account/sessions")?.into_response())
+}
+
+#[component]
+pub(crate) fn session_list(sessions: Vec<SessionView>) -> Element {
+    rsx!(
+        h1 { "sessions" }
+        ul {
+            for session in sessions {
+                li {
+                    strong { {session.device} }
+                    if session.current {
+                        " (this device)"
+                    }
+                    br {}
+                    "{session.ip_address}, signed in {session.created_at}, last seen {session.last_seen_at}"
+                    if !session.current {
+                        form {
+                            action: "/account/sessions/{session.session_id}/revoke",
+                            method: "POST",
+                            input {
+                                r#type: "submit",
+                                value: "Revoke",
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        form {
+            action: "/account/sessions/revoke-others",
+            method: "POST",
+            input {
+                r#type: "submit",
+                value: "Sign out everywhere else",
+            }
+        }
+    )
+}
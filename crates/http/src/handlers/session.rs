@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+use crate::{
+    client::describe_user_agent,
+    error::{Error, Result},
+    handlers::logout::notify_backchannel,
+    token_auth::AccountClaims,
+    ServerState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use lockpad_models::session::Session;
+use lockpad_ulid::Ulid;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SessionResponse {
+    #[serde(flatten)]
+    session: Session,
+    /// A readable description of the device the session was started on
+    device: String,
+    /// Whether this is the session the request's token was issued from
+    current: bool,
+}
+
+/// Determines which session the token was issued from, if any.
+fn current_session_id(claims: &lockpad_auth::Claims) -> Result<Option<Ulid>> {
+    let session_id = claims.sid.as_deref().map(Ulid::from_str).transpose()?;
+
+    Ok(session_id)
+}
+
+pub(crate) async fn list_sessions(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+) -> Result<Json<Vec<SessionResponse>>> {
+    let user_id = Ulid::from_str(&claims.sub)?;
+    let current_session_id = current_session_id(&claims)?;
+
+    let sessions = repositories.sessions.by_user_id(&user_id).await?;
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            device: describe_user_agent(session.user_agent.as_deref()),
+            current: Some(session.session_id) == current_session_id,
+            session,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+pub(crate) async fn revoke_session(
+    State(state): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+    Path(session_id): Path<Ulid>,
+) -> Result<StatusCode> {
+    let user_id = Ulid::from_str(&claims.sub)?;
+
+    revoke(&state, &user_id, &session_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ends all sessions except the one the token was issued from.
+/// When the token did not come from a session, every session is ended.
+pub(crate) async fn revoke_other_sessions(
+    State(state): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+) -> Result<StatusCode> {
+    let user_id = Ulid::from_str(&claims.sub)?;
+    let current_session_id = current_session_id(&claims)?;
+
+    revoke_others(&state, &user_id, current_session_id.as_ref()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ends one of the user's sessions, notifying the applications it was used with.
+pub(crate) async fn revoke(state: &ServerState, user_id: &Ulid, session_id: &Ulid) -> Result<()> {
+    let session = state
+        .repositories
+        .sessions
+        .by_id(user_id, session_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    notify_backchannel(state, &session).await?;
+    state.repositories.sessions.delete(&session).await?;
+    tracing::debug!(?session_id, "revoked session");
+
+    Ok(())
+}
+
+/// Ends all of the user's sessions other than `keep`.
+pub(crate) async fn revoke_others(
+    state: &ServerState,
+    user_id: &Ulid,
+    keep: Option<&Ulid>,
+) -> Result<()> {
+    let sessions = state.repositories.sessions.by_user_id(user_id).await?;
+
+    for session in sessions
+        .iter()
+        .filter(|session| Some(&session.session_id) != keep)
+    {
+        notify_backchannel(state, session).await?;
+        state.repositories.sessions.delete(session).await?;
+    }
+    tracing::debug!(?user_id, "revoked other sessions");
+
+    Ok(())
+}
@@ -1,32 +1,127 @@
-use crate::{error::Result, ServerState};
-use axum::{extract::State, Json};
-use lockpad_models::user;
+use std::str::FromStr;
+
+use crate::{
+    audit,
+    client::ClientInfo,
+    error::{Error, Result},
+    token_auth::AccountClaims,
+    ServerState,
+};
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use lockpad_models::{
+    audit_event::{AuditAction, AuditEvent},
+    repository::Repositories,
+    user,
+    user_data::UserData,
+};
 use lockpad_ulid::Ulid;
+use serde::Serialize;
 
-pub(crate) async fn list_users(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
-) -> Result<Json<Vec<user::User>>> {
-    let pagination = lockpad_models::Pagination {
-        last_key: None,
-        count: 10,
-    };
+/// A user's profile, without their password hash.
+#[derive(Debug, Serialize)]
+pub(crate) struct UserResponse {
+    user_id: Ulid,
+    identifier: String,
+    email: Option<String>,
+    admin: bool,
+    disabled: bool,
+}
 
-    let (users, _pagination) = user::User::query(&pg_pool, pagination).await?;
+impl From<user::User> for UserResponse {
+    fn from(user: user::User) -> Self {
+        Self {
+            user_id: user.user_id,
+            identifier: user.identifier,
+            email: user.email,
+            admin: user.admin,
+            disabled: user.disabled,
+        }
+    }
+}
 
-    tracing::debug!(?users, "query result");
+/// Returns the profile of the user the token was issued to.
+pub(crate) async fn me(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
+) -> Result<Json<UserResponse>> {
+    let user_id = Ulid::from_str(&claims.sub)?;
 
-    Ok(Json(users))
+    let user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(Json(user.into()))
 }
 
+/// Returns a user's profile.
+/// Users can only see their own profile, admins can see everyone's.
 pub(crate) async fn get_user(
-    State(ServerState { pg_pool, .. }): State<ServerState>,
+    State(ServerState { repositories, .. }): State<ServerState>,
+    AccountClaims(claims): AccountClaims,
     user_id: axum::extract::Path<Ulid>,
-) -> Result<Json<user::User>> {
+) -> Result<Json<UserResponse>> {
     tracing::debug!(?user_id, "getting user");
 
-    let user = user::User::by_id(&pg_pool, &user_id.0).await?;
+    let caller_id = Ulid::from_str(&claims.sub)?;
+    if caller_id != user_id.0 {
+        let caller = repositories.users.by_id(&caller_id).await?;
+        if !caller.is_some_and(|caller| caller.admin && !caller.disabled) {
+            // Don't reveal whether the user exists.
+            return Err(Error::NotFound);
+        }
+    }
+
+    let user = repositories.users.by_id(&user_id.0).await?;
     match user {
-        Some(user) => Ok(Json(user)),
-        None => Err(crate::error::Error::NotFound),
+        Some(user) => Ok(Json(user.into())),
+        None => Err(Error::NotFound),
     }
 }
+
+/// Downloads everything stored about the user the token was issued to.
+pub(crate) async fn export_me(
+    State(ServerState { repositories, .. }): State<ServerState>,
+    client: ClientInfo,
+    AccountClaims(claims): AccountClaims,
+) -> Result<Response> {
+    let user_id = Ulid::from_str(&claims.sub)?;
+
+    let user = repositories
+        .users
+        .by_id(&user_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    export(&repositories, &client, user_id, user).await
+}
+
+/// Collects the user's data into a JSON download and records who exported it in the audit log.
+pub(crate) async fn export(
+    repositories: &Repositories,
+    client: &ClientInfo,
+    actor_id: Ulid,
+    user: user::User,
+) -> Result<Response> {
+    let user_id = user.user_id;
+    let data = UserData::collect(repositories, user).await?;
+    audit::record(
+        repositories,
+        client,
+        AuditEvent::builder()
+            .action(AuditAction::UserExport)
+            .actor_id(Some(actor_id))
+            .target_id(Some(user_id)),
+    )
+    .await?;
+    tracing::debug!(?user_id, ?actor_id, "exported user data");
+
+    let disposition = format!("attachment; filename=\"lockpad-{user_id}.json\"");
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(data)).into_response())
+}
@@ -1,41 +1,105 @@
+use api_key_auth::ApiKeyUsage;
 use axum::{
     extract::FromRef,
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
-use lockpad_auth::PublicKey;
-use std::net::SocketAddr;
+use lockout::{LockoutPolicy, LockoutStore, LoginThrottle, MemoryLockoutStore};
+use lockpad_auth::{ApiKeyCache, Audience, PublicKey};
+use lockpad_models::repository::Repositories;
+use password::{PasswordHashing, PasswordPolicy};
+use rate_limit::{
+    MemoryRateLimitStore, RateLimitLayer, RateLimitPolicy, RateLimitStore, RateLimiter,
+};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::net::TcpListener;
 
+pub mod api_key_auth;
+pub mod audit;
+pub mod client;
 pub mod error;
 pub mod handlers;
+pub mod lockout;
+pub mod pagination;
+pub mod password;
+pub mod rate_limit;
+pub mod session;
+mod token_auth;
+mod totp;
 pub mod validation;
 
 use error::Result;
 use handlers::{
-    auth::{authorize, authorize_json, register},
-    pages::{disabled_register_screen, login_screen, register_screen, root},
-    user::{get_user, list_users},
+    auth::{authorize, authorize_json, register, register_json},
+    logout::{logout, sign_out_everywhere},
+    pages::{
+        account::{
+            account_screen, add_mfa_factor_form, change_email_form, change_password_form,
+            create_api_key_form, create_application_form, delete_account_form,
+            delete_mfa_factor_form, export_account, revoke_api_key_form, revoke_grant_form,
+        },
+        admin::{
+            dashboard_api_keys, dashboard_applications, dashboard_disable_user,
+            dashboard_enable_user, dashboard_signing_key, dashboard_user, dashboard_users,
+        },
+        consent::consent,
+        disabled_register_screen, login_screen, register_screen, root,
+        sessions::{revoke_other_sessions_form, revoke_session_form, sessions_screen},
+    },
+    user::{get_user, me},
 };
 
 pub struct Server {
     addr: SocketAddr,
 
-    pg_pool: sqlx::pool::Pool<sqlx::Postgres>,
+    /// Where users, applications, api keys, sessions and grants are stored.
+    repositories: Repositories,
 
     /// The secret used to sign the JWT tokens.
     jwt_secret: Vec<u8>,
     /// The public key used to verify the JWT tokens.
     jwt_public: Vec<u8>,
+    /// Identifies this server in the tokens it issues to applications.
+    issuer: String,
+    /// Whether to use the `X-Forwarded-For` header to determine the client's address.
+    trust_forwarded_for: bool,
+    /// How failed logins are throttled
+    lockout_policy: LockoutPolicy,
+    /// Where failed logins are counted
+    lockout_store: Arc<dyn LockoutStore>,
+    /// The rules new passwords have to follow
+    password_policy: PasswordPolicy,
+    /// How passwords and api key secrets are hashed
+    password_hashing: PasswordHashing,
+    /// How many requests clients may make
+    rate_limit_policy: RateLimitPolicy,
+    /// Where the rate limiter keeps its counts
+    rate_limit_store: Arc<dyn RateLimitStore>,
+    /// Uses of api keys, written to the database in the background
+    api_key_usage: ApiKeyUsage,
 
     disable_signup: bool,
 }
 
 #[derive(Clone)]
 pub struct ServerState {
-    pub pg_pool: sqlx::pool::Pool<sqlx::Postgres>,
+    pub repositories: Repositories,
     pub encoding_key: jsonwebtoken::EncodingKey,
     pub public_key: PublicKey,
+    pub issuer: String,
+    pub http_client: reqwest::Client,
+    pub trust_forwarded_for: bool,
+    /// Api keys that were verified recently
+    pub api_key_cache: ApiKeyCache,
+    /// Uses of api keys that are yet to be written to the database
+    pub api_key_usage: ApiKeyUsage,
+    /// Failed logins, to slow down guessing passwords
+    pub login_throttle: LoginThrottle,
+    /// The rules new passwords have to follow
+    pub password_policy: PasswordPolicy,
+    /// How passwords and api key secrets are hashed
+    pub password_hashing: PasswordHashing,
 }
 
 impl FromRef<ServerState> for PublicKey {
@@ -44,29 +108,160 @@ impl FromRef<ServerState> for PublicKey {
     }
 }
 
+/// Lockpad's own api only accepts tokens that weren't issued to an application.
+impl FromRef<ServerState> for Audience {
+    fn from_ref(_state: &ServerState) -> Self {
+        Audience::lockpad()
+    }
+}
+
 impl Server {
     pub fn builder() -> Builder {
         Builder::default()
     }
 
     pub async fn run(self) -> Result<()> {
+        let addr = self.addr;
+        tokio::spawn(
+            self.api_key_usage
+                .clone()
+                .flush_periodically(self.repositories.clone()),
+        );
+        let app = self.router()?;
+
+        tracing::info!("Listening on {0}", addr);
+        let listener = TcpListener::bind(&addr).await?;
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// The routes of the server, sharing the state built from its configuration.
+    pub(crate) fn router(self) -> Result<Router> {
         let cors = tower_http::cors::CorsLayer::permissive();
+        let rate_limit = RateLimitLayer::new(RateLimiter::new(
+            self.rate_limit_policy,
+            self.rate_limit_store,
+            self.repositories.applications.clone(),
+            self.trust_forwarded_for,
+        ));
 
         let encoding_key = jsonwebtoken::EncodingKey::from_rsa_pem(&self.jwt_secret)?;
         let public_key = PublicKey::new(self.jwt_public)?;
         let state = ServerState {
-            pg_pool: self.pg_pool,
+            repositories: self.repositories,
             encoding_key,
             public_key,
+            issuer: self.issuer,
+            http_client: reqwest::Client::new(),
+            trust_forwarded_for: self.trust_forwarded_for,
+            api_key_cache: ApiKeyCache::new(api_key_auth::API_KEY_CACHE_TTL),
+            api_key_usage: self.api_key_usage,
+            login_throttle: LoginThrottle::new(self.lockout_policy, self.lockout_store),
+            password_policy: self.password_policy,
+            password_hashing: self.password_hashing,
         };
 
+        // Routes meant for services calling with an api key instead of a token
+        let api_key_routes = Router::new()
+            .route("/api/key", get(handlers::api_key::current_api_key))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api_key_auth::require_api_key,
+            ));
+
         let mut app = Router::new()
             .route("/", get(root))
             .route("/login", get(login_screen))
             .route("/forms/authorize", post(authorize))
+            .route("/forms/consent", post(consent))
             .route("/api/authorize", post(authorize_json))
-            .route("/users", get(list_users))
+            .route("/api/introspect", post(handlers::api_key::introspect))
+            .route("/logout", get(logout))
+            .route(
+                "/me/sessions",
+                get(handlers::session::list_sessions).delete(sign_out_everywhere),
+            )
+            .route(
+                "/me/sessions/revoke-others",
+                post(handlers::session::revoke_other_sessions),
+            )
+            .route(
+                "/me/sessions/:session_id",
+                delete(handlers::session::revoke_session),
+            )
+            .route("/account", get(account_screen))
+            .route("/account/password", post(change_password_form))
+            .route("/account/email", post(change_email_form))
+            .route("/account/api-keys", post(create_api_key_form))
+            .route(
+                "/account/api-keys/:api_key_id/revoke",
+                post(revoke_api_key_form),
+            )
+            .route("/account/applications", post(create_application_form))
+            .route("/account/mfa", post(add_mfa_factor_form))
+            .route(
+                "/account/mfa/:mfa_factor_id/delete",
+                post(delete_mfa_factor_form),
+            )
+            .route("/account/delete", post(delete_account_form))
+            .route("/account/export", get(export_account))
+            .route(
+                "/account/grants/:application_id/revoke",
+                post(revoke_grant_form),
+            )
+            .route("/account/sessions", get(sessions_screen))
+            .route(
+                "/account/sessions/revoke-others",
+                post(revoke_other_sessions_form),
+            )
+            .route(
+                "/account/sessions/:session_id/revoke",
+                post(revoke_session_form),
+            )
+            .route("/dashboard", get(dashboard_users))
+            .route("/dashboard/users/:user_id", get(dashboard_user))
+            .route(
+                "/dashboard/users/:user_id/disable",
+                post(dashboard_disable_user),
+            )
+            .route(
+                "/dashboard/users/:user_id/enable",
+                post(dashboard_enable_user),
+            )
+            .route("/dashboard/applications", get(dashboard_applications))
+            .route("/dashboard/api-keys", get(dashboard_api_keys))
+            .route("/dashboard/signing-key", get(dashboard_signing_key))
+            .route("/me", get(me))
+            .route("/me/export", get(handlers::user::export_me))
             .route("/users/:user_id", get(get_user))
+            .route("/admin/users", get(handlers::admin::list_users))
+            .route(
+                "/admin/users/:user_id",
+                get(handlers::admin::get_user)
+                    .patch(handlers::admin::update_user)
+                    .delete(handlers::admin::delete_user),
+            )
+            .route(
+                "/admin/users/:user_id/export",
+                get(handlers::admin::export_user),
+            )
+            .route(
+                "/admin/users/:user_id/password-reset",
+                post(handlers::admin::reset_password),
+            )
+            .route(
+                "/admin/users/:user_id/unlock",
+                post(handlers::admin::unlock_user),
+            )
+            .route(
+                "/admin/audit-events",
+                get(handlers::admin::list_audit_events),
+            )
             .route(
                 "/applications",
                 get(handlers::application::list_applications)
@@ -74,37 +269,54 @@ impl Server {
             )
             .route(
                 "/applications/:application_id",
-                get(handlers::application::get_application),
+                get(handlers::application::get_application)
+                    .put(handlers::application::replace_application)
+                    .patch(handlers::application::update_application)
+                    .delete(handlers::application::delete_application),
             )
             .route(
                 "/api-keys",
                 get(handlers::api_key::list_api_keys).post(handlers::api_key::create_api_key),
             )
-            .route("/api-keys/:api_key_id", get(handlers::api_key::get_api_key))
-            .route("/.well-known/jwks.json", get(handlers::jwks::jwks))
-            .route("/health", get(handlers::health::health));
+            .route(
+                "/api-keys/:api_key_id",
+                get(handlers::api_key::get_api_key).delete(handlers::api_key::revoke_api_key),
+            )
+            .merge(api_key_routes);
         if !self.disable_signup {
             app = app
                 .route("/forms/register", post(register))
+                .route("/api/register", post(register_json))
                 .route("/register", get(register_screen));
         } else {
             app = app.route("/register", get(disabled_register_screen));
         }
-        let app = app.with_state(state).layer(cors);
-
-        tracing::info!("Listening on {0}", self.addr);
-        let listener = TcpListener::bind(&self.addr).await?;
-        axum::serve(listener, app).await?;
+        // Health checks and key fetches come from infrastructure and other services, they aren't limited.
+        let unlimited = Router::new()
+            .route("/.well-known/jwks.json", get(handlers::jwks::jwks))
+            .route("/health", get(handlers::health::health));
 
-        Ok(())
+        Ok(app
+            .layer(rate_limit)
+            .merge(unlimited)
+            .with_state(state)
+            .layer(cors))
     }
 }
 
 pub struct Builder {
     addr: Option<SocketAddr>,
-    pg_pool: Option<sqlx::pool::Pool<sqlx::Postgres>>,
+    repositories: Option<Repositories>,
     jwt_secret: Option<Vec<u8>>,
     jwt_public: Option<Vec<u8>>,
+    issuer: Option<String>,
+    trust_forwarded_for: Option<bool>,
+    lockout_policy: Option<LockoutPolicy>,
+    lockout_store: Option<Arc<dyn LockoutStore>>,
+    password_policy: Option<PasswordPolicy>,
+    password_hashing: Option<PasswordHashing>,
+    rate_limit_policy: Option<RateLimitPolicy>,
+    rate_limit_store: Option<Arc<dyn RateLimitStore>>,
     disable_signup: Option<bool>,
 }
 
@@ -112,9 +324,17 @@ impl Builder {
     pub fn new() -> Self {
         Self {
             addr: None,
-            pg_pool: None,
+            repositories: None,
             jwt_secret: None,
             jwt_public: None,
+            issuer: None,
+            trust_forwarded_for: None,
+            lockout_policy: None,
+            lockout_store: None,
+            password_policy: None,
+            password_hashing: None,
+            rate_limit_policy: None,
+            rate_limit_store: None,
             disable_signup: None,
         }
     }
@@ -124,8 +344,21 @@ impl Builder {
         self
     }
 
+    /// Store everything in Postgres
     pub fn pg_pool(mut self, pg_pool: sqlx::pool::Pool<sqlx::Postgres>) -> Self {
-        self.pg_pool = Some(pg_pool);
+        self.repositories = Some(Repositories::postgres(pg_pool));
+        self
+    }
+
+    /// Store everything in memory, for tests and demos. Nothing survives a restart.
+    pub fn in_memory(mut self) -> Self {
+        self.repositories = Some(Repositories::memory());
+        self
+    }
+
+    /// Store everything in custom repositories
+    pub fn repositories(mut self, repositories: Repositories) -> Self {
+        self.repositories = Some(repositories);
         self
     }
 
@@ -139,6 +372,50 @@ impl Builder {
         self
     }
 
+    pub fn issuer(mut self, issuer: String) -> Self {
+        self.issuer = Some(issuer);
+        self
+    }
+
+    pub fn trust_forwarded_for(mut self, trust_forwarded_for: bool) -> Self {
+        self.trust_forwarded_for = Some(trust_forwarded_for);
+        self
+    }
+
+    pub fn lockout_policy(mut self, lockout_policy: LockoutPolicy) -> Self {
+        self.lockout_policy = Some(lockout_policy);
+        self
+    }
+
+    /// Where failed logins are counted, in memory by default.
+    /// Servers sharing a database can share the counts by using a [`lockout::PostgresLockoutStore`].
+    pub fn lockout_store(mut self, lockout_store: Arc<dyn LockoutStore>) -> Self {
+        self.lockout_store = Some(lockout_store);
+        self
+    }
+
+    pub fn password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = Some(password_policy);
+        self
+    }
+
+    pub fn password_hashing(mut self, password_hashing: PasswordHashing) -> Self {
+        self.password_hashing = Some(password_hashing);
+        self
+    }
+
+    pub fn rate_limit_policy(mut self, rate_limit_policy: RateLimitPolicy) -> Self {
+        self.rate_limit_policy = Some(rate_limit_policy);
+        self
+    }
+
+    /// Where the rate limiter keeps its counts, in memory by default.
+    /// Servers sharing a database can share the counts by using a [`rate_limit::PostgresRateLimitStore`].
+    pub fn rate_limit_store(mut self, rate_limit_store: Arc<dyn RateLimitStore>) -> Self {
+        self.rate_limit_store = Some(rate_limit_store);
+        self
+    }
+
     pub fn disable_signup(mut self, disable_signup: bool) -> Self {
         self.disable_signup = Some(disable_signup);
         self
@@ -146,16 +423,37 @@ impl Builder {
 
     pub fn build(self) -> Result<Server> {
         let addr = self.addr.ok_or(error::Error::ServerBuilder)?;
-        let pg_pool = self.pg_pool.ok_or(error::Error::ServerBuilder)?;
+        let repositories = self.repositories.ok_or(error::Error::ServerBuilder)?;
         let jwt_secret = self.jwt_secret.ok_or(error::Error::ServerBuilder)?;
         let jwt_public = self.jwt_public.ok_or(error::Error::ServerBuilder)?;
+        let issuer = self.issuer.unwrap_or_else(|| "lockpad".to_string());
+        let trust_forwarded_for = self.trust_forwarded_for.unwrap_or(false);
+        let lockout_policy = self.lockout_policy.unwrap_or_default();
+        let lockout_store = self
+            .lockout_store
+            .unwrap_or_else(|| Arc::new(MemoryLockoutStore::default()));
+        let password_policy = self.password_policy.unwrap_or_default();
+        let password_hashing = self.password_hashing.unwrap_or_default();
+        let rate_limit_policy = self.rate_limit_policy.unwrap_or_default();
+        let rate_limit_store = self
+            .rate_limit_store
+            .unwrap_or_else(|| Arc::new(MemoryRateLimitStore::default()));
         let disable_signup = self.disable_signup.unwrap_or(false);
 
         Ok(Server {
             addr,
-            pg_pool,
+            repositories,
             jwt_secret,
             jwt_public,
+            issuer,
+            trust_forwarded_for,
+            lockout_policy,
+            lockout_store,
+            password_policy,
+            password_hashing,
+            rate_limit_policy,
+            rate_limit_store,
+            api_key_usage: ApiKeyUsage::default(),
             disable_signup,
         })
     }
@@ -165,10 +463,135 @@ impl Default for Builder {
     fn default() -> Self {
         Self {
             addr: Some(SocketAddr::from(([0, 0, 0, 0], 5000))),
-            pg_pool: None,
+            repositories: None,
             jwt_secret: None,
             jwt_public: None,
+            issuer: None,
+            trust_forwarded_for: None,
+            lockout_policy: None,
+            lockout_store: None,
+            password_policy: None,
+            password_hashing: None,
+            rate_limit_policy: None,
+            rate_limit_store: None,
             disable_signup: None,
         }
     }
 }
+
+/// Helpers for tests that send requests through the server's routes.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    /// A password the default policy accepts.
+    pub(crate) const PASSWORD: &str = "correct horse battery staple";
+
+    /// The routes of a server keeping everything in `repositories`.
+    pub(crate) fn app(repositories: Repositories) -> Router {
+        Server::builder()
+            .repositories(repositories)
+            .jwt_secret(include_bytes!("../testdata/secret-rsa.pem").to_vec())
+            .jwt_public(include_bytes!("../testdata/public-rsa.pem").to_vec())
+            .build()
+            .and_then(Server::router)
+            .expect("test server builds")
+    }
+
+    /// Sends the request, returning the response's status, headers and body.
+    pub(crate) async fn send(
+        app: &Router,
+        request: Request<Body>,
+    ) -> (StatusCode, HeaderMap, String) {
+        let response = app.clone().oneshot(request).await.expect("infallible");
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .expect("body is readable");
+
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    /// A JSON request with an optional bearer token.
+    pub(crate) fn json(
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: serde_json::Value,
+    ) -> Request<Body> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        request
+            .body(Body::from(body.to_string()))
+            .expect("request is valid")
+    }
+
+    /// Signs the claims with the test server's key.
+    pub(crate) async fn encode(claims: lockpad_auth::Claims) -> String {
+        let key =
+            jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("../testdata/secret-rsa.pem"))
+                .expect("test key is valid");
+
+        claims.encode(&key).await.expect("claims encode")
+    }
+
+    /// Registers a user with [`PASSWORD`] and returns a token for them.
+    pub(crate) async fn register(app: &Router, username: &str) -> String {
+        let credentials = serde_json::json!({ "username": username, "password": PASSWORD });
+        let (status, _, body) = send(
+            app,
+            json("POST", "/api/register", None, credentials.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+
+        let (status, _, body) = send(app, json("POST", "/api/authorize", None, credentials)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let response: serde_json::Value = serde_json::from_str(&body).expect("token response");
+
+        response["token"].as_str().expect("token").to_string()
+    }
+
+    /// A form submission, sent with the session cookie when there is one.
+    pub(crate) fn form(uri: &str, cookie: Option<&str>, body: &str) -> Request<Body> {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+
+        request
+            .body(Body::from(body.to_string()))
+            .expect("request is valid")
+    }
+
+    /// Logs in through the login form, returning the session cookie to send along with further requests.
+    pub(crate) async fn login(app: &Router, username: &str, password: &str) -> String {
+        let body = serde_urlencoded::to_string([("username", username), ("password", password)])
+            .expect("form encodes");
+        let (status, headers, body) = send(app, form("/forms/authorize", None, &body)).await;
+        assert_eq!(status, StatusCode::FOUND, "{body}");
+        let cookie = headers[header::SET_COOKIE]
+            .to_str()
+            .expect("cookie is ascii");
+
+        cookie.split(';').next().expect("cookie").to_string()
+    }
+}
@@ -0,0 +1,317 @@
+use crate::error::Result;
+use lockpad_auth::PurgeSchedule;
+use lockpad_models::login_failure;
+pub use lockpad_models::login_failure::Failures;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// How failed logins slow down and eventually lock out further attempts.
+#[derive(Clone, Debug)]
+pub struct LockoutPolicy {
+    /// Failed logins to one identifier before it is locked out
+    pub max_failures: u32,
+    /// Failed logins from one address before it is locked out
+    pub max_failures_per_ip: u32,
+    /// The wait after the first failure, doubling with every further failure
+    pub backoff: Duration,
+    /// How long a lockout lasts. Failures are forgotten once this long has passed since the last one
+    pub lockout: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            max_failures_per_ip: 20,
+            backoff: Duration::from_secs(1),
+            lockout: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// What failed logins are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LockoutKey {
+    Identifier(String),
+    Ip(IpAddr),
+}
+
+impl fmt::Display for LockoutKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Identifier(identifier) => write!(f, "identifier:{identifier}"),
+            Self::Ip(ip) => write!(f, "ip:{ip}"),
+        }
+    }
+}
+
+/// Where the failed logins are counted.
+#[async_trait::async_trait]
+pub trait LockoutStore: Send + Sync {
+    /// The failures counted against the key.
+    async fn failures(&self, key: &str) -> Result<Option<Failures>>;
+    /// Counts a failure against the key, returning the new count.
+    /// The count starts over when the last failure is more than `forget_after` ago.
+    async fn record_failure(&self, key: &str, forget_after: Duration) -> Result<u32>;
+    /// Forgets the failures counted against the key.
+    async fn clear(&self, key: &str) -> Result<()>;
+}
+
+/// Keeps the counts in memory, each server instance counts failures on its own and they are reset when it restarts.
+#[derive(Default)]
+pub struct MemoryLockoutStore {
+    /// The number of failures and the time of the last one
+    failures: Mutex<HashMap<String, (u32, Instant)>>,
+    purge: PurgeSchedule,
+}
+
+impl MemoryLockoutStore {
+    fn failures(&self) -> MutexGuard<'_, HashMap<String, (u32, Instant)>> {
+        self.failures.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[async_trait::async_trait]
+impl LockoutStore for MemoryLockoutStore {
+    async fn failures(&self, key: &str) -> Result<Option<Failures>> {
+        Ok(self
+            .failures()
+            .get(key)
+            .map(|(count, last_failure)| Failures {
+                count: *count,
+                since_last: last_failure.elapsed(),
+            }))
+    }
+
+    async fn record_failure(&self, key: &str, forget_after: Duration) -> Result<u32> {
+        let now = Instant::now();
+        let mut failures = self.failures();
+        if self.purge.due() {
+            failures
+                .retain(|_, (_, last_failure)| now.duration_since(*last_failure) < forget_after);
+        }
+
+        let (count, last_failure) = failures.entry(key.to_string()).or_insert((0, now));
+        // Failures that weren't purged yet are still forgotten once they are old enough.
+        if now.duration_since(*last_failure) >= forget_after {
+            *count = 0;
+        }
+        *count = count.saturating_add(1);
+        *last_failure = now;
+
+        Ok(*count)
+    }
+
+    async fn clear(&self, key: &str) -> Result<()> {
+        self.failures().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps the counts in Postgres, so every server using the database shares them.
+pub struct PostgresLockoutStore {
+    pool: sqlx::pool::Pool<sqlx::Postgres>,
+    purge: PurgeSchedule,
+}
+
+impl PostgresLockoutStore {
+    pub fn new(pool: sqlx::pool::Pool<sqlx::Postgres>) -> Self {
+        Self {
+            pool,
+            purge: PurgeSchedule::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LockoutStore for PostgresLockoutStore {
+    async fn failures(&self, key: &str) -> Result<Option<Failures>> {
+        Ok(login_failure::failures(&self.pool, key).await?)
+    }
+
+    async fn record_failure(&self, key: &str, forget_after: Duration) -> Result<u32> {
+        if self.purge.due() {
+            let purged = login_failure::purge(&self.pool, forget_after).await?;
+            tracing::debug!(purged, "purged old login failures");
+        }
+
+        Ok(login_failure::record_failure(&self.pool, key, forget_after).await?)
+    }
+
+    async fn clear(&self, key: &str) -> Result<()> {
+        Ok(login_failure::clear(&self.pool, key).await?)
+    }
+}
+
+/// Counts failed logins per identifier and per address, making clients wait longer after each one.
+/// Once a threshold is reached further attempts are refused until the lockout ends or an admin unlocks the identifier.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    policy: LockoutPolicy,
+    store: Arc<dyn LockoutStore>,
+}
+
+impl LoginThrottle {
+    pub fn new(policy: LockoutPolicy, store: Arc<dyn LockoutStore>) -> Self {
+        Self { policy, store }
+    }
+
+    fn keys(identifier: &str, ip: Option<IpAddr>) -> impl Iterator<Item = LockoutKey> {
+        std::iter::once(LockoutKey::Identifier(identifier.to_string()))
+            .chain(ip.map(LockoutKey::Ip))
+    }
+
+    fn max_failures(&self, key: &LockoutKey) -> u32 {
+        match key {
+            LockoutKey::Identifier(_) => self.policy.max_failures,
+            LockoutKey::Ip(_) => self.policy.max_failures_per_ip,
+        }
+    }
+
+    /// How long after its last failure the key is refused.
+    fn blocked_for(&self, key: &LockoutKey, count: u32) -> Duration {
+        if count >= self.max_failures(key) {
+            return self.policy.lockout;
+        }
+
+        let doublings = count.saturating_sub(1).min(31);
+        self.policy
+            .backoff
+            .saturating_mul(1 << doublings)
+            .min(self.policy.lockout)
+    }
+
+    /// How long the client has to wait before trying the identifier again, `None` when it may try now.
+    pub async fn retry_after(
+        &self,
+        identifier: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<Duration>> {
+        let mut wait = None;
+        for key in Self::keys(identifier, ip) {
+            let Some(failures) = self.store.failures(&key.to_string()).await? else {
+                continue;
+            };
+            let blocked_for = self.blocked_for(&key, failures.count);
+            let remaining = blocked_for.saturating_sub(failures.since_last);
+            if !remaining.is_zero() {
+                wait = wait.max(Some(remaining));
+            }
+        }
+
+        Ok(wait)
+    }
+
+    /// Counts a failed login, returning the keys this failure locked out.
+    pub async fn record_failure(
+        &self,
+        identifier: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Vec<LockoutKey>> {
+        let mut locked = Vec::new();
+        for key in Self::keys(identifier, ip) {
+            let count = self
+                .store
+                .record_failure(&key.to_string(), self.policy.lockout)
+                .await?;
+
+            if count == self.max_failures(&key) {
+                locked.push(key);
+            }
+        }
+
+        Ok(locked)
+    }
+
+    /// Forgets the failures of an identifier after a successful login.
+    /// The address keeps its count, so logging in to one account doesn't allow guessing at others.
+    pub async fn record_success(&self, identifier: &str) -> Result<()> {
+        self.unlock(identifier).await
+    }
+
+    /// Lifts a lockout of the identifier.
+    pub async fn unlock(&self, identifier: &str) -> Result<()> {
+        let key = LockoutKey::Identifier(identifier.to_string());
+        self.store.clear(&key.to_string()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_failures: 3,
+            max_failures_per_ip: 4,
+            backoff: Duration::from_secs(1),
+            lockout: Duration::from_secs(60),
+        }
+    }
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(policy(), Arc::new(MemoryLockoutStore::default()))
+    }
+
+    #[tokio::test]
+    async fn backs_off_then_locks_out() -> Result<()> {
+        let throttle = throttle();
+        assert_eq!(throttle.retry_after("alice", None).await?, None);
+
+        assert!(throttle.record_failure("alice", None).await?.is_empty());
+        let wait = throttle.retry_after("alice", None).await?.unwrap();
+        assert!(wait <= Duration::from_secs(1));
+        assert!(throttle.record_failure("alice", None).await?.is_empty());
+        assert!(throttle.retry_after("alice", None).await?.unwrap() > Duration::from_secs(1));
+
+        let locked = throttle.record_failure("alice", None).await?;
+        assert_eq!(locked, vec![LockoutKey::Identifier("alice".to_string())]);
+        assert!(throttle.retry_after("alice", None).await?.unwrap() > Duration::from_secs(30));
+        assert_eq!(throttle.retry_after("bob", None).await?, None);
+
+        throttle.unlock("alice").await?;
+        assert_eq!(throttle.retry_after("alice", None).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn addresses_are_counted_across_identifiers() -> Result<()> {
+        let throttle = throttle();
+        let ip = Some(IpAddr::from([192, 0, 2, 1]));
+
+        for identifier in ["a", "b", "c"] {
+            throttle.record_failure(identifier, ip).await?;
+        }
+        let locked = throttle.record_failure("d", ip).await?;
+        assert_eq!(locked, vec![LockoutKey::Ip(IpAddr::from([192, 0, 2, 1]))]);
+
+        // a successful login doesn't lift the address' lockout
+        throttle.record_success("e").await?;
+        assert!(throttle.retry_after("e", ip).await?.unwrap() > Duration::from_secs(30));
+        assert_eq!(throttle.retry_after("e", None).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failures_are_forgotten_after_the_lockout() -> Result<()> {
+        let policy = LockoutPolicy {
+            max_failures: 2,
+            lockout: Duration::from_millis(20),
+            ..policy()
+        };
+        let throttle = LoginThrottle::new(policy, Arc::new(MemoryLockoutStore::default()));
+
+        assert!(throttle.record_failure("alice", None).await?.is_empty());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(throttle.record_failure("alice", None).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use axum::{
+    http::{header, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use lockpad_models::Pagination;
+use lockpad_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+
+/// The page size used when the request doesn't ask for one.
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 25;
+
+/// The largest page a request can ask for.
+pub(crate) const MAX_PAGE_SIZE: usize = 100;
+
+/// Query parameters selecting a page of a listing.
+/// Listings are ordered by id, `after` is the `next` cursor of the previous page.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct PageQuery {
+    pub after: Option<Ulid>,
+    pub limit: Option<usize>,
+}
+
+impl PageQuery {
+    pub(crate) fn pagination(&self) -> Pagination {
+        Pagination {
+            last_key: self.after,
+            count: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        }
+    }
+}
+
+/// A page of a listing along with the cursor of the next one.
+/// The next page is also linked in a `Link` header.
+#[derive(Debug, Serialize)]
+pub(crate) struct Page<T> {
+    items: Vec<T>,
+    next: Option<Ulid>,
+    #[serde(skip)]
+    link: Option<HeaderValue>,
+}
+
+impl<T> Page<T> {
+    /// Creates the page from a query's results, `uri` is the request's uri the next page's link is based on.
+    pub(crate) fn new(uri: &Uri, items: Vec<T>, pagination: Pagination) -> Self {
+        let link = pagination.last_key.and_then(|next| {
+            let value = format!(
+                "<{}>; rel=\"next\"",
+                next_uri(uri, &next, pagination.count.max(1))
+            );
+            HeaderValue::from_str(&value).ok()
+        });
+
+        Self {
+            items,
+            next: pagination.last_key,
+            link,
+        }
+    }
+
+    /// Converts the items, for example into response types.
+    pub(crate) fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next: self.next,
+            link: self.link,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        match self.link.clone() {
+            Some(link) => ([(header::LINK, link)], Json(self)).into_response(),
+            None => Json(self).into_response(),
+        }
+    }
+}
+
+/// The request's uri with its other query parameters kept and the cursor moved to `next`
+pub(crate) fn next_uri(uri: &Uri, next: &Ulid, limit: usize) -> String {
+    let mut params: Vec<(String, String)> = uri
+        .query()
+        .and_then(|query| serde_urlencoded::from_str(query).ok())
+        .unwrap_or_default();
+    params.retain(|(name, _)| name != "after" && name != "limit");
+    params.push(("after".to_string(), next.to_string()));
+    params.push(("limit".to_string(), limit.to_string()));
+
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    format!("{}?{query}", uri.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_link_keeps_other_parameters() {
+        let next = Ulid::generate();
+        let uri: Uri = "/admin/users?q=al%20ice&after=01GVF4BCM32TTM19YBFB2ZNE4R&limit=2"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            next_uri(&uri, &next, 2),
+            format!("/admin/users?q=al+ice&after={next}&limit=2")
+        );
+
+        let page = Page::new(
+            &uri,
+            vec![1, 2],
+            Pagination {
+                last_key: Some(next),
+                count: 2,
+            },
+        );
+        assert_eq!(
+            page.link.unwrap(),
+            format!("</admin/users?q=al+ice&after={next}&limit=2>; rel=\"next\"")
+        );
+    }
+
+    #[test]
+    fn limits_are_clamped() {
+        let pagination = PageQuery::default().pagination();
+        assert_eq!(pagination.count, DEFAULT_PAGE_SIZE);
+
+        let query = PageQuery {
+            after: None,
+            limit: Some(10_000),
+        };
+        assert_eq!(query.pagination().count, MAX_PAGE_SIZE);
+    }
+}
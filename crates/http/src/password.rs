@@ -0,0 +1,437 @@
+use argon2::{
+    password_hash::{Output, PasswordHash},
+    Algorithm, Argon2, Params, Version,
+};
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+use validator::{ValidationError, ValidationErrors};
+
+/// Only the start of longer passwords is scored, zxcvbn gets slow on long input.
+const STRENGTH_CHECK_MAX_CHARS: usize = 100;
+
+/// The rules new passwords have to follow.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    /// The fewest characters a password may have
+    pub min_length: usize,
+    /// The lowest zxcvbn score accepted, from 0 which accepts anything to 4 for passwords that are very hard to guess
+    pub min_strength: u8,
+    /// Refuse passwords containing the username
+    pub disallow_username: bool,
+    /// Passwords known from data breaches, which are refused
+    pub breached_passwords: Option<BreachedPasswords>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            min_strength: 2,
+            disallow_username: true,
+            breached_passwords: None,
+        }
+    }
+}
+
+fn policy_error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(message.into());
+    error
+}
+
+impl PasswordPolicy {
+    /// Checks a new password for the user, adding every rule it breaks to the errors of `field`.
+    pub async fn validate(
+        &self,
+        errors: &mut ValidationErrors,
+        field: &'static str,
+        username: &str,
+        password: &str,
+    ) {
+        for error in self.check(username, password).await {
+            errors.add(field, error);
+        }
+    }
+
+    /// Checks a new password for the user, returning every rule it breaks.
+    /// When the breached password corpus can't be read the password is refused, a broken corpus shouldn't let every password through.
+    async fn check(&self, username: &str, password: &str) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            errors.push(policy_error(
+                "length",
+                format!("Use at least {} characters.", self.min_length),
+            ));
+        }
+
+        let username = username.trim().to_lowercase();
+        if self.disallow_username
+            && !username.is_empty()
+            && password.to_lowercase().contains(&username)
+        {
+            errors.push(policy_error(
+                "username",
+                "Don't use your username in your password.".to_string(),
+            ));
+        }
+
+        let scored: String = password.chars().take(STRENGTH_CHECK_MAX_CHARS).collect();
+        let entropy = zxcvbn::zxcvbn(&scored, &[&username]);
+        if u8::from(entropy.score()) < self.min_strength {
+            let mut message = "This password is too easy to guess.".to_string();
+            if let Some(feedback) = entropy.feedback() {
+                if let Some(warning) = feedback.warning() {
+                    message = format!("{message} {warning}");
+                }
+                for suggestion in feedback.suggestions() {
+                    message = format!("{message} {suggestion}");
+                }
+            }
+            errors.push(policy_error("strength", message));
+        }
+
+        if let Some(breached_passwords) = &self.breached_passwords {
+            match breached_passwords.contains(password).await {
+                Ok(false) => {}
+                Ok(true) => errors.push(policy_error(
+                    "breached",
+                    "This password has appeared in a data breach, choose a different one."
+                        .to_string(),
+                )),
+                Err(err) => {
+                    tracing::error!(?err, "failed to check for a breached password");
+                    errors.push(policy_error(
+                        "breached_unavailable",
+                        "The password can't be checked against data breaches right now, try again later."
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+/// How new password hashes are made.
+/// Hashes carry the algorithm and parameters they were made with, so changing these keeps existing hashes working.
+/// Outdated hashes are replaced the next time their user logs in.
+#[derive(Clone, Debug, Default)]
+pub struct PasswordHashing {
+    algorithm: Algorithm,
+    params: Params,
+}
+
+impl PasswordHashing {
+    /// Uses the argon2 variant with the memory cost in KiB, the number of passes over the memory and the number of lanes.
+    pub fn new(
+        algorithm: Algorithm,
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    ) -> Result<Self, argon2::Error> {
+        let params = Params::new(memory_kib, iterations, parallelism, None)?;
+
+        Ok(Self { algorithm, params })
+    }
+
+    pub(crate) fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(self.algorithm, Version::default(), self.params.clone())
+    }
+
+    /// Whether the hash was made with the current algorithm and parameters.
+    pub(crate) fn is_current(&self, hash: &PasswordHash) -> bool {
+        let Ok(params) = Params::try_from(hash) else {
+            return false;
+        };
+
+        hash.algorithm == self.algorithm.ident()
+            && hash.version == Some(Version::default().into())
+            && params.m_cost() == self.params.m_cost()
+            && params.t_cost() == self.params.t_cost()
+            && params.p_cost() == self.params.p_cost()
+    }
+}
+
+/// The kinds of hashes passwords can be verified against.
+/// Lockpad makes argon2 hashes, the others are accepted for users imported from other systems and replaced when they log in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashKind {
+    /// An argon2 PHC string, like `$argon2id$v=19$m=4096,t=3,p=1$<salt>$<hash>`
+    Argon2,
+    /// A bcrypt modular crypt string, like `$2b$12$<salt and hash>`
+    Bcrypt,
+    /// A scrypt PHC string, like `$scrypt$ln=15,r=8,p=1$<salt>$<hash>`
+    Scrypt,
+    /// A PBKDF2 PHC string, like `$pbkdf2-sha256$i=600000,l=32$<salt>$<hash>`
+    Pbkdf2,
+    /// A PBKDF2-SHA256 modular crypt string as passlib makes them, like `$pbkdf2-sha256$29000$<salt>$<hash>`
+    PasslibPbkdf2,
+}
+
+impl HashKind {
+    /// Recognizes the format of a hash, `None` when it isn't one passwords can be verified against.
+    pub fn detect(secret: &str) -> Option<Self> {
+        if secret.starts_with("$2") {
+            return secret
+                .parse::<bcrypt::HashParts>()
+                .is_ok()
+                .then_some(Self::Bcrypt);
+        }
+        if PasslibPbkdf2::parse(secret).is_some() {
+            return Some(Self::PasslibPbkdf2);
+        }
+
+        let hash = PasswordHash::new(secret).ok()?;
+        hash.hash?;
+        match hash.algorithm.as_str() {
+            "argon2id" | "argon2i" | "argon2d" => Some(Self::Argon2),
+            "scrypt" => Some(Self::Scrypt),
+            "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Some(Self::Pbkdf2),
+            _ => None,
+        }
+    }
+}
+
+/// Checks data against a hash of any supported kind, using the parameters stored in the hash.
+pub(crate) fn verify_hash(data: &[u8], secret: &str) -> bool {
+    match HashKind::detect(secret) {
+        None => {
+            tracing::warn!("password hash of an unsupported kind");
+            false
+        }
+        Some(HashKind::Bcrypt) => bcrypt::verify(data, secret).unwrap_or(false),
+        Some(HashKind::PasslibPbkdf2) => {
+            PasslibPbkdf2::parse(secret).is_some_and(|hash| hash.verify(data))
+        }
+        Some(HashKind::Argon2 | HashKind::Scrypt | HashKind::Pbkdf2) => PasswordHash::new(secret)
+            .is_ok_and(|hash| {
+                hash.verify_password(
+                    &[&Argon2::default(), &scrypt::Scrypt, &pbkdf2::Pbkdf2],
+                    data,
+                )
+                .is_ok()
+            }),
+    }
+}
+
+/// A PBKDF2-SHA256 hash in passlib's format.
+/// It resembles a PHC string, but has a bare round count and encodes with `.` in place of `+`.
+struct PasslibPbkdf2 {
+    rounds: u32,
+    salt: Vec<u8>,
+    checksum: Output,
+}
+
+impl PasslibPbkdf2 {
+    fn parse(secret: &str) -> Option<Self> {
+        let mut parts = secret.strip_prefix("$pbkdf2-sha256$")?.split('$');
+        let rounds = parts.next()?.parse().ok().filter(|rounds| *rounds > 0)?;
+        let salt = Self::decode(parts.next()?)?;
+        let checksum = Output::new(&Self::decode(parts.next()?)?).ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            rounds,
+            salt,
+            checksum,
+        })
+    }
+
+    fn decode(value: &str) -> Option<Vec<u8>> {
+        base64::engine::general_purpose::STANDARD_NO_PAD
+            .decode(value.replace('.', "+"))
+            .ok()
+    }
+
+    fn verify(&self, data: &[u8]) -> bool {
+        let mut computed = vec![0u8; self.checksum.len()];
+        pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(data, &self.salt, self.rounds, &mut computed);
+
+        // Comparing outputs takes constant time.
+        Output::new(&computed).is_ok_and(|computed| computed == self.checksum)
+    }
+}
+
+/// An offline copy of the Pwned Passwords corpus, split into range files like the ones the range api serves.
+/// Each file is named after the first five characters of the SHA-1 hashes it covers, such as `5BAA6.txt`,
+/// and lists the rest of every hash along with how often it was seen, as `<suffix>:<count>`.
+#[derive(Clone, Debug)]
+pub struct BreachedPasswords {
+    directory: PathBuf,
+}
+
+impl BreachedPasswords {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Whether the password appears in the corpus.
+    pub async fn contains(&self, password: &str) -> std::io::Result<bool> {
+        let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        let path = self.directory.join(format!("{prefix}.txt"));
+        let range = tokio::fs::read_to_string(path).await?;
+
+        // Ranges can be padded with entries seen zero times.
+        let found = range
+            .lines()
+            .filter_map(|line| line.trim().split_once(':'))
+            .any(|(candidate, count)| {
+                candidate.eq_ignore_ascii_case(suffix) && count.trim() != "0"
+            });
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(errors: &[ValidationError]) -> Vec<&str> {
+        errors.iter().map(|error| error.code.as_ref()).collect()
+    }
+
+    #[tokio::test]
+    async fn rules() {
+        let policy = PasswordPolicy::default();
+
+        assert!(policy
+            .check("alice", "correct horse battery staple")
+            .await
+            .is_empty());
+        assert_eq!(
+            codes(&policy.check("alice", "abc").await),
+            ["length", "strength"]
+        );
+        assert!(codes(&policy.check("alice", "Alice-in-chains-1971").await).contains(&"username"));
+        assert_eq!(
+            codes(&policy.check("alice", "password123").await),
+            ["strength"]
+        );
+    }
+
+    #[test]
+    fn outdated_hashes() {
+        use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(b"password", &salt).unwrap();
+        assert!(PasswordHashing::default().is_current(&hash));
+
+        let stronger = PasswordHashing::new(Algorithm::Argon2id, 19 * 1024, 2, 1).unwrap();
+        assert!(!stronger.is_current(&hash));
+        let argon2i = PasswordHashing::new(Algorithm::Argon2i, 4096, 3, 1).unwrap();
+        assert!(!argon2i.is_current(&hash));
+
+        let hash = stronger.argon2().hash_password(b"password", &salt).unwrap();
+        assert!(stronger.is_current(&hash));
+    }
+
+    #[test]
+    fn legacy_hashes() {
+        use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+
+        let salt = SaltString::generate(&mut OsRng);
+        let hashes = [
+            (
+                HashKind::Argon2,
+                Argon2::default()
+                    .hash_password(b"U*U", &salt)
+                    .unwrap()
+                    .to_string(),
+            ),
+            (
+                HashKind::Bcrypt,
+                "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW".to_string(),
+            ),
+            (
+                HashKind::Scrypt,
+                scrypt::Scrypt
+                    .hash_password_customized(
+                        b"U*U",
+                        None,
+                        None,
+                        scrypt::Params::new(4, 8, 1).unwrap(),
+                        &salt,
+                    )
+                    .unwrap()
+                    .to_string(),
+            ),
+            (
+                HashKind::Pbkdf2,
+                pbkdf2::Pbkdf2
+                    .hash_password(b"U*U", &salt)
+                    .unwrap()
+                    .to_string(),
+            ),
+        ];
+        for (kind, hash) in hashes {
+            assert_eq!(HashKind::detect(&hash), Some(kind), "{hash}");
+            assert!(verify_hash(b"U*U", &hash), "{hash}");
+            assert!(!verify_hash(b"U*V", &hash), "{hash}");
+        }
+
+        let passlib = "$pbkdf2-sha256$1000$MDEyMzQ1Njc4OWFiY2RlZg$.t0d48AX/.9e4/SD0XcJA4iyKJ4wRa4gFk.sbpzwxjA";
+        assert_eq!(HashKind::detect(passlib), Some(HashKind::PasslibPbkdf2));
+        assert!(verify_hash(b"hunter2hunter2", passlib));
+        assert!(!verify_hash(b"hunter2", passlib));
+
+        for unsupported in ["hunter2", "$1$saltsalt$hash", "$2b$12$short", "$md5$x$y"] {
+            assert_eq!(HashKind::detect(unsupported), None, "{unsupported}");
+            assert!(!verify_hash(b"hunter2", unsupported));
+        }
+    }
+
+    #[tokio::test]
+    async fn breached_passwords() -> std::io::Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("lockpad-breached-{}", std::process::id()));
+        tokio::fs::create_dir_all(&directory).await?;
+        // The SHA-1 hash of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8.
+        tokio::fs::write(
+            directory.join("5BAA6.txt"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n",
+        )
+        .await?;
+        let breached_passwords = BreachedPasswords::new(&directory);
+
+        assert!(breached_passwords.contains("password").await?);
+        assert!(breached_passwords
+            .contains("not in the corpus")
+            .await
+            .is_err());
+
+        let policy = PasswordPolicy {
+            min_length: 0,
+            min_strength: 0,
+            disallow_username: false,
+            breached_passwords: Some(breached_passwords),
+        };
+        assert_eq!(
+            codes(&policy.check("alice", "password").await),
+            ["breached"]
+        );
+        // the range of this one is missing from the corpus
+        assert_eq!(
+            codes(&policy.check("alice", "not in the corpus").await),
+            ["breached_unavailable"]
+        );
+
+        let mut errors = ValidationErrors::new();
+        policy
+            .validate(&mut errors, "new_password", "alice", "password")
+            .await;
+        assert!(errors.field_errors().contains_key("new_password"));
+
+        tokio::fs::remove_dir_all(&directory).await
+    }
+}
@@ -0,0 +1,535 @@
+use crate::{
+    client::client_ip,
+    error::{Error, Result},
+    session::hash_token,
+};
+use axum::{
+    extract::Request,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use lockpad_auth::{ApiKeyToken, PurgeSchedule};
+use lockpad_models::repository::ApplicationRepository;
+use lockpad_ulid::Ulid;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// A token bucket holding `burst` tokens, refilled at `per_minute` tokens a minute.
+/// Every request takes a token, requests finding the bucket empty are refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Quota {
+    /// Allows `requests` a minute, all of which can be made at once.
+    /// Returns `None` for zero, which disables the limit.
+    pub fn per_minute(requests: u32) -> Option<Self> {
+        (requests > 0).then_some(Self {
+            burst: requests,
+            per_minute: requests,
+        })
+    }
+
+    /// The time it takes for a token to be refilled.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60) / self.per_minute.max(1)
+    }
+
+    /// How far ahead of the refills requests can get, the tokens in a full bucket besides the one being taken.
+    fn tolerance(&self) -> Duration {
+        self.interval() * self.burst.saturating_sub(1)
+    }
+}
+
+/// The quotas requests are held to. Each one that is set is tracked separately.
+#[derive(Clone, Debug)]
+pub struct RateLimitPolicy {
+    /// Requests from one address
+    pub per_ip: Option<Quota>,
+    /// Requests naming a registered application in their `client_id` query parameter.
+    /// Any other `client_id` only counts against the address, so made up ids don't each get a full bucket.
+    pub per_client: Option<Quota>,
+    /// Requests made with an api key in their headers.
+    /// The keys aren't verified yet, so the bucket belongs to the whole key rather than its id,
+    /// a made up key carrying a real key's id doesn't drain the real key's bucket.
+    pub per_api_key: Option<Quota>,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            per_ip: Quota::per_minute(120),
+            per_client: Quota::per_minute(600),
+            per_api_key: Quota::per_minute(600),
+        }
+    }
+}
+
+/// Where the token buckets are kept.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// How long to wait for a token in the key's bucket, `None` when one can be taken now.
+    async fn wait(&self, key: &str, quota: &Quota) -> Result<Option<Duration>>;
+    /// Takes a token from the key's bucket, returning how long to wait for one when it is empty.
+    async fn take(&self, key: &str, quota: &Quota) -> Result<Option<Duration>>;
+}
+
+/// Keeps the buckets in memory, each server instance limits requests on its own.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    /// The time each bucket is full again
+    full_at: Mutex<HashMap<String, Instant>>,
+    purge: PurgeSchedule,
+}
+
+impl MemoryRateLimitStore {
+    fn full_at(&self) -> MutexGuard<'_, HashMap<String, Instant>> {
+        self.full_at.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn wait(&self, key: &str, quota: &Quota) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        let ahead = self.full_at().get(key).map_or(Duration::ZERO, |full_at| {
+            full_at.saturating_duration_since(now)
+        });
+
+        Ok((ahead > quota.tolerance()).then(|| ahead - quota.tolerance()))
+    }
+
+    async fn take(&self, key: &str, quota: &Quota) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        let mut buckets = self.full_at();
+
+        let full_at = buckets.get(key).copied().unwrap_or(now).max(now);
+        let ahead = full_at - now;
+        if ahead > quota.tolerance() {
+            return Ok(Some(ahead - quota.tolerance()));
+        }
+
+        if self.purge.due() {
+            buckets.retain(|_, full_at| *full_at > now);
+        }
+        buckets.insert(key.to_string(), full_at + quota.interval());
+
+        Ok(None)
+    }
+}
+
+/// Keeps the buckets in Postgres, so every server using the database shares the limits.
+pub struct PostgresRateLimitStore {
+    pool: sqlx::pool::Pool<sqlx::Postgres>,
+    purge: PurgeSchedule,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: sqlx::pool::Pool<sqlx::Postgres>) -> Self {
+        Self {
+            pool,
+            purge: PurgeSchedule::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    async fn wait(&self, key: &str, quota: &Quota) -> Result<Option<Duration>> {
+        let wait =
+            lockpad_models::rate_limit::token_wait(&self.pool, key, quota.tolerance()).await?;
+
+        Ok(wait)
+    }
+
+    async fn take(&self, key: &str, quota: &Quota) -> Result<Option<Duration>> {
+        if self.purge.due() {
+            let purged = lockpad_models::rate_limit::purge_full_buckets(&self.pool).await?;
+            tracing::debug!(purged, "purged full rate limit buckets");
+        }
+
+        let wait = lockpad_models::rate_limit::take_token(
+            &self.pool,
+            key,
+            quota.interval(),
+            quota.tolerance(),
+        )
+        .await?;
+
+        Ok(wait)
+    }
+}
+
+/// Checks requests against the policy, taking a token from each bucket the request counts against.
+#[derive(Clone)]
+pub struct RateLimiter {
+    policy: RateLimitPolicy,
+    store: Arc<dyn RateLimitStore>,
+    /// Looks up the `client_id` of requests, only registered applications get a bucket of their own
+    applications: Arc<dyn ApplicationRepository>,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn new(
+        policy: RateLimitPolicy,
+        store: Arc<dyn RateLimitStore>,
+        applications: Arc<dyn ApplicationRepository>,
+        trust_forwarded_for: bool,
+    ) -> Self {
+        Self {
+            policy,
+            store,
+            applications,
+            trust_forwarded_for,
+        }
+    }
+
+    /// The registered application named by the `client_id` query parameter, as the key of its bucket.
+    async fn application_id(&self, parts: &Parts) -> Option<String> {
+        let client_id = parts.uri.query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| name == "client_id")
+                .map(|(_, value)| value.into_owned())
+        })?;
+        let application_id = Ulid::from_str(&client_id).ok()?;
+
+        match self.applications.by_id(&application_id).await {
+            Ok(application) => {
+                application.map(|application| application.application_id.to_string())
+            }
+            Err(err) => {
+                tracing::warn!(?err, "failed to look up the client_id of a request");
+                None
+            }
+        }
+    }
+
+    /// The buckets the request counts against, along with their quota.
+    async fn buckets(&self, parts: &Parts) -> Vec<(String, Quota)> {
+        let mut buckets = Vec::new();
+
+        if let Some(quota) = self.policy.per_ip {
+            let ip = client_ip(&parts.headers, &parts.extensions, self.trust_forwarded_for);
+            if let Some(ip) = ip {
+                buckets.push((format!("ip:{ip}"), quota));
+            }
+        }
+        if let Some(quota) = self.policy.per_client {
+            if let Some(application_id) = self.application_id(parts).await {
+                buckets.push((format!("client:{application_id}"), quota));
+            }
+        }
+        if let Some(quota) = self.policy.per_api_key {
+            if let Ok(Some(token)) = ApiKeyToken::from_headers(&parts.headers) {
+                buckets.push((format!("api_key:{}", hash_token(&token.to_string())), quota));
+            }
+        }
+
+        buckets
+    }
+
+    /// Refuses the request when any of its buckets is empty.
+    /// Every bucket is checked before tokens are taken, so a refused request doesn't use up the buckets that had room.
+    /// Requests are let through when the store fails, so an unavailable store doesn't take the server down with it.
+    pub async fn check(&self, parts: &Parts) -> Result<()> {
+        let buckets = self.buckets(parts).await;
+
+        for (key, quota) in &buckets {
+            match self.store.wait(key, quota).await {
+                Ok(None) => {}
+                Ok(Some(wait)) => {
+                    tracing::debug!(key, ?wait, "rate limited");
+                    return Err(Error::TooManyRequests(wait));
+                }
+                Err(err) => tracing::warn!(?err, key, "failed to check rate limit"),
+            }
+        }
+        // Concurrent requests can still empty a bucket in between, taking the token then refuses the request.
+        for (key, quota) in buckets {
+            match self.store.take(&key, &quota).await {
+                Ok(None) => {}
+                Ok(Some(wait)) => {
+                    tracing::debug!(key, ?wait, "rate limited");
+                    return Err(Error::TooManyRequests(wait));
+                }
+                Err(err) => tracing::warn!(?err, key, "failed to check rate limit"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies a [`RateLimiter`] to every request of the wrapped service.
+/// Limited requests are answered with `429 Too Many Requests` and a `Retry-After` header.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> tower::Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: RateLimiter,
+}
+
+impl<S> tower::Service<Request> for RateLimitService<S>
+where
+    S: tower::Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Infallible>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone may not be ready, so the service that was polled is the one used.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            match limiter.check(&parts).await {
+                Ok(()) => inner.call(Request::from_parts(parts, body)).await,
+                Err(err) => Ok(err.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::send;
+    use axum::http::StatusCode;
+    use lockpad_models::{
+        application::Application, entity::Builder, repository::Repositories, user::User,
+    };
+
+    #[tokio::test]
+    async fn buckets_empty_and_refill() -> Result<()> {
+        let store = MemoryRateLimitStore::default();
+        let quota = Quota {
+            burst: 2,
+            per_minute: 600,
+        };
+
+        assert_eq!(store.take("ip:192.0.2.1", &quota).await?, None);
+        assert_eq!(store.take("ip:192.0.2.1", &quota).await?, None);
+        let wait = store.take("ip:192.0.2.1", &quota).await?.unwrap();
+        assert!(wait <= quota.interval());
+        assert_eq!(store.take("ip:192.0.2.2", &quota).await?, None);
+
+        tokio::time::sleep(wait).await;
+        assert_eq!(store.take("ip:192.0.2.1", &quota).await?, None);
+
+        Ok(())
+    }
+
+    /// Registers an application, returning its id to use as a `client_id`.
+    async fn application(repositories: &Repositories) -> String {
+        let owner = User::builder()
+            .identifier(Ulid::generate().to_string())
+            .secret("hash".to_string())
+            .build()
+            .unwrap();
+        repositories.users.create(&owner).await.unwrap();
+        let application = Application::builder()
+            .owner_id(owner.user_id)
+            .name("test".to_string())
+            .allowed_origins(vec![])
+            .allowed_callback_urls(vec![])
+            .build()
+            .unwrap();
+        repositories
+            .applications
+            .create(&application)
+            .await
+            .unwrap();
+
+        application.application_id.to_string()
+    }
+
+    fn request(uri: &str, forwarded_for: &str) -> Parts {
+        let (parts, _) = Request::builder()
+            .uri(uri)
+            .header("x-api-key", "ignored")
+            .header("x-forwarded-for", forwarded_for)
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+    }
+
+    #[tokio::test]
+    async fn requests_count_against_each_registered_client() {
+        let repositories = Repositories::memory();
+        let limiter = RateLimiter::new(
+            RateLimitPolicy {
+                per_ip: None,
+                per_client: Quota::per_minute(1),
+                per_api_key: Quota::per_minute(0),
+            },
+            Arc::new(MemoryRateLimitStore::default()),
+            repositories.applications.clone(),
+            false,
+        );
+        let a = application(&repositories).await;
+        let b = application(&repositories).await;
+        let request = |uri: &str| request(uri, "192.0.2.1");
+
+        let uri = format!("/login?client_id={a}");
+        assert!(limiter.check(&request(&uri)).await.is_ok());
+        assert!(matches!(
+            limiter.check(&request(&uri)).await,
+            Err(Error::TooManyRequests(_))
+        ));
+        assert!(limiter
+            .check(&request(&format!("/login?client_id={b}")))
+            .await
+            .is_ok());
+        assert!(limiter.check(&request("/health")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn made_up_clients_count_against_the_address() {
+        let repositories = Repositories::memory();
+        let limiter = RateLimiter::new(
+            RateLimitPolicy {
+                per_ip: Quota::per_minute(1),
+                per_client: Quota::per_minute(1),
+                per_api_key: None,
+            },
+            Arc::new(MemoryRateLimitStore::default()),
+            repositories.applications.clone(),
+            true,
+        );
+
+        let made_up = Ulid::generate();
+        let request =
+            |client_id: &str| request(&format!("/login?client_id={client_id}"), "192.0.2.1");
+        assert!(limiter.check(&request(&made_up.to_string())).await.is_ok());
+        assert!(limiter.check(&request("another")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refused_requests_take_no_tokens() {
+        let repositories = Repositories::memory();
+        let limiter = RateLimiter::new(
+            RateLimitPolicy {
+                per_ip: Quota::per_minute(1),
+                per_client: Quota::per_minute(1),
+                per_api_key: None,
+            },
+            Arc::new(MemoryRateLimitStore::default()),
+            repositories.applications.clone(),
+            true,
+        );
+        let a = application(&repositories).await;
+        let b = application(&repositories).await;
+
+        let uri = format!("/login?client_id={a}");
+        assert!(limiter.check(&request(&uri, "192.0.2.1")).await.is_ok());
+        // refused by the client's bucket, the second address keeps its token
+        assert!(limiter.check(&request(&uri, "192.0.2.2")).await.is_err());
+        let uri = format!("/login?client_id={b}");
+        assert!(limiter.check(&request(&uri, "192.0.2.2")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn health_checks_and_keys_are_not_limited() {
+        let repositories = Repositories::memory();
+        let app = crate::Server::builder()
+            .repositories(repositories.clone())
+            .jwt_secret(include_bytes!("../testdata/secret-rsa.pem").to_vec())
+            .jwt_public(include_bytes!("../testdata/public-rsa.pem").to_vec())
+            .rate_limit_policy(RateLimitPolicy {
+                per_ip: None,
+                per_client: Quota::per_minute(1),
+                per_api_key: None,
+            })
+            .build()
+            .and_then(crate::Server::router)
+            .unwrap();
+        let client_id = application(&repositories).await;
+        let get = |path: &str| {
+            Request::builder()
+                .uri(format!("{path}?client_id={client_id}"))
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        for path in ["/health", "/.well-known/jwks.json", "/health"] {
+            assert_eq!(send(&app, get(path)).await.0, StatusCode::OK, "{path}");
+        }
+        assert_ne!(
+            send(&app, get("/login")).await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send(&app, get("/login")).await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn forged_api_keys_get_their_own_bucket() {
+        let limiter = RateLimiter::new(
+            RateLimitPolicy {
+                per_ip: None,
+                per_client: None,
+                per_api_key: Quota::per_minute(1),
+            },
+            Arc::new(MemoryRateLimitStore::default()),
+            Repositories::memory().applications,
+            false,
+        );
+        let real = ApiKeyToken::generate(Ulid::generate().to_string());
+        let forged = ApiKeyToken::generate(real.api_key_id().to_string());
+        let request = |token: &ApiKeyToken| {
+            let (parts, _) = Request::builder()
+                .header("x-api-key", token.to_string())
+                .body(())
+                .unwrap()
+                .into_parts();
+            parts
+        };
+
+        assert!(limiter.check(&request(&forged)).await.is_ok());
+        assert!(limiter.check(&request(&forged)).await.is_err());
+        assert!(limiter.check(&request(&real)).await.is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{client::ClientInfo, error::Error, ServerState};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use base64::Engine;
+use lockpad_models::{entity::Builder, repository::Repositories, session::Session};
+use lockpad_ulid::Ulid;
+use sha2::{Digest, Sha256};
+use time::Duration;
+
+/// The name of the cookie holding the session token.
+pub(crate) const SESSION_COOKIE: &str = "lockpad_session";
+
+/// How long a session remains valid after the user logs in.
+pub(crate) const SESSION_LIFETIME: Duration = Duration::days(14);
+
+/// Generates a random session token.
+/// The token is only ever given to the browser, the database stores its hash.
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hashes a high entropy token, such as a session token, for storage and lookup.
+/// The token has enough entropy that a fast hash is sufficient here.
+pub(crate) fn hash_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest)
+}
+
+/// Creates a new session for the user and adds its cookie to the jar.
+pub(crate) async fn start_session(
+    repositories: &Repositories,
+    jar: CookieJar,
+    user_id: Ulid,
+    client: &ClientInfo,
+) -> crate::error::Result<(CookieJar, Session)> {
+    let token = generate_token();
+
+    let session = Session::builder()
+        .user_id(user_id)
+        .token_hash(hash_token(&token))
+        .lifetime(SESSION_LIFETIME)
+        .ip_address(client.ip_string())
+        .user_agent(client.user_agent.clone())
+        .build()?;
+    repositories.sessions.create(&session).await?;
+    tracing::debug!(?session.session_id, "started session");
+
+    let cookie = Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(SESSION_LIFETIME)
+        .build();
+
+    Ok((jar.add(cookie), session))
+}
+
+/// Ends the session and removes its cookie from the jar.
+pub(crate) async fn end_session(
+    repositories: &Repositories,
+    jar: CookieJar,
+    session: &Session,
+) -> crate::error::Result<CookieJar> {
+    repositories.sessions.delete(session).await?;
+    tracing::debug!(?session.session_id, "ended session");
+
+    Ok(jar.remove(Cookie::build(SESSION_COOKIE).path("/")))
+}
+
+/// The session belonging to the request's session cookie, if there is a valid one.
+pub struct CurrentSession(pub Option<Session>);
+
+#[async_trait::async_trait]
+impl FromRequestParts<ServerState> for CurrentSession {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        let token = match jar.get(SESSION_COOKIE) {
+            None => return Ok(CurrentSession(None)),
+            Some(cookie) => cookie.value().to_owned(),
+        };
+
+        let mut session = state
+            .repositories
+            .sessions
+            .by_token_hash(&hash_token(&token))
+            .await?;
+        if let Some(session) = &mut session {
+            let client = ClientInfo::from_parts(parts, state.trust_forwarded_for);
+            state
+                .repositories
+                .sessions
+                .touch(session, client.ip_string(), client.user_agent)
+                .await?;
+        }
+
+        Ok(CurrentSession(session))
+    }
+}
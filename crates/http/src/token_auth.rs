@@ -0,0 +1,216 @@
+use crate::{error::Error, ServerState};
+use axum::{
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use lockpad_auth::Claims;
+use lockpad_ulid::Ulid;
+use std::str::FromStr;
+
+/// The claims of a token that may manage the user's account through lockpad's own api.
+/// Tokens issued to an application and tokens limited to scopes, such as the ones minted from a scoped api key, are refused.
+/// They are meant for other services, so they can't be used to create keys or applications in the user's name.
+/// Tokens of deleted and disabled users, and the ones issued before the user signed out everywhere or changed their password, are refused as well.
+pub(crate) struct AccountClaims(pub Claims);
+
+#[axum::async_trait]
+impl FromRequestParts<ServerState> for AccountClaims {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        // Tokens issued to an application are refused by the extractor, the server only accepts its own audience.
+        let claims = Claims::from_request_parts(parts, state).await?;
+
+        if claims.scope.is_some() {
+            tracing::debug!(claims.sub, "token is limited to scopes");
+            return Err(Error::Forbidden.into_response());
+        }
+
+        let user_id =
+            Ulid::from_str(&claims.sub).map_err(|err| Error::from(err).into_response())?;
+        let user = state
+            .repositories
+            .users
+            .by_id(&user_id)
+            .await
+            .map_err(|err| Error::from(err).into_response())?;
+        let Some(user) = user else {
+            tracing::debug!(?user_id, "token belongs to a deleted user");
+            return Err(Error::Unauthorized.into_response());
+        };
+        if user.disabled {
+            tracing::debug!(?user_id, "token belongs to a disabled user");
+            return Err(Error::Forbidden.into_response());
+        }
+        // `iat` only has whole seconds, so tokens issued in the same second as the revocation are refused too.
+        if user
+            .tokens_valid_after
+            .is_some_and(|valid_after| claims.iat as i64 <= valid_after.unix_timestamp())
+        {
+            tracing::debug!(?user_id, "token was revoked");
+            return Err(Error::Unauthorized.into_response());
+        }
+
+        Ok(Self(claims))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{app, encode, json, register, send};
+    use axum::{
+        extract::FromRef,
+        http::{header, StatusCode},
+        routing::get,
+        Router,
+    };
+    use lockpad_auth::{Audience, Claims, PublicKey};
+    use lockpad_models::repository::Repositories;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn scoped_tokens_cannot_manage_the_account() {
+        let app = app(Repositories::memory());
+        let token = register(&app, "ci-owner").await;
+
+        let key = json!({ "name": "ci", "scopes": ["read"] });
+        let (status, _, body) = send(&app, json("POST", "/api-keys", Some(&token), key)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let created: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let secret = created["secret"].as_str().unwrap();
+
+        let mut request = json("POST", "/api/authorize", None, json!(null));
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            format!("ApiKey {secret}").parse().unwrap(),
+        );
+        let (status, _, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let minted: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let scoped_token = minted["token"].as_str().unwrap();
+
+        let key = json!({ "name": "escalated" });
+        let request = json("POST", "/api-keys", Some(scoped_token), key.clone());
+        assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN);
+        let request = json("GET", "/me/export", Some(scoped_token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN);
+
+        let request = json("POST", "/api-keys", Some(&token), key);
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn application_tokens_cannot_manage_the_account() {
+        let app = app(Repositories::memory());
+        let token = register(&app, "app-user").await;
+        let request = json("GET", "/me", Some(&token), json!(null));
+        let (_, _, body) = send(&app, request).await;
+        let user: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let user_id = user["user_id"].as_str().unwrap().to_string();
+
+        let application_token = encode(
+            Claims::new(user_id.clone()).with_audience("01M5A2RBCH3Q4M4W1RS8ZKFE6V".to_string()),
+        )
+        .await;
+        for (method, uri) in [
+            ("POST", "/api-keys"),
+            ("GET", "/applications"),
+            ("GET", "/me/export"),
+        ] {
+            let request = json(
+                method,
+                uri,
+                Some(&application_token),
+                json!({ "name": "stolen" }),
+            );
+            assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN, "{uri}");
+        }
+
+        let request = json(
+            "GET",
+            "/me",
+            Some(&encode(Claims::new(user_id)).await),
+            json!(null),
+        );
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refused_after_signing_out_everywhere() {
+        let app = app(Repositories::memory());
+        let token = register(&app, "leaving").await;
+        let request = json("GET", "/me", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+
+        let request = json("DELETE", "/me/sessions", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::NO_CONTENT);
+
+        let request = json("GET", "/me", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn disabled_and_deleted_users_are_refused() {
+        let repositories = Repositories::memory();
+        let app = app(repositories.clone());
+        let token = register(&app, "disabled").await;
+        let mut user = repositories
+            .users
+            .by_identifier("disabled")
+            .await
+            .unwrap()
+            .unwrap();
+
+        user.disabled = true;
+        repositories.users.update(&user).await.unwrap();
+        let request = json("GET", "/me", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN);
+
+        repositories.users.delete(&user).await.unwrap();
+        let request = json("GET", "/api-keys", Some(&token), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[derive(Clone)]
+    struct ApplicationState {
+        public_key: PublicKey,
+    }
+
+    impl FromRef<ApplicationState> for PublicKey {
+        fn from_ref(state: &ApplicationState) -> Self {
+            state.public_key.clone()
+        }
+    }
+
+    impl FromRef<ApplicationState> for Audience {
+        fn from_ref(_state: &ApplicationState) -> Self {
+            Audience::application("01M5A2RBCH3Q4M4W1RS8ZKFE6V".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn applications_only_accept_tokens_issued_to_them() {
+        let public_key = PublicKey::new(include_bytes!("../testdata/public-rsa.pem").to_vec());
+        let app = Router::new()
+            .route("/", get(|claims: Claims| async move { claims.sub }))
+            .with_state(ApplicationState {
+                public_key: public_key.unwrap(),
+            });
+        let claims = || Claims::new("01GVF4BCM32TTM19YBFB2ZNE4R".to_string());
+
+        let own = encode(claims().with_audience("01M5A2RBCH3Q4M4W1RS8ZKFE6V".to_string())).await;
+        let other = encode(claims().with_audience("01M5A2RBCH3Q4M4W1RS8ZKFE6W".to_string())).await;
+        let lockpad = encode(claims()).await;
+
+        let request = json("GET", "/", Some(&own), json!(null));
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+        for token in [other, lockpad] {
+            let request = json("GET", "/", Some(&token), json!(null));
+            assert_eq!(send(&app, request).await.0, StatusCode::FORBIDDEN);
+        }
+    }
+}
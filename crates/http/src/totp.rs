@@ -0,0 +1,177 @@
+//! Time-based one-time passwords (RFC 6238), the codes authenticator apps show.
+//! Apps are given the secret base32 encoded, and show a new 6 digit code every 30 seconds.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// Seconds each code is shown for.
+const STEP_SECONDS: i64 = 30;
+/// Codes are this many digits.
+const DIGITS: u32 = 6;
+/// Codes of this many steps before and after the current one are accepted too, for clocks that are slightly off.
+const SKEW: i64 = 1;
+/// Generated secrets are 160 bits, the length of a SHA-1 hash RFC 4226 recommends.
+const SECRET_BYTES: usize = 20;
+/// Secrets shorter than 128 bits, the least RFC 4226 allows, are refused.
+const MIN_SECRET_BYTES: usize = 16;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new random secret, base32 encoded.
+pub(crate) fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    base32_encode(&bytes)
+}
+
+/// Encodes the bytes with the RFC 4648 alphabet, without padding as authenticator apps expect.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer
+            .iter()
+            .fold(0u64, |bits, byte| bits << 8 | u64::from(*byte));
+
+        let characters = (chunk.len() * 8).div_ceil(5);
+        for i in 0..characters {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            encoded.push(char::from(BASE32_ALPHABET[index as usize]));
+        }
+    }
+
+    encoded
+}
+
+/// Decodes base32 the way users may type it, ignoring case, spaces and padding.
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 5 / 8);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for character in text.bytes().filter(|c| !matches!(c, b' ' | b'=')) {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == character.to_ascii_uppercase())?;
+        bits = bits << 5 | value as u32;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            decoded.push((bits >> count) as u8);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// The HOTP value (RFC 4226) of the counter, truncated to [`DIGITS`] digits.
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks the code against the secret at `unix_time`, returning the time step the code belongs to.
+/// Callers have to remember the step, so the code can't be used again.
+pub(crate) fn verify(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let key = base32_decode(secret).filter(|key| key.len() >= MIN_SECRET_BYTES)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = unix_time.div_euclid(STEP_SECONDS);
+    (current - SKEW..=current + SKEW)
+        .find(|step| u64::try_from(*step).is_ok_and(|counter| hotp(&key, counter) == code))
+}
+
+/// The code an authenticator app shows at `unix_time`.
+#[cfg(test)]
+pub(crate) fn code_at(secret: &str, unix_time: i64) -> String {
+    let key = base32_decode(secret).expect("secret is base32");
+    let counter = u64::try_from(unix_time.div_euclid(STEP_SECONDS)).expect("time is after 1970");
+
+    format!("{:0width$}", hotp(&key, counter), width = DIGITS as usize)
+}
+
+/// A link authenticator apps can import the secret from.
+pub(crate) fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let encode =
+        |text: &str| url::form_urlencoded::byte_serialize(text.as_bytes()).collect::<String>();
+
+    format!(
+        "otpauth://totp/{}:{}?secret={secret}&issuer={}&digits={DIGITS}&period={STEP_SECONDS}",
+        encode(issuer),
+        encode(account),
+        encode(issuer),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 secret of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// The code of the step, zero padded to [`DIGITS`] digits.
+    fn code(key: &[u8], step: u64) -> String {
+        format!("{:06}", hotp(key, step))
+    }
+
+    #[test]
+    fn matches_the_rfc_6238_test_vectors() {
+        // the RFC lists 8 digit codes, these are their last 6 digits
+        for (time, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            assert_eq!(code(RFC_SECRET, time / 30), expected, "{time}");
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        let secret = base32_encode(RFC_SECRET);
+        assert_eq!(secret, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode(&secret).unwrap(), RFC_SECRET);
+        assert_eq!(
+            base32_decode("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(),
+            RFC_SECRET
+        );
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_decode("MY======").unwrap(), b"f");
+        assert_eq!(base32_decode("not base32!"), None);
+
+        assert_eq!(generate_secret().len(), 32);
+    }
+
+    #[test]
+    fn accepts_codes_of_neighbouring_steps() {
+        let secret = base32_encode(RFC_SECRET);
+        let now = 1111111109;
+
+        assert_eq!(verify(&secret, "081804", now), Some(now / 30));
+        assert_eq!(verify(&secret, " 081804 ", now + 30), Some(now / 30));
+        assert_eq!(verify(&secret, "081804", now + 90), None);
+        assert_eq!(verify(&secret, "81804", now), None);
+        assert_eq!(verify(&secret, "+81804", now), None);
+        assert_eq!(verify("MZXW6", "081804", now), None);
+    }
+}
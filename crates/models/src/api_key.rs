@@ -1,13 +1,29 @@
 use crate::error::{Error, Result};
 use lockpad_ulid::Ulid;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ApiKey {
     pub api_key_id: Ulid,
     pub owner_id: Ulid,
     pub name: String,
+    #[serde(skip_serializing)]
     pub secret: String,
+
+    /// The scopes copied into tokens minted from the key.
+    /// A key without scopes can do everything its owner can.
+    pub scopes: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub revoked_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_used_at: Option<OffsetDateTime>,
+    /// The number of times the key was used.
+    pub use_count: i64,
 }
 
 impl ApiKey {
@@ -16,121 +32,232 @@ impl ApiKey {
     }
 
     pub async fn by_id(pool: &sqlx::pool::Pool<sqlx::Postgres>, id: &Ulid) -> Result<Option<Self>> {
-        let query = sqlx::query!(
+        let api_key = sqlx::query_as!(
+            ApiKey,
             r#"
             SELECT
-                api_key_id::uuid as "api_key_id: Ulid",
-                owner_id::uuid as "owner_id: Ulid",
+                api_key_id::uuid as "api_key_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
                 name,
-                secret
-            FROM 
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
                 api_keys
-            WHERE 
+            WHERE
                 api_key_id::uuid = $1
             "#,
             id.to_sqlx_uuid()
-        );
-
-        let api_key = query.fetch_one(pool).await?;
-        match (api_key.api_key_id, api_key.owner_id) {
-            (Some(api_key_id), Some(owner_id)) => {
-                let api_key = ApiKey {
-                    api_key_id,
-                    owner_id,
-                    name: api_key.name,
-                    secret: api_key.secret,
-                };
+        )
+        .fetch_optional(pool)
+        .await?;
 
-                Ok(Some(api_key))
-            }
-            _ => Ok(None),
-        }
+        Ok(api_key)
     }
 
-    pub async fn by_owner_id(
+    /// Finds an api key only if it belongs to the owner.
+    pub async fn by_owner_and_id(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
         owner_id: &Ulid,
+        id: &Ulid,
     ) -> Result<Option<Self>> {
         let api_key = sqlx::query_as!(
             ApiKey,
             r#"
             SELECT
-                api_key_id as "api_key_id: Ulid", owner_id as "owner_id: Ulid", name, secret
-            FROM 
+                api_key_id::uuid as "api_key_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
                 api_keys
-            WHERE 
-                owner_id = $1
+            WHERE
+                api_key_id::uuid = $1 AND owner_id::uuid = $2
             "#,
-            owner_id as _,
+            id.to_sqlx_uuid(),
+            owner_id.to_sqlx_uuid(),
         )
-        .fetch_one(pool)
+        .fetch_optional(pool)
         .await?;
 
-        Ok(Some(api_key))
+        Ok(api_key)
     }
 
     pub async fn create(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO 
-                api_keys(api_key_id, owner_id, name, secret)
-            SELECT 
-                api_key_id::uuid, owner_id::uuid, name, secret
+            INSERT INTO
+                api_keys(api_key_id, owner_id, name, secret, scopes, created_at, expires_at)
+            SELECT
+                api_key_id::uuid, owner_id::uuid, name, secret, scopes, created_at, expires_at
             FROM(
                 VALUES(
-                    $1, $2, $3, $4
+                    $1, $2, $3, $4, $5::text[], $6::timestamptz, $7::timestamptz
                 )
-            ) AS data(api_key_id, owner_id, name, secret)
+            ) AS data(api_key_id, owner_id, name, secret, scopes, created_at, expires_at)
             "#,
         )
         .bind(self.api_key_id.queryable())
         .bind(self.owner_id.queryable())
         .bind(&self.name)
         .bind(&self.secret)
+        .bind(&self.scopes)
+        .bind(self.created_at)
+        .bind(self.expires_at)
         .execute(pool)
         .await?;
 
         Ok(())
     }
 
+    /// Whether tokens can still be minted from the key.
+    pub fn is_active(&self) -> bool {
+        let expired = self
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc());
+
+        self.revoked_at.is_none() && !expired
+    }
+
+    /// Records that the key was used, either to mint a token or to authenticate a request directly.
+    pub async fn record_use(&mut self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        self.use_count = Self::record_uses_by_id(pool, &self.api_key_id, 1, now).await?;
+        self.last_used_at = Some(now);
+
+        Ok(())
+    }
+
+    /// Records uses of the key without loading it, returning the new use count.
+    /// `used_at` is the time of the last of them.
+    pub async fn record_uses_by_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        api_key_id: &Ulid,
+        uses: i64,
+        used_at: OffsetDateTime,
+    ) -> Result<i64> {
+        let use_count = sqlx::query_scalar!(
+            r#"
+            UPDATE
+                api_keys
+            SET
+                last_used_at = GREATEST(last_used_at, $2),
+                use_count = use_count + $3
+            WHERE
+                api_key_id::uuid = $1
+            RETURNING
+                use_count
+            "#,
+            api_key_id.to_sqlx_uuid(),
+            used_at,
+            uses,
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(use_count)
+    }
+
+    /// Stops the key from being used. The key is kept so its usage remains visible.
+    pub async fn revoke(&mut self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                api_keys
+            SET
+                revoked_at = COALESCE(revoked_at, $2)
+            WHERE
+                api_key_id::uuid = $1
+            "#,
+            self.api_key_id.to_sqlx_uuid(),
+            now,
+        )
+        .execute(pool)
+        .await?;
+
+        self.revoked_at.get_or_insert(now);
+
+        Ok(())
+    }
+
+    /// Lists every api key, regardless of owner.
+    pub async fn all(pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<Vec<Self>> {
+        let api_keys = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT
+                api_key_id::uuid as "api_key_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
+                api_keys
+            ORDER BY
+                api_key_id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(api_keys)
+    }
+
+    /// Lists the owner's api keys.
     pub async fn query(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
-        _owner_id: Ulid,
-        _pagination: crate::Pagination,
+        owner_id: Ulid,
+        pagination: crate::Pagination,
     ) -> Result<(Vec<Self>, crate::Pagination)> {
-        // TODO: Implement pagination for querying
-        let api_keys = sqlx::query!(
+        let api_keys = sqlx::query_as!(
+            ApiKey,
             r#"
             SELECT
-                api_key_id::uuid as "api_key_id: Ulid",
-                owner_id::uuid as "owner_id: Ulid",
-                name, secret
-            FROM 
+                api_key_id::uuid as "api_key_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
                 api_keys
+            WHERE
+                owner_id::uuid = $1
+                AND ($2::uuid IS NULL OR api_key_id::uuid > $2)
+            ORDER BY
+                api_key_id::uuid
+            LIMIT $3
             "#,
+            owner_id.to_sqlx_uuid(),
+            pagination.after(),
+            pagination.fetch_limit(),
         )
         .fetch_all(pool)
         .await?;
 
-        let api_keys = api_keys
-            .into_iter()
-            .filter_map(|user| match (user.api_key_id, user.owner_id) {
-                (Some(api_key_id), Some(owner_id)) => Some(ApiKey {
-                    api_key_id,
-                    owner_id,
-                    name: user.name,
-                    secret: user.secret,
-                }),
-                _ => None,
-            })
-            .collect::<Vec<_>>();
-
-        let pagination = crate::Pagination {
-            last_key: api_keys.last().map(|api_key| api_key.api_key_id),
-            count: api_keys.len(),
-        };
-
-        Ok((api_keys, pagination))
+        Ok(pagination.page(api_keys, |api_key| api_key.api_key_id))
     }
 }
 
@@ -140,6 +267,8 @@ pub struct Builder {
     owner_id: Option<Ulid>,
     name: Option<String>,
     secret: Option<String>,
+    scopes: Vec<String>,
+    expires_at: Option<OffsetDateTime>,
 }
 
 impl Builder {
@@ -162,6 +291,16 @@ impl Builder {
         self.secret = Some(value);
         self
     }
+
+    pub fn scopes(mut self, scopes: Vec<String>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<OffsetDateTime>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
 }
 
 impl crate::entity::Builder for Builder {
@@ -182,6 +321,12 @@ impl crate::entity::Builder for Builder {
             owner_id,
             name,
             secret,
+            scopes: self.scopes,
+            created_at: OffsetDateTime::now_utc(),
+            expires_at: self.expires_at,
+            revoked_at: None,
+            last_used_at: None,
+            use_count: 0,
         })
     }
 }
@@ -2,7 +2,7 @@ use crate::error::{Error, Result};
 use lockpad_ulid::Ulid;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Application {
     pub application_id: Ulid,
     pub owner_id: Ulid,
@@ -10,6 +10,10 @@ pub struct Application {
     pub name: String,
     pub allowed_origins: Vec<String>,
     pub allowed_callback_urls: Vec<String>,
+    /// Locations the user may be sent to after logging out.
+    pub allowed_logout_urls: Vec<String>,
+    /// Receives OpenID Connect back-channel logout notifications.
+    pub backchannel_logout_uri: Option<String>,
 }
 
 impl Application {
@@ -18,16 +22,17 @@ impl Application {
     }
 
     pub async fn by_id(pool: &sqlx::pool::Pool<sqlx::Postgres>, id: &Ulid) -> Result<Option<Self>> {
-        /*
         let application = sqlx::query_as!(
             Application,
             r#"
             SELECT
-                application_id as "application_id: Ulid",
-                owner_id as "owner_id: Ulid",
+                application_id as "application_id!: Ulid",
+                owner_id as "owner_id!: Ulid",
                 name,
                 allowed_origins,
-                allowed_callback_urls
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
             FROM
                 applications
             WHERE
@@ -35,78 +40,56 @@ impl Application {
             "#,
             id as _,
         )
-        .fetch_one(pool)
+        .fetch_optional(pool)
         .await?;
-        */
 
-        let query = sqlx::query!(
-            r#"
-            SELECT
-                application_id::uuid as "application_id: Ulid",
-                owner_id::uuid as "owner_id: Ulid",
-                name,
-                allowed_origins,
-                allowed_callback_urls
-            FROM 
-                applications
-            WHERE 
-                application_id::uuid = $1
-            "#,
-            id.to_sqlx_uuid()
-        );
-
-        let application = query.fetch_one(pool).await?;
-        match (application.application_id, application.owner_id) {
-            (Some(application_id), Some(owner_id)) => {
-                let application = Application {
-                    application_id,
-                    owner_id,
-                    name: application.name,
-                    allowed_origins: application.allowed_origins,
-                    allowed_callback_urls: application.allowed_callback_urls,
-                };
-
-                Ok(Some(application))
-            }
-            _ => Ok(None),
-        }
-    }
-
-    pub async fn by_owner_id(
+        Ok(application)
+    }
+
+    /// Finds an application only if it belongs to the owner.
+    pub async fn by_owner_and_id(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
         owner_id: &Ulid,
+        id: &Ulid,
     ) -> Result<Option<Self>> {
         let application = sqlx::query_as!(
             Application,
             r#"
             SELECT
-                application_id as "application_id: Ulid", owner_id as "owner_id: Ulid", name, allowed_origins, allowed_callback_urls
-            FROM 
+                application_id::uuid as "application_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
                 applications
-            WHERE 
-                owner_id = $1
+            WHERE
+                application_id::uuid = $1 AND owner_id::uuid = $2
             "#,
-            owner_id as _,
+            id.to_sqlx_uuid(),
+            owner_id.to_sqlx_uuid(),
         )
-        .fetch_one(pool)
+        .fetch_optional(pool)
         .await?;
 
-        Ok(Some(application))
+        Ok(application)
     }
 
     pub async fn create(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
         sqlx::query(
             r#"
             INSERT INTO 
-                applications(application_id, owner_id, name, allowed_origins, allowed_callback_urls)
+                applications(application_id, owner_id, name, allowed_origins, allowed_callback_urls, allowed_logout_urls, backchannel_logout_uri)
             SELECT 
-                application_id::uuid, owner_id::uuid, name, allowed_origins, allowed_callback_urls
+                application_id::uuid, owner_id::uuid, name, allowed_origins, allowed_callback_urls, allowed_logout_urls, backchannel_logout_uri
             FROM(
                 VALUES(
                     $1, $2, $3, 
-                    $4::text[], $5::text[]
+                    $4::text[], $5::text[], $6::text[], $7
                 )
-            ) AS data(application_id, owner_id, name, allowed_origins, allowed_callback_urls)
+            ) AS data(application_id, owner_id, name, allowed_origins, allowed_callback_urls, allowed_logout_urls, backchannel_logout_uri)
             "#,
         )
         .bind(self.application_id.queryable())
@@ -114,55 +97,150 @@ impl Application {
         .bind(&self.name)
         .bind(&self.allowed_origins)
         .bind(&self.allowed_callback_urls)
+        .bind(&self.allowed_logout_urls)
+        .bind(&self.backchannel_logout_uri)
         .execute(pool)
         .await?;
 
         Ok(())
     }
 
+    /// Lists the owner's applications.
     pub async fn query(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
-        _owner_id: Ulid,
-        _pagination: crate::Pagination,
+        owner_id: Ulid,
+        pagination: crate::Pagination,
     ) -> Result<(Vec<Self>, crate::Pagination)> {
-        // TODO: Implement pagination for querying
-        let applications = sqlx::query!(
+        let applications = sqlx::query_as!(
+            Application,
+            r#"
+            SELECT
+                application_id::uuid as "application_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
+                applications
+            WHERE
+                owner_id::uuid = $1
+                AND ($2::uuid IS NULL OR application_id::uuid > $2)
+            ORDER BY
+                application_id::uuid
+            LIMIT $3
+            "#,
+            owner_id.to_sqlx_uuid(),
+            pagination.after(),
+            pagination.fetch_limit(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(pagination.page(applications, |application| application.application_id))
+    }
+
+    /// Saves changes to everything but the application's id and owner.
+    pub async fn update(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE
+                applications
+            SET
+                name = $2,
+                allowed_origins = $3,
+                allowed_callback_urls = $4,
+                allowed_logout_urls = $5,
+                backchannel_logout_uri = $6
+            WHERE
+                application_id::uuid = $1
+            "#,
+            self.application_id.to_sqlx_uuid(),
+            self.name,
+            &self.allowed_origins,
+            &self.allowed_callback_urls,
+            &self.allowed_logout_urls,
+            self.backchannel_logout_uri,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the application.
+    /// The grants users gave it and its record in their sessions are removed along with it.
+    pub async fn delete(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                applications
+            WHERE
+                application_id::uuid = $1
+            "#,
+            self.application_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lists every application, regardless of owner.
+    pub async fn all(pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<Vec<Self>> {
+        let applications = sqlx::query_as!(
+            Application,
             r#"
             SELECT
-                application_id::uuid as "application_id: Ulid",
-                owner_id::uuid as "owner_id: Ulid",
+                application_id::uuid as "application_id!: Ulid",
+                owner_id::uuid as "owner_id!: Ulid",
                 name,
                 allowed_origins,
-                allowed_callback_urls
-            FROM 
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
                 applications
+            ORDER BY
+                application_id
             "#,
         )
         .fetch_all(pool)
         .await?;
 
-        let applications = applications
-            .into_iter()
-            .filter_map(|user| match (user.application_id, user.owner_id) {
-                (Some(application_id), Some(owner_id)) => Some(Application {
-                    application_id,
-                    owner_id,
-                    name: user.name,
-                    allowed_origins: user.allowed_origins,
-                    allowed_callback_urls: user.allowed_callback_urls,
-                }),
-                _ => None,
-            })
-            .collect::<Vec<_>>();
-
-        let pagination = crate::Pagination {
-            last_key: applications
-                .last()
-                .map(|application| application.application_id),
-            count: applications.len(),
-        };
-
-        Ok((applications, pagination))
+        Ok(applications)
+    }
+
+    /// Finds the applications the session has logged in to that want to be notified when it ends.
+    pub async fn backchannel_logout_targets(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        session_id: &Ulid,
+    ) -> Result<Vec<Self>> {
+        let applications = sqlx::query_as!(
+            Application,
+            r#"
+            SELECT
+                applications.application_id::uuid as "application_id!: Ulid",
+                applications.owner_id::uuid as "owner_id!: Ulid",
+                applications.name,
+                applications.allowed_origins,
+                applications.allowed_callback_urls,
+                applications.allowed_logout_urls,
+                applications.backchannel_logout_uri
+            FROM
+                applications
+                JOIN session_applications USING (application_id)
+            WHERE
+                session_applications.session_id::uuid = $1
+                AND applications.backchannel_logout_uri IS NOT NULL
+            "#,
+            session_id.to_sqlx_uuid(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(applications)
     }
 }
 
@@ -173,6 +251,8 @@ pub struct Builder {
     name: Option<String>,
     allowed_origins: Option<Vec<String>>,
     allowed_callback_urls: Option<Vec<String>>,
+    allowed_logout_urls: Option<Vec<String>>,
+    backchannel_logout_uri: Option<String>,
 }
 
 impl Builder {
@@ -200,6 +280,16 @@ impl Builder {
         self.allowed_callback_urls = Some(allowed_callback_urls);
         self
     }
+
+    pub fn allowed_logout_urls(mut self, allowed_logout_urls: Vec<String>) -> Self {
+        self.allowed_logout_urls = Some(allowed_logout_urls);
+        self
+    }
+
+    pub fn backchannel_logout_uri(mut self, backchannel_logout_uri: Option<String>) -> Self {
+        self.backchannel_logout_uri = backchannel_logout_uri;
+        self
+    }
 }
 
 impl crate::entity::Builder for Builder {
@@ -220,6 +310,7 @@ impl crate::entity::Builder for Builder {
         let allowed_callback_urls = self
             .allowed_callback_urls
             .ok_or_else(|| Error::ModelFieldsMissing("allowed_callback_urls"))?;
+        let allowed_logout_urls = self.allowed_logout_urls.unwrap_or_default();
 
         Ok(Application {
             application_id,
@@ -227,6 +318,8 @@ impl crate::entity::Builder for Builder {
             name,
             allowed_origins,
             allowed_callback_urls,
+            allowed_logout_urls,
+            backchannel_logout_uri: self.backchannel_logout_uri,
         })
     }
 }
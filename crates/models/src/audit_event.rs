@@ -0,0 +1,280 @@
+use crate::error::{Error, Result};
+use lockpad_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Something security relevant that happened, such as a login attempt or an administrator changing an account.
+/// Events are kept after what they refer to is deleted, only what identifies a deleted user is removed from them.
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEvent {
+    pub audit_event_id: Ulid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+    /// The user who performed the action, unknown for failed logins to accounts that don't exist.
+    pub actor_id: Option<Ulid>,
+    pub action: AuditAction,
+    /// The user, application or api key the action was performed on.
+    pub target_id: Option<Ulid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: AuditOutcome,
+    /// Context that doesn't fit elsewhere, such as the identifier a failed login used.
+    pub detail: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    /// Minting a token from an api key
+    ApiKeyLogin,
+    Register,
+    ApiKeyCreate,
+    ApiKeyRevoke,
+    ApplicationCreate,
+    ApplicationUpdate,
+    ApplicationDelete,
+    /// An administrator changing a user
+    UserUpdate,
+    /// An administrator or the user deleting the account, the tombstone left once it is gone
+    UserDelete,
+    /// An administrator or the user downloading everything stored about the user
+    UserExport,
+    /// An administrator replacing a user's password
+    PasswordReset,
+    /// The user changing their own password
+    PasswordChange,
+    /// The user changing or removing the email of their account
+    EmailChange,
+    /// Too many failed logins to an identifier or from an address
+    Lockout,
+    /// An administrator lifting a user's lockout
+    Unlock,
+    /// The user adding an authenticator app
+    MfaFactorCreate,
+    /// The user removing an authenticator app
+    MfaFactorDelete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// Narrows down a listing of audit events, every filter that is set must match.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor_id: Option<Ulid>,
+    pub target_id: Option<Ulid>,
+    pub action: Option<AuditAction>,
+    pub outcome: Option<AuditOutcome>,
+    /// Only events that occurred at or after this time
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    /// Only events that occurred before this time
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub until: Option<OffsetDateTime>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.actor_id
+            .is_none_or(|actor_id| event.actor_id == Some(actor_id))
+            && self
+                .target_id
+                .is_none_or(|target_id| event.target_id == Some(target_id))
+            && self.action.is_none_or(|action| event.action == action)
+            && self.outcome.is_none_or(|outcome| event.outcome == outcome)
+            && self.since.is_none_or(|since| event.occurred_at >= since)
+            && self.until.is_none_or(|until| event.occurred_at < until)
+    }
+}
+
+impl AuditEvent {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    pub async fn create(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                audit_events(audit_event_id, occurred_at, actor_id, action, target_id, ip_address, user_agent, outcome, detail)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            self.audit_event_id.to_sqlx_uuid(),
+            self.occurred_at,
+            self.actor_id.as_ref().map(Ulid::to_sqlx_uuid),
+            self.action as _,
+            self.target_id.as_ref().map(Ulid::to_sqlx_uuid),
+            self.ip_address,
+            self.user_agent,
+            self.outcome as _,
+            self.detail,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lists the events matching the filter, oldest first.
+    pub async fn query(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        filter: &AuditFilter,
+        pagination: crate::Pagination,
+    ) -> Result<(Vec<Self>, crate::Pagination)> {
+        let events = sqlx::query_as!(
+            AuditEvent,
+            r#"
+            SELECT
+                audit_event_id as "audit_event_id!: Ulid",
+                occurred_at,
+                actor_id as "actor_id: Ulid",
+                action as "action: AuditAction",
+                target_id as "target_id: Ulid",
+                ip_address,
+                user_agent,
+                outcome as "outcome: AuditOutcome",
+                detail
+            FROM
+                audit_events
+            WHERE
+                ($1::uuid IS NULL OR actor_id = $1)
+                AND ($2::uuid IS NULL OR target_id = $2)
+                AND ($3::text IS NULL OR action = $3)
+                AND ($4::text IS NULL OR outcome = $4)
+                AND ($5::timestamptz IS NULL OR occurred_at >= $5)
+                AND ($6::timestamptz IS NULL OR occurred_at < $6)
+                AND ($7::uuid IS NULL OR audit_event_id > $7)
+            ORDER BY
+                audit_event_id
+            LIMIT $8
+            "#,
+            filter.actor_id.as_ref().map(Ulid::to_sqlx_uuid),
+            filter.target_id.as_ref().map(Ulid::to_sqlx_uuid),
+            filter.action as _,
+            filter.outcome as _,
+            filter.since,
+            filter.until,
+            pagination.after(),
+            pagination.fetch_limit(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(pagination.page(events, |event| event.audit_event_id))
+    }
+
+    /// Forgets the addresses, user agents and identifier of a deleted user.
+    pub async fn redact_user(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+        identifier: &str,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            UPDATE audit_events
+            SET ip_address = NULL, user_agent = NULL
+            WHERE actor_id = $1 OR (target_id = $1 AND action IN ($2, $3))
+            "#,
+            user_id.to_sqlx_uuid(),
+            AuditAction::Login as _,
+            AuditAction::Lockout as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE audit_events
+            SET detail = NULL
+            WHERE action IN ($1, $2) AND (detail = $3 OR detail = 'identifier:' || $3)
+            "#,
+            AuditAction::Login as _,
+            AuditAction::Lockout as _,
+            identifier,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Builder {
+    actor_id: Option<Ulid>,
+    action: Option<AuditAction>,
+    target_id: Option<Ulid>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    outcome: Option<AuditOutcome>,
+    detail: Option<String>,
+}
+
+impl Builder {
+    pub fn actor_id(mut self, actor_id: Option<Ulid>) -> Self {
+        self.actor_id = actor_id;
+        self
+    }
+
+    pub fn action(mut self, action: AuditAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    pub fn target_id(mut self, target_id: Option<Ulid>) -> Self {
+        self.target_id = target_id;
+        self
+    }
+
+    pub fn ip_address(mut self, ip_address: Option<String>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Defaults to [`AuditOutcome::Success`].
+    pub fn outcome(mut self, outcome: AuditOutcome) -> Self {
+        self.outcome = Some(outcome);
+        self
+    }
+
+    pub fn detail(mut self, detail: Option<String>) -> Self {
+        self.detail = detail;
+        self
+    }
+}
+
+impl crate::entity::Builder for Builder {
+    type Item = AuditEvent;
+
+    fn build(self) -> Result<Self::Item> {
+        let action = self
+            .action
+            .ok_or_else(|| Error::ModelFieldsMissing("action"))?;
+
+        Ok(AuditEvent {
+            audit_event_id: Ulid::generate(),
+            occurred_at: OffsetDateTime::now_utc(),
+            actor_id: self.actor_id,
+            action,
+            target_id: self.target_id,
+            ip_address: self.ip_address,
+            user_agent: self.user_agent,
+            outcome: self.outcome.unwrap_or(AuditOutcome::Success),
+            detail: self.detail,
+        })
+    }
+}
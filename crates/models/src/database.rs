@@ -0,0 +1,209 @@
+//! The databases lockpad can store its data in, along with the migrations creating their schema.
+//! The migrations are embedded in the binary, so a database can be brought up to date without the sources.
+
+use crate::{
+    error::{Error, Result},
+    repository::Repositories,
+};
+use sqlx::migrate::{Migrate, Migrator};
+
+/// The migrations creating the postgres schema.
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
+
+/// The migrations creating the sqlite schema.
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("../../migrations/sqlite");
+
+/// Whether a single migration has been applied.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// A connection pool to one of the supported databases.
+#[derive(Clone)]
+pub enum Database {
+    Postgres(sqlx::PgPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::SqlitePool),
+}
+
+impl Database {
+    /// The repositories storing everything in this database.
+    pub fn repositories(&self) -> Repositories {
+        match self {
+            Self::Postgres(pool) => Repositories::postgres(pool.clone()),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => Repositories::sqlite(pool.clone()),
+        }
+    }
+
+    pub fn migrator(&self) -> &'static Migrator {
+        match self {
+            Self::Postgres(_) => &POSTGRES_MIGRATOR,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => &SQLITE_MIGRATOR,
+        }
+    }
+
+    /// Lists every migration this build knows about, oldest first.
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>> {
+        let applied = self.applied_versions().await?;
+
+        let status = self
+            .migrator()
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: applied.contains(&migration.version),
+            })
+            .collect();
+
+        Ok(status)
+    }
+
+    /// Applies every pending migration, returning the ones that were applied.
+    pub async fn migrate(&self) -> Result<Vec<MigrationStatus>> {
+        let pending: Vec<MigrationStatus> = self
+            .status()
+            .await?
+            .into_iter()
+            .filter(|migration| !migration.applied)
+            .collect();
+
+        match self {
+            Self::Postgres(pool) => self.migrator().run(pool).await?,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => self.migrator().run(pool).await?,
+        }
+
+        Ok(pending)
+    }
+
+    /// Reverts the most recently applied migration, returning it.
+    /// Returns `None` when no migration has been applied.
+    pub async fn rollback(&self) -> Result<Option<MigrationStatus>> {
+        let mut applied = self.applied_versions().await?;
+        applied.sort_unstable();
+        let Some(latest) = applied.pop() else {
+            return Ok(None);
+        };
+        let Some(migration) = self
+            .migrator()
+            .iter()
+            .find(|migration| migration.version == latest)
+        else {
+            return Err(Error::UnknownMigration(latest));
+        };
+        let target = applied.last().copied().unwrap_or(0);
+
+        match self {
+            Self::Postgres(pool) => self.migrator().undo(pool, target).await?,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => self.migrator().undo(pool, target).await?,
+        }
+
+        Ok(Some(MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: false,
+        }))
+    }
+
+    /// Fails unless every migration of this build has been applied.
+    pub async fn ensure_current(&self) -> Result<()> {
+        let applied = self.applied_versions().await?;
+        if let Some(version) = applied
+            .iter()
+            .find(|version| !self.migrator().version_exists(**version))
+        {
+            tracing::warn!(
+                version,
+                "the database has a migration this build doesn't know, it may be newer than this build"
+            );
+        }
+
+        let pending: Vec<i64> = self
+            .status()
+            .await?
+            .into_iter()
+            .filter(|migration| !migration.applied)
+            .map(|migration| migration.version)
+            .collect();
+        if !pending.is_empty() {
+            return Err(Error::PendingMigrations(pending));
+        }
+
+        Ok(())
+    }
+
+    async fn applied_versions(&self) -> Result<Vec<i64>> {
+        match self {
+            Self::Postgres(pool) => applied_versions(pool).await,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(pool) => applied_versions(pool).await,
+        }
+    }
+}
+
+/// Lists the versions of the migrations applied to the database.
+/// A migration that failed halfway leaves the database dirty, which needs fixing by hand.
+async fn applied_versions<DB>(pool: &sqlx::Pool<DB>) -> Result<Vec<i64>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut connection = pool.acquire().await?;
+    connection.ensure_migrations_table().await?;
+    if let Some(version) = connection.dirty_version().await? {
+        return Err(Error::DirtyMigration(version));
+    }
+
+    let applied = connection.list_applied_migrations().await?;
+    Ok(applied
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    async fn database() -> Database {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        Database::Sqlite(pool)
+    }
+
+    #[tokio::test]
+    async fn migrate_and_rollback() -> Result<()> {
+        let database = database().await;
+        assert!(matches!(
+            database.ensure_current().await,
+            Err(Error::PendingMigrations(_))
+        ));
+
+        let applied = database.migrate().await?;
+        assert!(!applied.is_empty());
+        database.ensure_current().await?;
+        assert!(database.migrate().await?.is_empty());
+
+        let reverted = database.rollback().await?.unwrap();
+        let status = database.status().await?;
+        let last = status.last().unwrap();
+        assert_eq!(last.version, reverted.version);
+        assert!(!last.applied);
+        assert!(database.ensure_current().await.is_err());
+
+        Ok(())
+    }
+}
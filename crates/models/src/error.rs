@@ -4,12 +4,51 @@ pub enum Error {
     SerdeJson(#[from] serde_json::Error),
 
     #[error(transparent)]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(sqlx::Error),
 
-    #[error("invalid unique field")]
-    InvalidUniqueField,
+    #[error(transparent)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    /// Another record already has the value of a field that must be unique
+    #[error("{0} is already taken")]
+    Conflict(&'static str),
     #[error("required fields missing")]
     ModelFieldsMissing(&'static str),
+    #[error("{0} refers to a record that does not exist")]
+    MissingReference(&'static str),
+    #[error("the database schema is behind, migrations {0:?} are pending")]
+    PendingMigrations(Vec<i64>),
+    #[error("migration {0} failed partway and must be fixed by hand")]
+    DirtyMigration(i64),
+    #[error("migration {0} was applied to the database but is unknown to this build")]
+    UnknownMigration(i64),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Unique columns besides the primary keys, which are reported as `id`.
+const UNIQUE_COLUMNS: &[&str] = &["identifier", "token_hash"];
+
+impl From<sqlx::Error> for Error {
+    /// Unique violations become [`Error::Conflict`], naming the column when it is known.
+    /// Postgres reports the constraint, such as `users_identifier_key`, sqlite only a message like `UNIQUE constraint failed: users.identifier`.
+    fn from(err: sqlx::Error) -> Self {
+        let Some(database_error) = err
+            .as_database_error()
+            .filter(|database_error| database_error.is_unique_violation())
+        else {
+            return Self::Sqlx(err);
+        };
+
+        let source = database_error
+            .constraint()
+            .unwrap_or_else(|| database_error.message());
+        let column = UNIQUE_COLUMNS
+            .iter()
+            .find(|column| source.contains(*column))
+            .copied()
+            .unwrap_or("id");
+
+        Self::Conflict(column)
+    }
+}
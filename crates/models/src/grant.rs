@@ -0,0 +1,140 @@
+use crate::error::Result;
+use lockpad_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// The scopes a user has consented to give an application.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Grant {
+    pub user_id: Ulid,
+    pub application_id: Ulid,
+    pub scopes: Vec<String>,
+
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl Grant {
+    pub fn new(user_id: Ulid, application_id: Ulid, scopes: Vec<String>) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            user_id,
+            application_id,
+            scopes,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub async fn by_user_and_application(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<Option<Self>> {
+        let grant = sqlx::query_as!(
+            Grant,
+            r#"
+            SELECT
+                user_id::uuid as "user_id!: Ulid",
+                application_id::uuid as "application_id!: Ulid",
+                scopes,
+                created_at,
+                updated_at
+            FROM
+                grants
+            WHERE
+                user_id::uuid = $1 AND application_id::uuid = $2
+            "#,
+            user_id.to_sqlx_uuid(),
+            application_id.to_sqlx_uuid(),
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(grant)
+    }
+
+    pub async fn by_user_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+    ) -> Result<Vec<Self>> {
+        let grants = sqlx::query_as!(
+            Grant,
+            r#"
+            SELECT
+                user_id::uuid as "user_id!: Ulid",
+                application_id::uuid as "application_id!: Ulid",
+                scopes,
+                created_at,
+                updated_at
+            FROM
+                grants
+            WHERE
+                user_id::uuid = $1
+            ORDER BY
+                application_id
+            "#,
+            user_id.to_sqlx_uuid(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(grants)
+    }
+
+    /// Stores the grant, replacing the scopes of an existing grant for the same application.
+    pub async fn save(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                grants(user_id, application_id, scopes, created_at, updated_at)
+            SELECT
+                user_id::uuid, application_id::uuid, scopes, created_at, updated_at
+            FROM(
+                VALUES($1, $2, $3::text[], $4::timestamptz, $5::timestamptz)
+            ) AS data(user_id, application_id, scopes, created_at, updated_at)
+            ON CONFLICT (user_id, application_id) DO UPDATE SET
+                scopes = EXCLUDED.scopes,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            self.user_id.queryable(),
+            self.application_id.queryable(),
+            &self.scopes,
+            self.created_at,
+            self.updated_at,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                grants
+            WHERE
+                user_id::uuid = $1 AND application_id::uuid = $2
+            "#,
+            user_id.to_sqlx_uuid(),
+            application_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Determines whether every requested scope has been granted.
+    pub fn covers(&self, scopes: &[String]) -> bool {
+        scopes.iter().all(|scope| self.scopes.contains(scope))
+    }
+}
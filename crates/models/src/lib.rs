@@ -3,19 +3,112 @@ use serde::{Deserialize, Serialize};
 
 pub mod api_key;
 pub mod application;
+pub mod audit_event;
+pub mod database;
 pub mod entity;
 pub mod error;
+pub mod grant;
+pub mod login_failure;
+pub mod mfa_factor;
+pub mod rate_limit;
+pub mod repository;
+pub mod session;
 pub mod user;
+pub mod user_data;
 
+/// Keyset pagination over a listing ordered by id.
+/// When querying, `last_key` is the id to continue after and `count` the page size.
+/// In results, `last_key` is the cursor for the next page, `None` on the last page, and `count` the number of items returned.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Pagination {
     pub last_key: Option<Ulid>,
     pub count: usize,
 }
 
+impl Pagination {
+    /// The first page with up to `count` items
+    pub fn first(count: usize) -> Self {
+        Self {
+            last_key: None,
+            count,
+        }
+    }
+
+    pub(crate) fn after(&self) -> Option<sqlx::types::Uuid> {
+        self.last_key.as_ref().map(Ulid::to_sqlx_uuid)
+    }
+
+    /// One more row than the page holds is fetched to tell whether another page follows.
+    pub(crate) fn fetch_limit(&self) -> i64 {
+        self.count.saturating_add(1).try_into().unwrap_or(i64::MAX)
+    }
+
+    /// Trims the rows fetched with [`Pagination::fetch_limit`] to a page and determines the next cursor.
+    pub(crate) fn page<T>(&self, mut items: Vec<T>, key: impl Fn(&T) -> Ulid) -> (Vec<T>, Self) {
+        let has_more = items.len() > self.count;
+        items.truncate(self.count);
+
+        let pagination = Self {
+            last_key: items.last().filter(|_| has_more).map(key),
+            count: items.len(),
+        };
+
+        (items, pagination)
+    }
+}
+
 #[derive(Debug)]
 pub struct User {
     pub user_id: Option<lockpad_ulid::Ulid>,
     // pub user_id: Option<sqlx::types::Uuid>,
     pub identifier: String,
 }
+
+/// Helpers for tests that need a database.
+#[cfg(test)]
+pub(crate) mod testing {
+    /// Connects to the database in `DATABASE_URL`.
+    /// Tests calling this are marked `#[ignore = "needs DATABASE_URL"]`, so they only run when asked for with `--include-ignored`.
+    pub(crate) async fn pool() -> sqlx::PgPool {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        sqlx::PgPool::connect(&url)
+            .await
+            .expect("database is reachable")
+    }
+
+    /// A fresh in-memory sqlite database with the schema applied.
+    #[cfg(feature = "sqlite")]
+    pub(crate) async fn sqlite_pool() -> sqlx::SqlitePool {
+        // every connection to `sqlite::memory:` opens a separate database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("in-memory sqlite opens");
+        crate::database::SQLITE_MIGRATOR
+            .run(&pool)
+            .await
+            .expect("sqlite migrations apply");
+
+        pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_cursor() {
+        let ids: Vec<Ulid> = (0..3).map(|_| Ulid::generate()).collect();
+
+        let (items, next) = Pagination::first(2).page(ids.clone(), |id| *id);
+        assert_eq!(items, ids[..2]);
+        assert_eq!(next.last_key, Some(ids[1]));
+        assert_eq!(next.count, 2);
+
+        let (items, next) = Pagination::first(3).page(ids.clone(), |id| *id);
+        assert_eq!(items, ids);
+        assert_eq!(next.last_key, None);
+    }
+}
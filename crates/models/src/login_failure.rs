@@ -0,0 +1,134 @@
+//! Failed logins of the login throttle, kept in Postgres so every server using the database shares them.
+//! A key's failures are stored as their count and the time of the last one.
+
+use crate::error::Result;
+use std::time::Duration;
+
+/// The failures counted against a key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Failures {
+    pub count: u32,
+    /// The time passed since the last failure
+    pub since_last: Duration,
+}
+
+/// Looks up the failures counted against the key.
+pub async fn failures(
+    pool: &sqlx::pool::Pool<sqlx::Postgres>,
+    key: &str,
+) -> Result<Option<Failures>> {
+    let failures = sqlx::query!(
+        r#"
+        SELECT
+            count,
+            EXTRACT(EPOCH FROM now() - last_failure)::float8 as "since_last!"
+        FROM
+            login_failures
+        WHERE
+            key = $1
+        "#,
+        key,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(failures.map(|failures| Failures {
+        count: failures.count.try_into().unwrap_or_default(),
+        since_last: Duration::from_secs_f64(failures.since_last.max(0.0)),
+    }))
+}
+
+/// Counts a failure against the key, returning the new count.
+/// The count starts over when the last failure is more than `forget_after` ago.
+pub async fn record_failure(
+    pool: &sqlx::pool::Pool<sqlx::Postgres>,
+    key: &str,
+    forget_after: Duration,
+) -> Result<u32> {
+    let count = sqlx::query_scalar!(
+        r#"
+        INSERT INTO
+            login_failures AS failure(key, count, last_failure)
+        VALUES
+            ($1, 1, now())
+        ON CONFLICT (key) DO UPDATE SET
+            count = CASE
+                WHEN failure.last_failure <= now() - make_interval(secs => $2) THEN 1
+                ELSE failure.count + 1
+            END,
+            last_failure = now()
+        RETURNING
+            count
+        "#,
+        key,
+        forget_after.as_secs_f64(),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count.try_into().unwrap_or_default())
+}
+
+/// Forgets the failures counted against the key.
+pub async fn clear(pool: &sqlx::pool::Pool<sqlx::Postgres>, key: &str) -> Result<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            login_failures
+        WHERE
+            key = $1
+        "#,
+        key,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Forgets the failures whose last one is more than `forget_after` ago.
+pub async fn purge(pool: &sqlx::pool::Pool<sqlx::Postgres>, forget_after: Duration) -> Result<u64> {
+    let purged = sqlx::query!(
+        r#"
+        DELETE FROM
+            login_failures
+        WHERE
+            last_failure <= now() - make_interval(secs => $1)
+        "#,
+        forget_after.as_secs_f64(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(purged.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use lockpad_ulid::Ulid;
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn counts_and_forgets() -> Result<()> {
+        let pool = testing::pool().await;
+        let key = format!("test:{}", Ulid::generate());
+        let forget_after = Duration::from_millis(200);
+
+        assert_eq!(failures(&pool, &key).await?, None);
+        assert_eq!(record_failure(&pool, &key, forget_after).await?, 1);
+        assert_eq!(record_failure(&pool, &key, forget_after).await?, 2);
+        let counted = failures(&pool, &key).await?.unwrap();
+        assert_eq!(counted.count, 2);
+        assert!(counted.since_last < forget_after);
+
+        tokio::time::sleep(forget_after).await;
+        assert_eq!(record_failure(&pool, &key, forget_after).await?, 1);
+
+        clear(&pool, &key).await?;
+        assert_eq!(failures(&pool, &key).await?, None);
+
+        Ok(())
+    }
+}
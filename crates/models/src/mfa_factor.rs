@@ -0,0 +1,128 @@
+use crate::error::Result;
+use lockpad_ulid::Ulid;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// An authenticator app the user proves on login besides their password, by entering the one-time code it shows.
+#[derive(Clone, Debug, Serialize, sqlx::FromRow)]
+pub struct MfaFactor {
+    pub mfa_factor_id: Ulid,
+    pub user_id: Ulid,
+    pub name: String,
+    /// The base32 encoded secret the codes are derived from, shared with the app
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// The time step of the last code accepted, codes of it and earlier steps are refused so none can be used twice
+    pub last_step: Option<i64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl MfaFactor {
+    /// A factor whose enrollment was confirmed with the code of `step`.
+    pub fn new(user_id: Ulid, name: String, secret: String, step: i64) -> Self {
+        Self {
+            mfa_factor_id: Ulid::generate(),
+            user_id,
+            name,
+            secret,
+            last_step: Some(step),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub async fn by_user_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+    ) -> Result<Vec<Self>> {
+        let factors = sqlx::query_as!(
+            MfaFactor,
+            r#"
+            SELECT
+                mfa_factor_id as "mfa_factor_id!: Ulid",
+                user_id as "user_id!: Ulid",
+                name,
+                secret,
+                last_step,
+                created_at
+            FROM
+                mfa_factors
+            WHERE
+                user_id = $1
+            ORDER BY
+                mfa_factor_id
+            "#,
+            user_id.to_sqlx_uuid(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(factors)
+    }
+
+    pub async fn create(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                mfa_factors(mfa_factor_id, user_id, name, secret, last_step, created_at)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+            "#,
+            self.mfa_factor_id.to_sqlx_uuid(),
+            self.user_id.to_sqlx_uuid(),
+            self.name,
+            self.secret,
+            self.last_step,
+            self.created_at,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that a code of `step` was accepted, returning `false` when one of it or a later step was accepted before.
+    pub async fn record_use(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        mfa_factor_id: &Ulid,
+        step: i64,
+    ) -> Result<bool> {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE
+                mfa_factors
+            SET
+                last_step = $2
+            WHERE
+                mfa_factor_id = $1 AND (last_step IS NULL OR last_step < $2)
+            "#,
+            mfa_factor_id.to_sqlx_uuid(),
+            step,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(updated.rows_affected() == 1)
+    }
+
+    pub async fn delete(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+        mfa_factor_id: &Ulid,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                mfa_factors
+            WHERE
+                user_id = $1 AND mfa_factor_id = $2
+            "#,
+            user_id.to_sqlx_uuid(),
+            mfa_factor_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
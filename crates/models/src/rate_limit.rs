@@ -0,0 +1,108 @@
+//! Token buckets of the rate limiter, kept in Postgres so every server using the database shares them.
+//! A bucket is stored as the time it will be full again, taking a token moves that time one refill interval further.
+
+use crate::error::Result;
+use std::time::Duration;
+
+/// Takes a token from the bucket, returning how long to wait for one when it is empty.
+/// A token is refilled every `interval`, and the bucket holds the one being taken plus as many as refill within `tolerance`.
+pub async fn take_token(
+    pool: &sqlx::pool::Pool<sqlx::Postgres>,
+    key: &str,
+    interval: Duration,
+    tolerance: Duration,
+) -> Result<Option<Duration>> {
+    let taken = sqlx::query!(
+        r#"
+        INSERT INTO
+            rate_limit_buckets AS bucket(key, full_at)
+        VALUES
+            ($1, now() + make_interval(secs => $2))
+        ON CONFLICT (key) DO UPDATE SET
+            full_at = GREATEST(bucket.full_at, now()) + make_interval(secs => $2)
+        WHERE
+            bucket.full_at - now() <= make_interval(secs => $3)
+        RETURNING
+            full_at
+        "#,
+        key,
+        interval.as_secs_f64(),
+        tolerance.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    if taken.is_some() {
+        return Ok(None);
+    }
+
+    // The bucket may have been purged in the meantime, in which case it is full again.
+    token_wait(pool, key, tolerance).await
+}
+
+/// How long to wait for a token in the bucket, `None` when one can be taken now.
+pub async fn token_wait(
+    pool: &sqlx::pool::Pool<sqlx::Postgres>,
+    key: &str,
+    tolerance: Duration,
+) -> Result<Option<Duration>> {
+    let wait = sqlx::query_scalar!(
+        r#"
+        SELECT
+            EXTRACT(EPOCH FROM full_at - now())::float8 - $2 as "wait!"
+        FROM
+            rate_limit_buckets
+        WHERE
+            key = $1
+        "#,
+        key,
+        tolerance.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(wait.filter(|wait| *wait > 0.0).map(Duration::from_secs_f64))
+}
+
+/// Forgets the buckets that have refilled completely, they are recreated full when needed again.
+pub async fn purge_full_buckets(pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<u64> {
+    let purged = sqlx::query!(
+        r#"
+        DELETE FROM
+            rate_limit_buckets
+        WHERE
+            full_at < now()
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(purged.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use lockpad_ulid::Ulid;
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn empties_and_refills() -> Result<()> {
+        let pool = testing::pool().await;
+        let key = format!("test:{}", Ulid::generate());
+        let interval = Duration::from_millis(200);
+        // two tokens, the one taken and one more
+        let tolerance = interval;
+
+        assert_eq!(take_token(&pool, &key, interval, tolerance).await?, None);
+        assert_eq!(take_token(&pool, &key, interval, tolerance).await?, None);
+        assert!(token_wait(&pool, &key, tolerance).await?.is_some());
+        let wait = take_token(&pool, &key, interval, tolerance).await?.unwrap();
+        assert!(wait <= interval);
+
+        tokio::time::sleep(wait).await;
+        assert_eq!(take_token(&pool, &key, interval, tolerance).await?, None);
+
+        Ok(())
+    }
+}
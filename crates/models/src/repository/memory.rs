@@ -0,0 +1,604 @@
+use super::{
+    ApiKeyRepository, ApplicationRepository, AuditEventRepository, GrantRepository,
+    MfaFactorRepository, SessionRepository, UserRepository,
+};
+use crate::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditAction, AuditEvent, AuditFilter},
+    error::{Error, Result},
+    grant::Grant,
+    mfa_factor::MfaFactor,
+    session::Session,
+    user::User,
+    Pagination,
+};
+use lockpad_ulid::Ulid;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Mutex, MutexGuard},
+};
+use time::OffsetDateTime;
+
+#[derive(Default)]
+struct Tables {
+    users: BTreeMap<Ulid, User>,
+    applications: BTreeMap<Ulid, Application>,
+    api_keys: BTreeMap<Ulid, ApiKey>,
+    sessions: BTreeMap<Ulid, Session>,
+    /// Pairs of session and application ids
+    session_applications: BTreeSet<(Ulid, Ulid)>,
+    /// Keyed by user and application id
+    grants: BTreeMap<(Ulid, Ulid), Grant>,
+    mfa_factors: BTreeMap<Ulid, MfaFactor>,
+    audit_events: BTreeMap<Ulid, AuditEvent>,
+}
+
+impl Tables {
+    /// Removes the application along with everything referring to it
+    fn delete_application(&mut self, application_id: &Ulid) {
+        self.applications.remove(application_id);
+        self.grants
+            .retain(|(_, granted_to), _| granted_to != application_id);
+        self.session_applications
+            .retain(|(_, logged_in_to)| logged_in_to != application_id);
+    }
+
+    /// Removes the session along with the record of the applications it logged in to
+    fn delete_session(&mut self, session_id: &Ulid) {
+        self.sessions.remove(session_id);
+        self.session_applications
+            .retain(|(session, _)| session != session_id);
+    }
+}
+
+/// Keeps the models in memory, following the same rules as the database.
+/// Nothing is persisted, so this is meant for tests and demos.
+#[derive(Default)]
+pub struct MemoryRepository {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryRepository {
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Takes a page of `items` ordered by id, the way the database queries do.
+fn paginate<'a, T: Clone + 'a>(
+    items: impl Iterator<Item = (&'a Ulid, &'a T)>,
+    pagination: Pagination,
+    key: impl Fn(&T) -> Ulid,
+) -> (Vec<T>, Pagination) {
+    let limit = usize::try_from(pagination.fetch_limit()).unwrap_or(usize::MAX);
+    let items = items
+        .filter(|(id, _)| pagination.last_key.is_none_or(|after| **id > after))
+        .take(limit)
+        .map(|(_, item)| item.clone())
+        .collect();
+
+    pagination.page(items, key)
+}
+
+fn unexpired(session: &Session) -> bool {
+    session.expires_at > OffsetDateTime::now_utc()
+}
+
+#[async_trait::async_trait]
+impl UserRepository for MemoryRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<User>> {
+        Ok(self.tables().users.get(id).cloned())
+    }
+
+    async fn by_identifier(&self, identifier: &str) -> Result<Option<User>> {
+        let tables = self.tables();
+        let user = tables
+            .users
+            .values()
+            .find(|user| user.identifier == identifier);
+
+        Ok(user.cloned())
+    }
+
+    async fn search(&self, term: &str, pagination: Pagination) -> Result<(Vec<User>, Pagination)> {
+        let term = term.to_lowercase();
+        let matches = |user: &User| {
+            user.identifier.to_lowercase().contains(&term)
+                || user
+                    .email
+                    .as_ref()
+                    .is_some_and(|email| email.to_lowercase().contains(&term))
+        };
+
+        let tables = self.tables();
+        let users = tables.users.iter().filter(|(_, user)| matches(user));
+
+        Ok(paginate(users, pagination, |user| user.user_id))
+    }
+
+    async fn create(&self, user: &User) -> Result<()> {
+        let mut tables = self.tables();
+        let taken = tables
+            .users
+            .values()
+            .any(|existing| existing.identifier == user.identifier);
+        if taken {
+            return Err(Error::Conflict("identifier"));
+        }
+        if tables.users.contains_key(&user.user_id) {
+            return Err(Error::Conflict("id"));
+        }
+
+        tables.users.insert(user.user_id, user.clone());
+        Ok(())
+    }
+
+    async fn update(&self, user: &User) -> Result<()> {
+        if let Some(existing) = self.tables().users.get_mut(&user.user_id) {
+            existing.secret = user.secret.clone();
+            existing.email = user.email.clone();
+            existing.admin = user.admin;
+            existing.disabled = user.disabled;
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_tokens(&self, id: &Ulid) -> Result<()> {
+        if let Some(existing) = self.tables().users.get_mut(id) {
+            existing.tokens_valid_after = Some(OffsetDateTime::now_utc());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, user: &User) -> Result<()> {
+        let mut tables = self.tables();
+        let user_id = user.user_id;
+
+        tables.users.remove(&user_id);
+        tables
+            .api_keys
+            .retain(|_, api_key| api_key.owner_id != user_id);
+        tables
+            .grants
+            .retain(|(granted_by, _), _| *granted_by != user_id);
+        tables
+            .mfa_factors
+            .retain(|_, factor| factor.user_id != user_id);
+
+        let applications: Vec<Ulid> = tables
+            .applications
+            .values()
+            .filter(|application| application.owner_id == user_id)
+            .map(|application| application.application_id)
+            .collect();
+        for application_id in applications {
+            tables.delete_application(&application_id);
+        }
+
+        let sessions: Vec<Ulid> = tables
+            .sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .map(|session| session.session_id)
+            .collect();
+        for session_id in sessions {
+            tables.delete_session(&session_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ApplicationRepository for MemoryRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<Application>> {
+        Ok(self.tables().applications.get(id).cloned())
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<Application>> {
+        let tables = self.tables();
+        let application = tables
+            .applications
+            .get(id)
+            .filter(|application| application.owner_id == *owner_id);
+
+        Ok(application.cloned())
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<Application>, Pagination)> {
+        let tables = self.tables();
+        let applications = tables
+            .applications
+            .iter()
+            .filter(|(_, application)| application.owner_id == owner_id);
+
+        Ok(paginate(applications, pagination, |application| {
+            application.application_id
+        }))
+    }
+
+    async fn all(&self) -> Result<Vec<Application>> {
+        Ok(self.tables().applications.values().cloned().collect())
+    }
+
+    async fn create(&self, application: &Application) -> Result<()> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&application.owner_id) {
+            return Err(Error::MissingReference("owner_id"));
+        }
+        if tables
+            .applications
+            .contains_key(&application.application_id)
+        {
+            return Err(Error::Conflict("id"));
+        }
+
+        tables
+            .applications
+            .insert(application.application_id, application.clone());
+        Ok(())
+    }
+
+    async fn update(&self, application: &Application) -> Result<()> {
+        let mut tables = self.tables();
+        if let Some(existing) = tables.applications.get_mut(&application.application_id) {
+            *existing = Application {
+                owner_id: existing.owner_id,
+                ..application.clone()
+            };
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, application: &Application) -> Result<()> {
+        self.tables()
+            .delete_application(&application.application_id);
+
+        Ok(())
+    }
+
+    async fn backchannel_logout_targets(&self, session_id: &Ulid) -> Result<Vec<Application>> {
+        let tables = self.tables();
+        let applications = tables
+            .session_applications
+            .iter()
+            .filter(|(session, _)| session == session_id)
+            .filter_map(|(_, application_id)| tables.applications.get(application_id))
+            .filter(|application| application.backchannel_logout_uri.is_some())
+            .cloned()
+            .collect();
+
+        Ok(applications)
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiKeyRepository for MemoryRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<ApiKey>> {
+        Ok(self.tables().api_keys.get(id).cloned())
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<ApiKey>> {
+        let tables = self.tables();
+        let api_key = tables
+            .api_keys
+            .get(id)
+            .filter(|api_key| api_key.owner_id == *owner_id);
+
+        Ok(api_key.cloned())
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<ApiKey>, Pagination)> {
+        let tables = self.tables();
+        let api_keys = tables
+            .api_keys
+            .iter()
+            .filter(|(_, api_key)| api_key.owner_id == owner_id);
+
+        Ok(paginate(api_keys, pagination, |api_key| api_key.api_key_id))
+    }
+
+    async fn all(&self) -> Result<Vec<ApiKey>> {
+        Ok(self.tables().api_keys.values().cloned().collect())
+    }
+
+    async fn create(&self, api_key: &ApiKey) -> Result<()> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&api_key.owner_id) {
+            return Err(Error::MissingReference("owner_id"));
+        }
+        if tables.api_keys.contains_key(&api_key.api_key_id) {
+            return Err(Error::Conflict("id"));
+        }
+
+        tables.api_keys.insert(api_key.api_key_id, api_key.clone());
+        Ok(())
+    }
+
+    async fn record_use(&self, api_key: &mut ApiKey) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        api_key.use_count = self.record_uses_by_id(&api_key.api_key_id, 1, now).await?;
+        api_key.last_used_at = Some(now);
+
+        Ok(())
+    }
+
+    async fn record_uses_by_id(
+        &self,
+        api_key_id: &Ulid,
+        uses: i64,
+        used_at: OffsetDateTime,
+    ) -> Result<i64> {
+        let mut tables = self.tables();
+        let api_key = tables
+            .api_keys
+            .get_mut(api_key_id)
+            .ok_or(Error::Sqlx(sqlx::Error::RowNotFound))?;
+
+        api_key.use_count += uses;
+        api_key.last_used_at = api_key.last_used_at.max(Some(used_at));
+
+        Ok(api_key.use_count)
+    }
+
+    async fn revoke(&self, api_key: &mut ApiKey) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        if let Some(stored) = self.tables().api_keys.get_mut(&api_key.api_key_id) {
+            stored.revoked_at.get_or_insert(now);
+        }
+        api_key.revoked_at.get_or_insert(now);
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionRepository for MemoryRepository {
+    async fn by_token_hash(&self, token_hash: &str) -> Result<Option<Session>> {
+        let tables = self.tables();
+        let session = tables
+            .sessions
+            .values()
+            .find(|session| session.token_hash == token_hash && unexpired(session));
+
+        Ok(session.cloned())
+    }
+
+    async fn by_id(&self, user_id: &Ulid, session_id: &Ulid) -> Result<Option<Session>> {
+        let tables = self.tables();
+        let session = tables
+            .sessions
+            .get(session_id)
+            .filter(|session| session.user_id == *user_id && unexpired(session));
+
+        Ok(session.cloned())
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Session>> {
+        let tables = self.tables();
+        let mut sessions: Vec<Session> = tables
+            .sessions
+            .values()
+            .filter(|session| session.user_id == *user_id && unexpired(session))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+        Ok(sessions)
+    }
+
+    async fn create(&self, session: &Session) -> Result<()> {
+        let mut tables = self.tables();
+        if !tables.users.contains_key(&session.user_id) {
+            return Err(Error::MissingReference("user_id"));
+        }
+
+        tables.sessions.insert(session.session_id, session.clone());
+        Ok(())
+    }
+
+    async fn delete(&self, session: &Session) -> Result<()> {
+        self.tables().delete_session(&session.session_id);
+
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        if !session.touch_due(now) {
+            return Ok(());
+        }
+
+        session.seen(now, ip_address, user_agent);
+        if let Some(stored) = self.tables().sessions.get_mut(&session.session_id) {
+            stored.last_seen_at = session.last_seen_at;
+            stored.ip_address.clone_from(&session.ip_address);
+            stored.user_agent.clone_from(&session.user_agent);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_by_user_id(&self, user_id: &Ulid) -> Result<()> {
+        let mut tables = self.tables();
+        let sessions: Vec<Ulid> = tables
+            .sessions
+            .values()
+            .filter(|session| session.user_id == *user_id)
+            .map(|session| session.session_id)
+            .collect();
+        for session_id in sessions {
+            tables.delete_session(&session_id);
+        }
+
+        Ok(())
+    }
+
+    async fn add_application(&self, session: &Session, application_id: &Ulid) -> Result<()> {
+        self.tables()
+            .session_applications
+            .insert((session.session_id, *application_id));
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl GrantRepository for MemoryRepository {
+    async fn by_user_and_application(
+        &self,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<Option<Grant>> {
+        let tables = self.tables();
+
+        Ok(tables.grants.get(&(*user_id, *application_id)).cloned())
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Grant>> {
+        let tables = self.tables();
+        let grants = tables
+            .grants
+            .values()
+            .filter(|grant| grant.user_id == *user_id)
+            .cloned()
+            .collect();
+
+        Ok(grants)
+    }
+
+    async fn save(&self, grant: &Grant) -> Result<()> {
+        let mut tables = self.tables();
+        let key = (grant.user_id, grant.application_id);
+
+        match tables.grants.get_mut(&key) {
+            Some(existing) => {
+                existing.scopes.clone_from(&grant.scopes);
+                existing.updated_at = grant.updated_at;
+            }
+            None => {
+                tables.grants.insert(key, grant.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: &Ulid, application_id: &Ulid) -> Result<()> {
+        self.tables().grants.remove(&(*user_id, *application_id));
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MfaFactorRepository for MemoryRepository {
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<MfaFactor>> {
+        let tables = self.tables();
+        let factors = tables
+            .mfa_factors
+            .values()
+            .filter(|factor| factor.user_id == *user_id)
+            .cloned()
+            .collect();
+
+        Ok(factors)
+    }
+
+    async fn create(&self, factor: &MfaFactor) -> Result<()> {
+        self.tables()
+            .mfa_factors
+            .insert(factor.mfa_factor_id, factor.clone());
+
+        Ok(())
+    }
+
+    async fn record_use(&self, mfa_factor_id: &Ulid, step: i64) -> Result<bool> {
+        let mut tables = self.tables();
+        let Some(factor) = tables.mfa_factors.get_mut(mfa_factor_id) else {
+            return Ok(false);
+        };
+        if factor.last_step.is_some_and(|last_step| last_step >= step) {
+            return Ok(false);
+        }
+        factor.last_step = Some(step);
+
+        Ok(true)
+    }
+
+    async fn delete(&self, user_id: &Ulid, mfa_factor_id: &Ulid) -> Result<()> {
+        let mut tables = self.tables();
+        if tables
+            .mfa_factors
+            .get(mfa_factor_id)
+            .is_some_and(|factor| factor.user_id == *user_id)
+        {
+            tables.mfa_factors.remove(mfa_factor_id);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditEventRepository for MemoryRepository {
+    async fn create(&self, event: &AuditEvent) -> Result<()> {
+        self.tables()
+            .audit_events
+            .insert(event.audit_event_id, event.clone());
+
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditEvent>, Pagination)> {
+        let tables = self.tables();
+        let events = tables
+            .audit_events
+            .iter()
+            .filter(|(_, event)| filter.matches(event));
+
+        Ok(paginate(events, pagination, |event| event.audit_event_id))
+    }
+
+    async fn redact_user(&self, user_id: &Ulid, identifier: &str) -> Result<()> {
+        let lockout_key = format!("identifier:{identifier}");
+        for event in self.tables().audit_events.values_mut() {
+            let login = matches!(event.action, AuditAction::Login | AuditAction::Lockout);
+            if event.actor_id == Some(*user_id) || (login && event.target_id == Some(*user_id)) {
+                event.ip_address = None;
+                event.user_agent = None;
+            }
+            if login
+                && (event.detail.as_deref() == Some(identifier)
+                    || event.detail.as_deref() == Some(lockout_key.as_str()))
+            {
+                event.detail = None;
+            }
+        }
+
+        Ok(())
+    }
+}
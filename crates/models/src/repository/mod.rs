@@ -0,0 +1,781 @@
+//! Storage for the models, independent of the database behind it.
+//! [`Repositories::postgres`] is used in production, [`Repositories::memory`] keeps everything in memory for tests and demos.
+//! With the `sqlite` feature, [`Repositories::sqlite`] suits small deployments without Postgres.
+
+use crate::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditEvent, AuditFilter},
+    error::Result,
+    grant::Grant,
+    mfa_factor::MfaFactor,
+    session::Session,
+    user::User,
+    Pagination,
+};
+use lockpad_ulid::Ulid;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+mod memory;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use memory::MemoryRepository;
+pub use postgres::PostgresRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRepository;
+
+#[async_trait::async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<User>>;
+    async fn by_identifier(&self, identifier: &str) -> Result<Option<User>>;
+    /// Finds users whose identifier or email contains the search term, one page at a time.
+    async fn search(&self, term: &str, pagination: Pagination) -> Result<(Vec<User>, Pagination)>;
+    async fn create(&self, user: &User) -> Result<()>;
+    /// Saves changes to everything but the user's id, identifier and token cutoff.
+    async fn update(&self, user: &User) -> Result<()>;
+    /// Refuses every token issued to the user until now.
+    async fn revoke_tokens(&self, id: &Ulid) -> Result<()>;
+    /// Deletes the user along with everything they own.
+    async fn delete(&self, user: &User) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+pub trait ApplicationRepository: Send + Sync {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<Application>>;
+    /// Finds an application only if it belongs to the owner.
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<Application>>;
+    /// Lists the owner's applications.
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<Application>, Pagination)>;
+    /// Lists every application, regardless of owner.
+    async fn all(&self) -> Result<Vec<Application>>;
+    async fn create(&self, application: &Application) -> Result<()>;
+    /// Saves changes to everything but the application's id and owner.
+    async fn update(&self, application: &Application) -> Result<()>;
+    /// Deletes the application along with the grants given to it.
+    async fn delete(&self, application: &Application) -> Result<()>;
+    /// Finds the applications the session has logged in to that want to be notified when it ends.
+    async fn backchannel_logout_targets(&self, session_id: &Ulid) -> Result<Vec<Application>>;
+}
+
+#[async_trait::async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<ApiKey>>;
+    /// Finds an api key only if it belongs to the owner.
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<ApiKey>>;
+    /// Lists the owner's api keys.
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<ApiKey>, Pagination)>;
+    /// Lists every api key, regardless of owner.
+    async fn all(&self) -> Result<Vec<ApiKey>>;
+    async fn create(&self, api_key: &ApiKey) -> Result<()>;
+    /// Records that the key was used.
+    async fn record_use(&self, api_key: &mut ApiKey) -> Result<()>;
+    /// Records uses of the key counted elsewhere without loading it, returning the new use count.
+    /// `used_at` is the time of the last of them.
+    async fn record_uses_by_id(
+        &self,
+        api_key_id: &Ulid,
+        uses: i64,
+        used_at: OffsetDateTime,
+    ) -> Result<i64>;
+    /// Stops the key from being used. The key is kept so its usage remains visible.
+    async fn revoke(&self, api_key: &mut ApiKey) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+pub trait SessionRepository: Send + Sync {
+    /// Looks up a session that has not yet expired.
+    async fn by_token_hash(&self, token_hash: &str) -> Result<Option<Session>>;
+    /// Looks up a session of the given user that has not yet expired.
+    async fn by_id(&self, user_id: &Ulid, session_id: &Ulid) -> Result<Option<Session>>;
+    /// Lists the sessions of a user that have not yet expired, most recently used first.
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Session>>;
+    async fn create(&self, session: &Session) -> Result<()>;
+    async fn delete(&self, session: &Session) -> Result<()>;
+    /// Records that the session was just used, and from where.
+    /// To avoid writing on every request, this only happens once a minute.
+    async fn touch(
+        &self,
+        session: &mut Session,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<()>;
+    /// Ends every session belonging to the user.
+    async fn delete_by_user_id(&self, user_id: &Ulid) -> Result<()>;
+    /// Records that the session was used to log in to an application.
+    async fn add_application(&self, session: &Session, application_id: &Ulid) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+pub trait GrantRepository: Send + Sync {
+    async fn by_user_and_application(
+        &self,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<Option<Grant>>;
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Grant>>;
+    /// Stores the grant, replacing the scopes of an existing grant for the same application.
+    async fn save(&self, grant: &Grant) -> Result<()>;
+    async fn delete(&self, user_id: &Ulid, application_id: &Ulid) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+pub trait MfaFactorRepository: Send + Sync {
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<MfaFactor>>;
+    async fn create(&self, factor: &MfaFactor) -> Result<()>;
+    /// Records that a code of `step` was accepted, returning `false` when one of it or a later step was accepted before.
+    async fn record_use(&self, mfa_factor_id: &Ulid, step: i64) -> Result<bool>;
+    async fn delete(&self, user_id: &Ulid, mfa_factor_id: &Ulid) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+pub trait AuditEventRepository: Send + Sync {
+    /// Appends the event to the log, events are only changed afterwards to forget a deleted user.
+    async fn create(&self, event: &AuditEvent) -> Result<()>;
+    /// Lists the events matching the filter, oldest first.
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditEvent>, Pagination)>;
+    /// Forgets what identifies a deleted user, the address and user agent of events they performed and of logins and lockouts of their account,
+    /// and their identifier in the detail of failed logins and lockouts.
+    async fn redact_user(&self, user_id: &Ulid, identifier: &str) -> Result<()>;
+}
+
+/// Every repository the server needs, sharing one backend.
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub applications: Arc<dyn ApplicationRepository>,
+    pub api_keys: Arc<dyn ApiKeyRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub grants: Arc<dyn GrantRepository>,
+    pub mfa_factors: Arc<dyn MfaFactorRepository>,
+    pub audit_events: Arc<dyn AuditEventRepository>,
+}
+
+impl Repositories {
+    /// Store everything in Postgres
+    pub fn postgres(pool: sqlx::pool::Pool<sqlx::Postgres>) -> Self {
+        Self::from_backend(Arc::new(PostgresRepository::new(pool)))
+    }
+
+    /// Store everything in SQLite. The schema is created by [`crate::database::SQLITE_MIGRATOR`].
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(pool: sqlx::pool::Pool<sqlx::Sqlite>) -> Self {
+        Self::from_backend(Arc::new(SqliteRepository::new(pool)))
+    }
+
+    /// Store everything in memory, it is lost when the process exits
+    pub fn memory() -> Self {
+        Self::from_backend(Arc::new(MemoryRepository::default()))
+    }
+
+    fn from_backend<B>(backend: Arc<B>) -> Self
+    where
+        B: UserRepository
+            + ApplicationRepository
+            + ApiKeyRepository
+            + SessionRepository
+            + GrantRepository
+            + MfaFactorRepository
+            + AuditEventRepository
+            + 'static,
+    {
+        Self {
+            users: backend.clone(),
+            applications: backend.clone(),
+            api_keys: backend.clone(),
+            sessions: backend.clone(),
+            grants: backend.clone(),
+            mfa_factors: backend.clone(),
+            audit_events: backend,
+        }
+    }
+}
+
+/// The same suite runs against every backend.
+/// The Postgres tests need a database in `DATABASE_URL`, so they are ignored unless run with `--include-ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entity::Builder as _, error::Error, testing};
+    use time::Duration;
+
+    async fn user(repositories: &Repositories, prefix: &str) -> User {
+        let user = User::builder()
+            .identifier(format!("{prefix}-{}", Ulid::generate()))
+            .secret("not a hash".to_string())
+            .build()
+            .unwrap();
+        repositories.users.create(&user).await.unwrap();
+
+        user
+    }
+
+    async fn application(
+        repositories: &Repositories,
+        owner: &User,
+        backchannel_logout_uri: Option<String>,
+    ) -> Application {
+        let application = Application::builder()
+            .owner_id(owner.user_id)
+            .name("test".to_string())
+            .allowed_origins(vec!["https://example.com".to_string()])
+            .allowed_callback_urls(vec![])
+            .backchannel_logout_uri(backchannel_logout_uri)
+            .build()
+            .unwrap();
+        repositories
+            .applications
+            .create(&application)
+            .await
+            .unwrap();
+
+        application
+    }
+
+    async fn api_key(repositories: &Repositories, owner: &User) -> ApiKey {
+        let api_key = ApiKey::builder()
+            .owner_id(owner.user_id)
+            .name("test".to_string())
+            .secret("not a hash".to_string())
+            .scopes(vec!["read".to_string()])
+            .build()
+            .unwrap();
+        repositories.api_keys.create(&api_key).await.unwrap();
+
+        api_key
+    }
+
+    async fn owner_scoping(repositories: Repositories) -> Result<()> {
+        let alice = user(&repositories, "test").await;
+        let bob = user(&repositories, "test").await;
+
+        let mut api_keys = Vec::new();
+        let mut applications = Vec::new();
+        for owner in [&alice, &bob] {
+            api_keys.push(api_key(&repositories, owner).await.api_key_id);
+            applications.push(application(&repositories, owner, None).await.application_id);
+        }
+
+        let (keys, _) = repositories
+            .api_keys
+            .query(alice.user_id, Pagination::first(100))
+            .await?;
+        let ids: Vec<Ulid> = keys.iter().map(|key| key.api_key_id).collect();
+        assert_eq!(ids, vec![api_keys[0]]);
+        assert_eq!(keys[0].scopes, vec!["read".to_string()]);
+        let found = repositories
+            .api_keys
+            .by_owner_and_id(&alice.user_id, &api_keys[1])
+            .await?;
+        assert!(found.is_none());
+
+        let (found, _) = repositories
+            .applications
+            .query(bob.user_id, Pagination::first(100))
+            .await?;
+        let ids: Vec<Ulid> = found.iter().map(|app| app.application_id).collect();
+        assert_eq!(ids, vec![applications[1]]);
+        assert_eq!(found[0].allowed_origins, vec!["https://example.com"]);
+        let found = repositories
+            .applications
+            .by_owner_and_id(&bob.user_id, &applications[0])
+            .await?;
+        assert!(found.is_none());
+
+        // Deleting a user removes everything they own.
+        repositories.users.delete(&alice).await?;
+        assert!(repositories.api_keys.by_id(&api_keys[0]).await?.is_none());
+        assert!(repositories
+            .applications
+            .by_id(&applications[0])
+            .await?
+            .is_none());
+
+        repositories.users.delete(&bob).await?;
+        Ok(())
+    }
+
+    async fn pagination(repositories: Repositories) -> Result<()> {
+        // Other tests may share the database, so only this test's users match the search.
+        let prefix = Ulid::generate().to_string();
+        let mut users = Vec::new();
+        for _ in 0..3 {
+            users.push(user(&repositories, &prefix).await);
+        }
+        // Ids created within the same millisecond aren't ordered by creation.
+        let mut ids: Vec<Ulid> = users.iter().map(|user| user.user_id).collect();
+        ids.sort();
+
+        let (page, next) = repositories
+            .users
+            .search(&prefix, Pagination::first(2))
+            .await?;
+        assert_eq!(page.len(), 2);
+        assert_eq!(next.last_key, Some(ids[1]));
+
+        let (page, next) = repositories
+            .users
+            .search(&prefix.to_lowercase(), next)
+            .await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].user_id, ids[2]);
+        assert_eq!(next.last_key, None);
+
+        for user in &users {
+            repositories.users.delete(user).await?;
+        }
+        Ok(())
+    }
+
+    async fn user_updates(repositories: Repositories) -> Result<()> {
+        let mut user = user(&repositories, "test").await;
+        user.email = Some("test@example.com".to_string());
+        user.admin = true;
+        repositories.users.update(&user).await?;
+
+        let found = repositories
+            .users
+            .by_identifier(&user.identifier)
+            .await?
+            .unwrap();
+        assert_eq!(found.user_id, user.user_id);
+        assert_eq!(found.email.as_deref(), Some("test@example.com"));
+        assert!(found.admin);
+        assert!(!found.disabled);
+        assert!(found.tokens_valid_after.is_none());
+
+        repositories.users.revoke_tokens(&user.user_id).await?;
+        let found = repositories.users.by_id(&user.user_id).await?.unwrap();
+        assert!(found.tokens_valid_after.is_some());
+        // saving other changes keeps the cutoff
+        repositories.users.update(&user).await?;
+        let found = repositories.users.by_id(&user.user_id).await?.unwrap();
+        assert!(found.tokens_valid_after.is_some());
+
+        // identifiers are unique
+        let mut duplicate = User::builder()
+            .identifier(user.identifier.clone())
+            .secret("not a hash".to_string())
+            .build()?;
+        duplicate.user_id = Ulid::generate();
+        assert!(matches!(
+            repositories.users.create(&duplicate).await,
+            Err(Error::Conflict("identifier"))
+        ));
+
+        repositories.users.delete(&user).await?;
+        Ok(())
+    }
+
+    async fn api_key_usage(repositories: Repositories) -> Result<()> {
+        let owner = user(&repositories, "test").await;
+        let mut api_key = api_key(&repositories, &owner).await;
+
+        repositories.api_keys.record_use(&mut api_key).await?;
+        let used_at = api_key.last_used_at.unwrap();
+        // A late report of earlier uses counts, without moving the last use back.
+        let count = repositories
+            .api_keys
+            .record_uses_by_id(&api_key.api_key_id, 3, used_at - Duration::hours(1))
+            .await?;
+        assert_eq!(count, 4);
+
+        repositories.api_keys.revoke(&mut api_key).await?;
+        let revoked_at = api_key.revoked_at.unwrap();
+        repositories.api_keys.revoke(&mut api_key).await?;
+
+        let found = repositories
+            .api_keys
+            .by_id(&api_key.api_key_id)
+            .await?
+            .unwrap();
+        assert_eq!(found.use_count, 4);
+        assert!(found.last_used_at.unwrap() > used_at - Duration::minutes(1));
+        assert!((found.revoked_at.unwrap() - revoked_at).abs() < Duration::milliseconds(1));
+        assert!(!found.is_active());
+
+        repositories.users.delete(&owner).await?;
+        Ok(())
+    }
+
+    async fn sessions(repositories: Repositories) -> Result<()> {
+        let owner = user(&repositories, "test").await;
+        let notified = application(
+            &repositories,
+            &owner,
+            Some("https://example.com/logout".to_string()),
+        )
+        .await;
+        let silent = application(&repositories, &owner, None).await;
+
+        let mut session = crate::session::Session::builder()
+            .user_id(owner.user_id)
+            .token_hash(Ulid::generate().to_string())
+            .lifetime(Duration::hours(1))
+            .build()?;
+        // Pretend the session was last used a while ago, so touching it is recorded.
+        session.last_seen_at -= Duration::minutes(5);
+        repositories.sessions.create(&session).await?;
+
+        let expired = crate::session::Session::builder()
+            .user_id(owner.user_id)
+            .token_hash(Ulid::generate().to_string())
+            .lifetime(Duration::seconds(-1))
+            .build()?;
+        repositories.sessions.create(&expired).await?;
+        let found = repositories
+            .sessions
+            .by_token_hash(&expired.token_hash)
+            .await?;
+        assert!(found.is_none());
+
+        let mut found = repositories
+            .sessions
+            .by_token_hash(&session.token_hash)
+            .await?
+            .unwrap();
+        repositories
+            .sessions
+            .touch(&mut found, Some("127.0.0.1".to_string()), None)
+            .await?;
+        let sessions = repositories.sessions.by_user_id(&owner.user_id).await?;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].ip_address.as_deref(), Some("127.0.0.1"));
+        assert!(sessions[0].last_seen_at > session.last_seen_at);
+
+        for application in [&notified, &silent] {
+            repositories
+                .sessions
+                .add_application(&session, &application.application_id)
+                .await?;
+        }
+        repositories
+            .sessions
+            .add_application(&session, &notified.application_id)
+            .await?;
+        let targets = repositories
+            .applications
+            .backchannel_logout_targets(&session.session_id)
+            .await?;
+        let ids: Vec<Ulid> = targets.iter().map(|app| app.application_id).collect();
+        assert_eq!(ids, vec![notified.application_id]);
+
+        repositories
+            .sessions
+            .delete_by_user_id(&owner.user_id)
+            .await?;
+        let found = repositories
+            .sessions
+            .by_id(&owner.user_id, &session.session_id)
+            .await?;
+        assert!(found.is_none());
+
+        repositories.users.delete(&owner).await?;
+        Ok(())
+    }
+
+    async fn grants(repositories: Repositories) -> Result<()> {
+        let owner = user(&repositories, "test").await;
+        let application = application(&repositories, &owner, None).await;
+
+        let grant = Grant::new(
+            owner.user_id,
+            application.application_id,
+            vec!["openid".to_string()],
+        );
+        repositories.grants.save(&grant).await?;
+        let widened = Grant::new(
+            owner.user_id,
+            application.application_id,
+            vec!["openid".to_string(), "email".to_string()],
+        );
+        repositories.grants.save(&widened).await?;
+
+        let grants = repositories.grants.by_user_id(&owner.user_id).await?;
+        assert_eq!(grants.len(), 1);
+        assert!(grants[0].covers(&["email".to_string()]));
+
+        repositories
+            .grants
+            .delete(&owner.user_id, &application.application_id)
+            .await?;
+        let found = repositories
+            .grants
+            .by_user_and_application(&owner.user_id, &application.application_id)
+            .await?;
+        assert!(found.is_none());
+
+        repositories.users.delete(&owner).await?;
+        Ok(())
+    }
+
+    async fn audit_events(repositories: Repositories) -> Result<()> {
+        use crate::audit_event::{AuditAction, AuditOutcome};
+
+        // Other tests may share the database, so only this test's actor is filtered on.
+        let actor_id = Ulid::generate();
+        let mut events = Vec::new();
+        for (action, outcome) in [
+            (AuditAction::Login, AuditOutcome::Failure),
+            (AuditAction::Login, AuditOutcome::Success),
+            (AuditAction::ApiKeyCreate, AuditOutcome::Success),
+        ] {
+            let event = AuditEvent::builder()
+                .actor_id(Some(actor_id))
+                .action(action)
+                .outcome(outcome)
+                .ip_address(Some("127.0.0.1".to_string()))
+                .build()?;
+            repositories.audit_events.create(&event).await?;
+            events.push(event);
+        }
+        events.sort_by_key(|event| event.audit_event_id);
+
+        let by_actor = AuditFilter {
+            actor_id: Some(actor_id),
+            ..Default::default()
+        };
+        let (page, next) = repositories
+            .audit_events
+            .query(&by_actor, Pagination::first(2))
+            .await?;
+        assert_eq!(page.len(), 2);
+        let (page, next) = repositories.audit_events.query(&by_actor, next).await?;
+        assert_eq!(page[0].audit_event_id, events[2].audit_event_id);
+        assert_eq!(page[0].ip_address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(next.last_key, None);
+
+        let failed_logins = AuditFilter {
+            action: Some(AuditAction::Login),
+            outcome: Some(AuditOutcome::Failure),
+            ..by_actor.clone()
+        };
+        let (page, _) = repositories
+            .audit_events
+            .query(&failed_logins, Pagination::first(10))
+            .await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].outcome, AuditOutcome::Failure);
+
+        let later = AuditFilter {
+            since: Some(OffsetDateTime::now_utc() + Duration::minutes(1)),
+            ..by_actor
+        };
+        let (page, _) = repositories
+            .audit_events
+            .query(&later, Pagination::first(10))
+            .await?;
+        assert!(page.is_empty());
+
+        Ok(())
+    }
+
+    async fn audit_redaction(repositories: Repositories) -> Result<()> {
+        use crate::audit_event::{AuditAction, AuditOutcome};
+
+        // Other tests may share the database, so only events from this test on are looked at.
+        let since = OffsetDateTime::now_utc() - Duration::seconds(1);
+        let user = user(&repositories, "redacted").await;
+        let other = failed_login(&repositories, "other").await;
+        let admin_id = Ulid::generate();
+        let lockout_key = format!("identifier:{}", user.identifier);
+        for (actor_id, action, target_id, detail) in [
+            (
+                Some(user.user_id),
+                AuditAction::Login,
+                Some(user.user_id),
+                None,
+            ),
+            (
+                None,
+                AuditAction::Login,
+                Some(user.user_id),
+                Some(user.identifier.clone()),
+            ),
+            (
+                None,
+                AuditAction::Login,
+                None,
+                Some(user.identifier.clone()),
+            ),
+            (
+                None,
+                AuditAction::Lockout,
+                Some(user.user_id),
+                Some(lockout_key),
+            ),
+            (
+                Some(admin_id),
+                AuditAction::UserUpdate,
+                Some(user.user_id),
+                Some("admin".to_string()),
+            ),
+        ] {
+            let event = AuditEvent::builder()
+                .actor_id(actor_id)
+                .action(action)
+                .target_id(target_id)
+                .outcome(AuditOutcome::Failure)
+                .ip_address(Some("192.0.2.1".to_string()))
+                .user_agent(Some("curl".to_string()))
+                .detail(detail)
+                .build()?;
+            repositories.audit_events.create(&event).await?;
+        }
+
+        repositories.users.delete(&user).await?;
+        repositories
+            .audit_events
+            .redact_user(&user.user_id, &user.identifier)
+            .await?;
+
+        let mut events = Vec::new();
+        for filter in [
+            AuditFilter {
+                target_id: Some(user.user_id),
+                ..Default::default()
+            },
+            AuditFilter {
+                action: Some(AuditAction::Login),
+                since: Some(since),
+                ..Default::default()
+            },
+        ] {
+            let (page, _) = repositories
+                .audit_events
+                .query(&filter, Pagination::first(1000))
+                .await?;
+            events.extend(page);
+        }
+        for event in &events {
+            if event.actor_id == Some(admin_id) {
+                assert_eq!(event.ip_address.as_deref(), Some("192.0.2.1"));
+                assert_eq!(event.detail.as_deref(), Some("admin"));
+                continue;
+            }
+            let detail = event.detail.as_deref().unwrap_or_default();
+            assert!(!detail.contains(&user.identifier), "{event:?}");
+            if event.target_id == Some(user.user_id) {
+                assert_eq!(event.ip_address, None, "{event:?}");
+                assert_eq!(event.user_agent, None, "{event:?}");
+            }
+        }
+        let untouched = events
+            .iter()
+            .find(|event| event.detail.as_deref() == Some(other.as_str()))
+            .expect("another user's failed login");
+        assert_eq!(untouched.ip_address.as_deref(), Some("192.0.2.1"));
+
+        Ok(())
+    }
+
+    /// Records a failed login for an identifier that doesn't belong to anyone and returns the identifier.
+    async fn failed_login(repositories: &Repositories, prefix: &str) -> String {
+        use crate::audit_event::{AuditAction, AuditOutcome};
+
+        let identifier = format!("{prefix}-{}", Ulid::generate());
+        let event = AuditEvent::builder()
+            .action(AuditAction::Login)
+            .outcome(AuditOutcome::Failure)
+            .ip_address(Some("192.0.2.1".to_string()))
+            .detail(Some(identifier.clone()))
+            .build()
+            .unwrap();
+        repositories.audit_events.create(&event).await.unwrap();
+
+        identifier
+    }
+
+    async fn mfa_factors(repositories: Repositories) -> Result<()> {
+        use crate::mfa_factor::MfaFactor;
+
+        let owner = user(&repositories, "mfa").await;
+        let other = user(&repositories, "mfa-other").await;
+        let factor = MfaFactor::new(owner.user_id, "phone".to_string(), "SECRET".to_string(), 5);
+        repositories.mfa_factors.create(&factor).await?;
+
+        let factors = repositories.mfa_factors.by_user_id(&owner.user_id).await?;
+        assert_eq!(factors.len(), 1);
+        assert_eq!(factors[0].secret, "SECRET");
+        assert_eq!(factors[0].last_step, Some(5));
+        assert!(repositories
+            .mfa_factors
+            .by_user_id(&other.user_id)
+            .await?
+            .is_empty());
+
+        // codes are accepted once, and never after a later one
+        let id = factor.mfa_factor_id;
+        assert!(!repositories.mfa_factors.record_use(&id, 5).await?);
+        assert!(repositories.mfa_factors.record_use(&id, 7).await?);
+        assert!(!repositories.mfa_factors.record_use(&id, 6).await?);
+
+        repositories.mfa_factors.delete(&other.user_id, &id).await?;
+        assert_eq!(
+            repositories
+                .mfa_factors
+                .by_user_id(&owner.user_id)
+                .await?
+                .len(),
+            1
+        );
+        repositories.users.delete(&owner).await?;
+        assert!(repositories
+            .mfa_factors
+            .by_user_id(&owner.user_id)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    /// Runs every test of the suite against the repositories `$repositories` evaluates to.
+    /// Attributes given before the backend's name are added to each of its tests.
+    macro_rules! backend_tests {
+        ($(#[$attr:meta])* $backend:ident, $repositories:expr) => {
+            mod $backend {
+                use super::*;
+
+                backend_tests!(@test [$(#[$attr])*] $repositories, owner_scoping);
+                backend_tests!(@test [$(#[$attr])*] $repositories, pagination);
+                backend_tests!(@test [$(#[$attr])*] $repositories, user_updates);
+                backend_tests!(@test [$(#[$attr])*] $repositories, api_key_usage);
+                backend_tests!(@test [$(#[$attr])*] $repositories, sessions);
+                backend_tests!(@test [$(#[$attr])*] $repositories, grants);
+                backend_tests!(@test [$(#[$attr])*] $repositories, audit_events);
+                backend_tests!(@test [$(#[$attr])*] $repositories, audit_redaction);
+                backend_tests!(@test [$(#[$attr])*] $repositories, mfa_factors);
+            }
+        };
+        (@test [$(#[$attr:meta])*] $repositories:expr, $test:ident) => {
+            #[tokio::test]
+            $(#[$attr])*
+            async fn $test() -> Result<()> {
+                super::$test($repositories).await
+            }
+        };
+    }
+
+    backend_tests!(memory, Repositories::memory());
+    backend_tests!(
+        #[ignore = "needs DATABASE_URL"]
+        postgres,
+        Repositories::postgres(testing::pool().await)
+    );
+    #[cfg(feature = "sqlite")]
+    backend_tests!(sqlite, Repositories::sqlite(testing::sqlite_pool().await));
+}
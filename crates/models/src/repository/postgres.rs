@@ -0,0 +1,244 @@
+use super::{
+    ApiKeyRepository, ApplicationRepository, AuditEventRepository, GrantRepository,
+    MfaFactorRepository, SessionRepository, UserRepository,
+};
+use crate::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditEvent, AuditFilter},
+    error::Result,
+    grant::Grant,
+    mfa_factor::MfaFactor,
+    session::Session,
+    user::User,
+    Pagination,
+};
+use lockpad_ulid::Ulid;
+use time::OffsetDateTime;
+
+/// Stores the models in Postgres, using the queries on the models themselves.
+#[derive(Clone)]
+pub struct PostgresRepository {
+    pool: sqlx::pool::Pool<sqlx::Postgres>,
+}
+
+impl PostgresRepository {
+    pub fn new(pool: sqlx::pool::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for PostgresRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<User>> {
+        User::by_id(&self.pool, id).await
+    }
+
+    async fn by_identifier(&self, identifier: &str) -> Result<Option<User>> {
+        User::by_identifier(&self.pool, identifier).await
+    }
+
+    async fn search(&self, term: &str, pagination: Pagination) -> Result<(Vec<User>, Pagination)> {
+        User::search(&self.pool, term, pagination).await
+    }
+
+    async fn create(&self, user: &User) -> Result<()> {
+        user.create(&self.pool).await
+    }
+
+    async fn update(&self, user: &User) -> Result<()> {
+        user.update(&self.pool).await
+    }
+
+    async fn revoke_tokens(&self, id: &Ulid) -> Result<()> {
+        User::revoke_tokens(&self.pool, id).await
+    }
+
+    async fn delete(&self, user: &User) -> Result<()> {
+        user.delete(&self.pool).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ApplicationRepository for PostgresRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<Application>> {
+        Application::by_id(&self.pool, id).await
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<Application>> {
+        Application::by_owner_and_id(&self.pool, owner_id, id).await
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<Application>, Pagination)> {
+        Application::query(&self.pool, owner_id, pagination).await
+    }
+
+    async fn all(&self) -> Result<Vec<Application>> {
+        Application::all(&self.pool).await
+    }
+
+    async fn create(&self, application: &Application) -> Result<()> {
+        application.create(&self.pool).await
+    }
+
+    async fn update(&self, application: &Application) -> Result<()> {
+        application.update(&self.pool).await
+    }
+
+    async fn delete(&self, application: &Application) -> Result<()> {
+        application.delete(&self.pool).await
+    }
+
+    async fn backchannel_logout_targets(&self, session_id: &Ulid) -> Result<Vec<Application>> {
+        Application::backchannel_logout_targets(&self.pool, session_id).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiKeyRepository for PostgresRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<ApiKey>> {
+        ApiKey::by_id(&self.pool, id).await
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<ApiKey>> {
+        ApiKey::by_owner_and_id(&self.pool, owner_id, id).await
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<ApiKey>, Pagination)> {
+        ApiKey::query(&self.pool, owner_id, pagination).await
+    }
+
+    async fn all(&self) -> Result<Vec<ApiKey>> {
+        ApiKey::all(&self.pool).await
+    }
+
+    async fn create(&self, api_key: &ApiKey) -> Result<()> {
+        api_key.create(&self.pool).await
+    }
+
+    async fn record_use(&self, api_key: &mut ApiKey) -> Result<()> {
+        api_key.record_use(&self.pool).await
+    }
+
+    async fn record_uses_by_id(
+        &self,
+        api_key_id: &Ulid,
+        uses: i64,
+        used_at: OffsetDateTime,
+    ) -> Result<i64> {
+        ApiKey::record_uses_by_id(&self.pool, api_key_id, uses, used_at).await
+    }
+
+    async fn revoke(&self, api_key: &mut ApiKey) -> Result<()> {
+        api_key.revoke(&self.pool).await
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionRepository for PostgresRepository {
+    async fn by_token_hash(&self, token_hash: &str) -> Result<Option<Session>> {
+        Session::by_token_hash(&self.pool, token_hash).await
+    }
+
+    async fn by_id(&self, user_id: &Ulid, session_id: &Ulid) -> Result<Option<Session>> {
+        Session::by_id(&self.pool, user_id, session_id).await
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Session>> {
+        Session::by_user_id(&self.pool, user_id).await
+    }
+
+    async fn create(&self, session: &Session) -> Result<()> {
+        session.create(&self.pool).await
+    }
+
+    async fn delete(&self, session: &Session) -> Result<()> {
+        session.delete(&self.pool).await
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<()> {
+        session.touch(&self.pool, ip_address, user_agent).await
+    }
+
+    async fn delete_by_user_id(&self, user_id: &Ulid) -> Result<()> {
+        Session::delete_by_user_id(&self.pool, user_id).await
+    }
+
+    async fn add_application(&self, session: &Session, application_id: &Ulid) -> Result<()> {
+        session.add_application(&self.pool, application_id).await
+    }
+}
+
+#[async_trait::async_trait]
+impl GrantRepository for PostgresRepository {
+    async fn by_user_and_application(
+        &self,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<Option<Grant>> {
+        Grant::by_user_and_application(&self.pool, user_id, application_id).await
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Grant>> {
+        Grant::by_user_id(&self.pool, user_id).await
+    }
+
+    async fn save(&self, grant: &Grant) -> Result<()> {
+        grant.save(&self.pool).await
+    }
+
+    async fn delete(&self, user_id: &Ulid, application_id: &Ulid) -> Result<()> {
+        Grant::delete(&self.pool, user_id, application_id).await
+    }
+}
+
+#[async_trait::async_trait]
+impl MfaFactorRepository for PostgresRepository {
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<MfaFactor>> {
+        MfaFactor::by_user_id(&self.pool, user_id).await
+    }
+
+    async fn create(&self, factor: &MfaFactor) -> Result<()> {
+        factor.create(&self.pool).await
+    }
+
+    async fn record_use(&self, mfa_factor_id: &Ulid, step: i64) -> Result<bool> {
+        MfaFactor::record_use(&self.pool, mfa_factor_id, step).await
+    }
+
+    async fn delete(&self, user_id: &Ulid, mfa_factor_id: &Ulid) -> Result<()> {
+        MfaFactor::delete(&self.pool, user_id, mfa_factor_id).await
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditEventRepository for PostgresRepository {
+    async fn create(&self, event: &AuditEvent) -> Result<()> {
+        event.create(&self.pool).await
+    }
+
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditEvent>, Pagination)> {
+        AuditEvent::query(&self.pool, filter, pagination).await
+    }
+
+    async fn redact_user(&self, user_id: &Ulid, identifier: &str) -> Result<()> {
+        AuditEvent::redact_user(&self.pool, user_id, identifier).await
+    }
+}
@@ -0,0 +1,1148 @@
+use super::{
+    ApiKeyRepository, ApplicationRepository, AuditEventRepository, GrantRepository,
+    MfaFactorRepository, SessionRepository, UserRepository,
+};
+use crate::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditAction, AuditEvent, AuditFilter},
+    error::Result,
+    grant::Grant,
+    mfa_factor::MfaFactor,
+    session::Session,
+    user::User,
+    Pagination,
+};
+use lockpad_ulid::Ulid;
+use sqlx::types::Json;
+use time::OffsetDateTime;
+
+/// Stores the models in SQLite, for deployments too small to warrant Postgres.
+/// Ids are stored as 16 byte blobs, lists as json arrays and timestamps as rfc3339 text.
+/// Timestamps don't sort as text, so they are compared with `julianday`.
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: sqlx::pool::Pool<sqlx::Sqlite>,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: sqlx::pool::Pool<sqlx::Sqlite>) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ApplicationRow {
+    application_id: Ulid,
+    owner_id: Ulid,
+    name: String,
+    allowed_origins: Json<Vec<String>>,
+    allowed_callback_urls: Json<Vec<String>>,
+    allowed_logout_urls: Json<Vec<String>>,
+    backchannel_logout_uri: Option<String>,
+}
+
+impl From<ApplicationRow> for Application {
+    fn from(row: ApplicationRow) -> Self {
+        Self {
+            application_id: row.application_id,
+            owner_id: row.owner_id,
+            name: row.name,
+            allowed_origins: row.allowed_origins.0,
+            allowed_callback_urls: row.allowed_callback_urls.0,
+            allowed_logout_urls: row.allowed_logout_urls.0,
+            backchannel_logout_uri: row.backchannel_logout_uri,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyRow {
+    api_key_id: Ulid,
+    owner_id: Ulid,
+    name: String,
+    secret: String,
+    scopes: Json<Vec<String>>,
+    created_at: OffsetDateTime,
+    expires_at: Option<OffsetDateTime>,
+    revoked_at: Option<OffsetDateTime>,
+    last_used_at: Option<OffsetDateTime>,
+    use_count: i64,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            api_key_id: row.api_key_id,
+            owner_id: row.owner_id,
+            name: row.name,
+            secret: row.secret,
+            scopes: row.scopes.0,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            revoked_at: row.revoked_at,
+            last_used_at: row.last_used_at,
+            use_count: row.use_count,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct GrantRow {
+    user_id: Ulid,
+    application_id: Ulid,
+    scopes: Json<Vec<String>>,
+    created_at: OffsetDateTime,
+    updated_at: OffsetDateTime,
+}
+
+impl From<GrantRow> for Grant {
+    fn from(row: GrantRow) -> Self {
+        Self {
+            user_id: row.user_id,
+            application_id: row.application_id,
+            scopes: row.scopes.0,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for SqliteRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT
+                user_id, identifier, secret, email, admin, disabled, tokens_valid_after
+            FROM
+                users
+            WHERE
+                user_id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn by_identifier(&self, identifier: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT
+                user_id, identifier, secret, email, admin, disabled, tokens_valid_after
+            FROM
+                users
+            WHERE
+                identifier = ?1
+            "#,
+        )
+        .bind(identifier)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn search(&self, term: &str, pagination: Pagination) -> Result<(Vec<User>, Pagination)> {
+        // LIKE ignores ascii case in sqlite, matching ILIKE in postgres
+        let pattern = format!(
+            "%{}%",
+            term.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT
+                user_id, identifier, secret, email, admin, disabled, tokens_valid_after
+            FROM
+                users
+            WHERE
+                (identifier LIKE ?1 ESCAPE '\' OR email LIKE ?1 ESCAPE '\')
+                AND (?2 IS NULL OR user_id > ?2)
+            ORDER BY
+                user_id
+            LIMIT ?3
+            "#,
+        )
+        .bind(pattern)
+        .bind(pagination.last_key)
+        .bind(pagination.fetch_limit())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pagination.page(users, |user| user.user_id))
+    }
+
+    async fn create(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                users(user_id, identifier, secret, email, admin, disabled)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(user.user_id)
+        .bind(&user.identifier)
+        .bind(&user.secret)
+        .bind(&user.email)
+        .bind(user.admin)
+        .bind(user.disabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE
+                users
+            SET
+                secret = ?2,
+                email = ?3,
+                admin = ?4,
+                disabled = ?5
+            WHERE
+                user_id = ?1
+            "#,
+        )
+        .bind(user.user_id)
+        .bind(&user.secret)
+        .bind(&user.email)
+        .bind(user.admin)
+        .bind(user.disabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_tokens(&self, id: &Ulid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE
+                users
+            SET
+                tokens_valid_after = ?2
+            WHERE
+                user_id = ?1
+            "#,
+        )
+        .bind(id)
+        .bind(OffsetDateTime::now_utc())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                users
+            WHERE
+                user_id = ?1
+            "#,
+        )
+        .bind(user.user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ApplicationRepository for SqliteRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<Application>> {
+        let application = sqlx::query_as::<_, ApplicationRow>(
+            r#"
+            SELECT
+                application_id,
+                owner_id,
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
+                applications
+            WHERE
+                application_id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(application.map(Application::from))
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<Application>> {
+        let application = sqlx::query_as::<_, ApplicationRow>(
+            r#"
+            SELECT
+                application_id,
+                owner_id,
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
+                applications
+            WHERE
+                application_id = ?1 AND owner_id = ?2
+            "#,
+        )
+        .bind(id)
+        .bind(owner_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(application.map(Application::from))
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<Application>, Pagination)> {
+        let applications = sqlx::query_as::<_, ApplicationRow>(
+            r#"
+            SELECT
+                application_id,
+                owner_id,
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
+                applications
+            WHERE
+                owner_id = ?1
+                AND (?2 IS NULL OR application_id > ?2)
+            ORDER BY
+                application_id
+            LIMIT ?3
+            "#,
+        )
+        .bind(owner_id)
+        .bind(pagination.last_key)
+        .bind(pagination.fetch_limit())
+        .fetch_all(&self.pool)
+        .await?;
+        let applications = applications.into_iter().map(Application::from).collect();
+
+        Ok(pagination.page(applications, |application| application.application_id))
+    }
+
+    async fn all(&self) -> Result<Vec<Application>> {
+        let applications = sqlx::query_as::<_, ApplicationRow>(
+            r#"
+            SELECT
+                application_id,
+                owner_id,
+                name,
+                allowed_origins,
+                allowed_callback_urls,
+                allowed_logout_urls,
+                backchannel_logout_uri
+            FROM
+                applications
+            ORDER BY
+                application_id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(applications.into_iter().map(Application::from).collect())
+    }
+
+    async fn create(&self, application: &Application) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                applications(application_id, owner_id, name, allowed_origins, allowed_callback_urls, allowed_logout_urls, backchannel_logout_uri)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(application.application_id)
+        .bind(application.owner_id)
+        .bind(&application.name)
+        .bind(Json(&application.allowed_origins))
+        .bind(Json(&application.allowed_callback_urls))
+        .bind(Json(&application.allowed_logout_urls))
+        .bind(&application.backchannel_logout_uri)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update(&self, application: &Application) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE
+                applications
+            SET
+                name = ?2,
+                allowed_origins = ?3,
+                allowed_callback_urls = ?4,
+                allowed_logout_urls = ?5,
+                backchannel_logout_uri = ?6
+            WHERE
+                application_id = ?1
+            "#,
+        )
+        .bind(application.application_id)
+        .bind(&application.name)
+        .bind(Json(&application.allowed_origins))
+        .bind(Json(&application.allowed_callback_urls))
+        .bind(Json(&application.allowed_logout_urls))
+        .bind(&application.backchannel_logout_uri)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, application: &Application) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                applications
+            WHERE
+                application_id = ?1
+            "#,
+        )
+        .bind(application.application_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn backchannel_logout_targets(&self, session_id: &Ulid) -> Result<Vec<Application>> {
+        let applications = sqlx::query_as::<_, ApplicationRow>(
+            r#"
+            SELECT
+                applications.application_id,
+                applications.owner_id,
+                applications.name,
+                applications.allowed_origins,
+                applications.allowed_callback_urls,
+                applications.allowed_logout_urls,
+                applications.backchannel_logout_uri
+            FROM
+                applications
+                JOIN session_applications USING (application_id)
+            WHERE
+                session_applications.session_id = ?1
+                AND applications.backchannel_logout_uri IS NOT NULL
+            "#,
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(applications.into_iter().map(Application::from).collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiKeyRepository for SqliteRepository {
+    async fn by_id(&self, id: &Ulid) -> Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as::<_, ApiKeyRow>(
+            r#"
+            SELECT
+                api_key_id,
+                owner_id,
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
+                api_keys
+            WHERE
+                api_key_id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key.map(ApiKey::from))
+    }
+
+    async fn by_owner_and_id(&self, owner_id: &Ulid, id: &Ulid) -> Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as::<_, ApiKeyRow>(
+            r#"
+            SELECT
+                api_key_id,
+                owner_id,
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
+                api_keys
+            WHERE
+                api_key_id = ?1 AND owner_id = ?2
+            "#,
+        )
+        .bind(id)
+        .bind(owner_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key.map(ApiKey::from))
+    }
+
+    async fn query(
+        &self,
+        owner_id: Ulid,
+        pagination: Pagination,
+    ) -> Result<(Vec<ApiKey>, Pagination)> {
+        let api_keys = sqlx::query_as::<_, ApiKeyRow>(
+            r#"
+            SELECT
+                api_key_id,
+                owner_id,
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
+                api_keys
+            WHERE
+                owner_id = ?1
+                AND (?2 IS NULL OR api_key_id > ?2)
+            ORDER BY
+                api_key_id
+            LIMIT ?3
+            "#,
+        )
+        .bind(owner_id)
+        .bind(pagination.last_key)
+        .bind(pagination.fetch_limit())
+        .fetch_all(&self.pool)
+        .await?;
+        let api_keys = api_keys.into_iter().map(ApiKey::from).collect();
+
+        Ok(pagination.page(api_keys, |api_key| api_key.api_key_id))
+    }
+
+    async fn all(&self) -> Result<Vec<ApiKey>> {
+        let api_keys = sqlx::query_as::<_, ApiKeyRow>(
+            r#"
+            SELECT
+                api_key_id,
+                owner_id,
+                name,
+                secret,
+                scopes,
+                created_at,
+                expires_at,
+                revoked_at,
+                last_used_at,
+                use_count
+            FROM
+                api_keys
+            ORDER BY
+                api_key_id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(api_keys.into_iter().map(ApiKey::from).collect())
+    }
+
+    async fn create(&self, api_key: &ApiKey) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                api_keys(api_key_id, owner_id, name, secret, scopes, created_at, expires_at, revoked_at, last_used_at, use_count)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(api_key.api_key_id)
+        .bind(api_key.owner_id)
+        .bind(&api_key.name)
+        .bind(&api_key.secret)
+        .bind(Json(&api_key.scopes))
+        .bind(api_key.created_at)
+        .bind(api_key.expires_at)
+        .bind(api_key.revoked_at)
+        .bind(api_key.last_used_at)
+        .bind(api_key.use_count)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_use(&self, api_key: &mut ApiKey) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        api_key.use_count = self.record_uses_by_id(&api_key.api_key_id, 1, now).await?;
+        api_key.last_used_at = Some(now);
+
+        Ok(())
+    }
+
+    async fn record_uses_by_id(
+        &self,
+        api_key_id: &Ulid,
+        uses: i64,
+        used_at: OffsetDateTime,
+    ) -> Result<i64> {
+        let use_count = sqlx::query_scalar(
+            r#"
+            UPDATE
+                api_keys
+            SET
+                last_used_at = CASE
+                    WHEN last_used_at IS NULL OR julianday(last_used_at) < julianday(?2) THEN ?2
+                    ELSE last_used_at
+                END,
+                use_count = use_count + ?3
+            WHERE
+                api_key_id = ?1
+            RETURNING
+                use_count
+            "#,
+        )
+        .bind(api_key_id)
+        .bind(used_at)
+        .bind(uses)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(use_count)
+    }
+
+    async fn revoke(&self, api_key: &mut ApiKey) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query(
+            r#"
+            UPDATE
+                api_keys
+            SET
+                revoked_at = COALESCE(revoked_at, ?2)
+            WHERE
+                api_key_id = ?1
+            "#,
+        )
+        .bind(api_key.api_key_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        api_key.revoked_at.get_or_insert(now);
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionRepository for SqliteRepository {
+    async fn by_token_hash(&self, token_hash: &str) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                session_id,
+                user_id,
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                token_hash = ?1 AND julianday(expires_at) > julianday('now')
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn by_id(&self, user_id: &Ulid, session_id: &Ulid) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                session_id,
+                user_id,
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                session_id = ?1 AND user_id = ?2 AND julianday(expires_at) > julianday('now')
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Session>> {
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                session_id,
+                user_id,
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                user_id = ?1 AND julianday(expires_at) > julianday('now')
+            ORDER BY
+                julianday(last_seen_at) DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn create(&self, session: &Session) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                sessions(session_id, user_id, token_hash, created_at, authenticated_at, expires_at, last_seen_at, ip_address, user_agent)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            "#,
+        )
+        .bind(session.session_id)
+        .bind(session.user_id)
+        .bind(&session.token_hash)
+        .bind(session.created_at)
+        .bind(session.authenticated_at)
+        .bind(session.expires_at)
+        .bind(session.last_seen_at)
+        .bind(&session.ip_address)
+        .bind(&session.user_agent)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, session: &Session) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                sessions
+            WHERE
+                session_id = ?1
+            "#,
+        )
+        .bind(session.session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        if !session.touch_due(now) {
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE
+                sessions
+            SET
+                last_seen_at = ?2,
+                ip_address = COALESCE(?3, ip_address),
+                user_agent = COALESCE(?4, user_agent)
+            WHERE
+                session_id = ?1
+            "#,
+        )
+        .bind(session.session_id)
+        .bind(now)
+        .bind(&ip_address)
+        .bind(&user_agent)
+        .execute(&self.pool)
+        .await?;
+
+        session.seen(now, ip_address, user_agent);
+
+        Ok(())
+    }
+
+    async fn delete_by_user_id(&self, user_id: &Ulid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                sessions
+            WHERE
+                user_id = ?1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_application(&self, session: &Session, application_id: &Ulid) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                session_applications(session_id, application_id)
+            VALUES
+                (?1, ?2)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(session.session_id)
+        .bind(application_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl GrantRepository for SqliteRepository {
+    async fn by_user_and_application(
+        &self,
+        user_id: &Ulid,
+        application_id: &Ulid,
+    ) -> Result<Option<Grant>> {
+        let grant = sqlx::query_as::<_, GrantRow>(
+            r#"
+            SELECT
+                user_id,
+                application_id,
+                scopes,
+                created_at,
+                updated_at
+            FROM
+                grants
+            WHERE
+                user_id = ?1 AND application_id = ?2
+            "#,
+        )
+        .bind(user_id)
+        .bind(application_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(grant.map(Grant::from))
+    }
+
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<Grant>> {
+        let grants = sqlx::query_as::<_, GrantRow>(
+            r#"
+            SELECT
+                user_id,
+                application_id,
+                scopes,
+                created_at,
+                updated_at
+            FROM
+                grants
+            WHERE
+                user_id = ?1
+            ORDER BY
+                application_id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(grants.into_iter().map(Grant::from).collect())
+    }
+
+    async fn save(&self, grant: &Grant) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                grants(user_id, application_id, scopes, created_at, updated_at)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (user_id, application_id) DO UPDATE SET
+                scopes = excluded.scopes,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(grant.user_id)
+        .bind(grant.application_id)
+        .bind(Json(&grant.scopes))
+        .bind(grant.created_at)
+        .bind(grant.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: &Ulid, application_id: &Ulid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                grants
+            WHERE
+                user_id = ?1 AND application_id = ?2
+            "#,
+        )
+        .bind(user_id)
+        .bind(application_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MfaFactorRepository for SqliteRepository {
+    async fn by_user_id(&self, user_id: &Ulid) -> Result<Vec<MfaFactor>> {
+        let factors = sqlx::query_as::<_, MfaFactor>(
+            r#"
+            SELECT
+                mfa_factor_id,
+                user_id,
+                name,
+                secret,
+                last_step,
+                created_at
+            FROM
+                mfa_factors
+            WHERE
+                user_id = ?1
+            ORDER BY
+                mfa_factor_id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(factors)
+    }
+
+    async fn create(&self, factor: &MfaFactor) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                mfa_factors(mfa_factor_id, user_id, name, secret, last_step, created_at)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(factor.mfa_factor_id)
+        .bind(factor.user_id)
+        .bind(&factor.name)
+        .bind(&factor.secret)
+        .bind(factor.last_step)
+        .bind(factor.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_use(&self, mfa_factor_id: &Ulid, step: i64) -> Result<bool> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE
+                mfa_factors
+            SET
+                last_step = ?2
+            WHERE
+                mfa_factor_id = ?1 AND (last_step IS NULL OR last_step < ?2)
+            "#,
+        )
+        .bind(mfa_factor_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(updated.rows_affected() == 1)
+    }
+
+    async fn delete(&self, user_id: &Ulid, mfa_factor_id: &Ulid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM
+                mfa_factors
+            WHERE
+                user_id = ?1 AND mfa_factor_id = ?2
+            "#,
+        )
+        .bind(user_id)
+        .bind(mfa_factor_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditEventRepository for SqliteRepository {
+    async fn create(&self, event: &AuditEvent) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO
+                audit_events(audit_event_id, occurred_at, actor_id, action, target_id, ip_address, user_agent, outcome, detail)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            "#,
+        )
+        .bind(event.audit_event_id)
+        .bind(event.occurred_at)
+        .bind(event.actor_id)
+        .bind(event.action)
+        .bind(event.target_id)
+        .bind(&event.ip_address)
+        .bind(&event.user_agent)
+        .bind(event.outcome)
+        .bind(&event.detail)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditEvent>, Pagination)> {
+        let events = sqlx::query_as::<_, AuditEvent>(
+            r#"
+            SELECT
+                audit_event_id,
+                occurred_at,
+                actor_id,
+                action,
+                target_id,
+                ip_address,
+                user_agent,
+                outcome,
+                detail
+            FROM
+                audit_events
+            WHERE
+                (?1 IS NULL OR actor_id = ?1)
+                AND (?2 IS NULL OR target_id = ?2)
+                AND (?3 IS NULL OR action = ?3)
+                AND (?4 IS NULL OR outcome = ?4)
+                AND (?5 IS NULL OR julianday(occurred_at) >= julianday(?5))
+                AND (?6 IS NULL OR julianday(occurred_at) < julianday(?6))
+                AND (?7 IS NULL OR audit_event_id > ?7)
+            ORDER BY
+                audit_event_id
+            LIMIT ?8
+            "#,
+        )
+        .bind(filter.actor_id)
+        .bind(filter.target_id)
+        .bind(filter.action)
+        .bind(filter.outcome)
+        .bind(filter.since)
+        .bind(filter.until)
+        .bind(pagination.last_key)
+        .bind(pagination.fetch_limit())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pagination.page(events, |event| event.audit_event_id))
+    }
+
+    async fn redact_user(&self, user_id: &Ulid, identifier: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE audit_events
+            SET ip_address = NULL, user_agent = NULL
+            WHERE actor_id = ?1 OR (target_id = ?1 AND action IN (?2, ?3))
+            "#,
+        )
+        .bind(user_id)
+        .bind(AuditAction::Login)
+        .bind(AuditAction::Lockout)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            UPDATE audit_events
+            SET detail = NULL
+            WHERE action IN (?1, ?2) AND (detail = ?3 OR detail = 'identifier:' || ?3)
+            "#,
+        )
+        .bind(AuditAction::Login)
+        .bind(AuditAction::Lockout)
+        .bind(identifier)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
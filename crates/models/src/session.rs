@@ -0,0 +1,349 @@
+use crate::error::{Error, Result};
+use lockpad_ulid::Ulid;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+/// A browser session established by logging in to lockpad itself.
+/// The session is identified by an opaque token stored in a cookie, only its hash is persisted.
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Session {
+    pub session_id: Ulid,
+    pub user_id: Ulid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    /// The last time the user proved their identity by providing credentials.
+    #[serde(with = "time::serde::rfc3339")]
+    pub authenticated_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+
+    /// The last time the session was used.
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Session {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Looks up a session that has not yet expired.
+    pub async fn by_token_hash(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        token_hash: &str,
+    ) -> Result<Option<Self>> {
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT
+                session_id::uuid as "session_id!: Ulid",
+                user_id::uuid as "user_id!: Ulid",
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                token_hash = $1 AND expires_at > now()
+            "#,
+            token_hash,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    /// Looks up a session of the given user that has not yet expired.
+    pub async fn by_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+        session_id: &Ulid,
+    ) -> Result<Option<Self>> {
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT
+                session_id::uuid as "session_id!: Ulid",
+                user_id::uuid as "user_id!: Ulid",
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                session_id::uuid = $1 AND user_id::uuid = $2 AND expires_at > now()
+            "#,
+            session_id.to_sqlx_uuid(),
+            user_id.to_sqlx_uuid(),
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    /// Lists the sessions of a user that have not yet expired.
+    pub async fn by_user_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+    ) -> Result<Vec<Self>> {
+        let sessions = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT
+                session_id::uuid as "session_id!: Ulid",
+                user_id::uuid as "user_id!: Ulid",
+                token_hash,
+                created_at,
+                authenticated_at,
+                expires_at,
+                last_seen_at,
+                ip_address,
+                user_agent
+            FROM
+                sessions
+            WHERE
+                user_id::uuid = $1 AND expires_at > now()
+            ORDER BY
+                last_seen_at DESC
+            "#,
+            user_id.to_sqlx_uuid(),
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    pub async fn create(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                sessions(session_id, user_id, token_hash, created_at, authenticated_at, expires_at, last_seen_at, ip_address, user_agent)
+            SELECT
+                session_id::uuid, user_id::uuid, token_hash, created_at, authenticated_at, expires_at, last_seen_at, ip_address, user_agent
+            FROM(
+                VALUES($1, $2, $3, $4::timestamptz, $5::timestamptz, $6::timestamptz, $7::timestamptz, $8, $9)
+            ) AS data(session_id, user_id, token_hash, created_at, authenticated_at, expires_at, last_seen_at, ip_address, user_agent)
+            "#,
+            self.session_id.queryable(),
+            self.user_id.queryable(),
+            self.token_hash,
+            self.created_at,
+            self.authenticated_at,
+            self.expires_at,
+            self.last_seen_at,
+            self.ip_address,
+            self.user_agent,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                sessions
+            WHERE
+                session_id::uuid = $1
+            "#,
+            self.session_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the session was just used, and from where.
+    /// To avoid writing on every request, this only happens once a minute.
+    pub async fn touch(
+        &mut self,
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        if !self.touch_due(now) {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE
+                sessions
+            SET
+                last_seen_at = $2,
+                ip_address = COALESCE($3, ip_address),
+                user_agent = COALESCE($4, user_agent)
+            WHERE
+                session_id::uuid = $1
+            "#,
+            self.session_id.to_sqlx_uuid(),
+            now,
+            ip_address,
+            user_agent,
+        )
+        .execute(pool)
+        .await?;
+
+        self.seen(now, ip_address, user_agent);
+
+        Ok(())
+    }
+
+    /// Whether the session was last recorded as seen long enough ago to record it again.
+    pub(crate) fn touch_due(&self, now: OffsetDateTime) -> bool {
+        now - self.last_seen_at >= Duration::minutes(1)
+    }
+
+    /// Updates where and when the session was last seen, keeping what isn't known.
+    pub(crate) fn seen(
+        &mut self,
+        now: OffsetDateTime,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) {
+        self.last_seen_at = now;
+        if ip_address.is_some() {
+            self.ip_address = ip_address;
+        }
+        if user_agent.is_some() {
+            self.user_agent = user_agent;
+        }
+    }
+
+    /// Ends every session belonging to the user.
+    pub async fn delete_by_user_id(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        user_id: &Ulid,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                sessions
+            WHERE
+                user_id::uuid = $1
+            "#,
+            user_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the session was used to log in to an application.
+    pub async fn add_application(
+        &self,
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        application_id: &Ulid,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                session_applications(session_id, application_id)
+            SELECT
+                session_id::uuid, application_id::uuid
+            FROM(
+                VALUES($1, $2)
+            ) AS data(session_id, application_id)
+            ON CONFLICT DO NOTHING
+            "#,
+            self.session_id.queryable(),
+            application_id.queryable(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Determines whether the user authenticated within the last `max_age`.
+    pub fn authenticated_within(&self, max_age: Duration) -> bool {
+        OffsetDateTime::now_utc() - self.authenticated_at <= max_age
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Builder {
+    user_id: Option<Ulid>,
+    token_hash: Option<String>,
+    lifetime: Option<Duration>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Builder {
+    pub fn user_id(mut self, user_id: Ulid) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    pub fn token_hash(mut self, token_hash: String) -> Self {
+        self.token_hash = Some(token_hash);
+        self
+    }
+
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    pub fn ip_address(mut self, ip_address: Option<String>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+}
+
+impl crate::entity::Builder for Builder {
+    type Item = Session;
+
+    fn build(self) -> Result<Self::Item> {
+        let user_id = self
+            .user_id
+            .ok_or_else(|| Error::ModelFieldsMissing("user_id"))?;
+        let token_hash = self
+            .token_hash
+            .ok_or_else(|| Error::ModelFieldsMissing("token_hash"))?;
+        let lifetime = self
+            .lifetime
+            .ok_or_else(|| Error::ModelFieldsMissing("lifetime"))?;
+
+        let now = OffsetDateTime::now_utc();
+
+        Ok(Session {
+            session_id: Ulid::generate(),
+            user_id,
+            token_hash,
+            created_at: now,
+            authenticated_at: now,
+            expires_at: now + lifetime,
+            last_seen_at: now,
+            ip_address: self.ip_address,
+            user_agent: self.user_agent,
+        })
+    }
+}
@@ -1,13 +1,22 @@
 use crate::error::{Error, Result};
 use lockpad_ulid::Ulid;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub user_id: Ulid,
     pub identifier: String,
     #[serde(skip_serializing)]
     pub secret: String,
+    pub email: Option<String>,
+    /// Administrators can manage every account.
+    pub admin: bool,
+    /// Disabled users cannot log in.
+    pub disabled: bool,
+    /// Tokens issued up to this time are refused, even when they have not expired yet.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub tokens_valid_after: Option<OffsetDateTime>,
 }
 
 impl User {
@@ -21,7 +30,7 @@ impl User {
             User,
             r#"
             SELECT
-                user_id as "user_id: Ulid", identifier, secret
+                user_id as "user_id: Ulid", identifier, secret, email, admin, disabled, tokens_valid_after
             FROM 
                 users
             WHERE 
@@ -29,38 +38,30 @@ impl User {
             "#,
             id as _,
         )
-        .fetch_one(pool)
+        .fetch_optional(pool)
         .await?;
 
-        Ok(Some(user))
+        Ok(user)
     }
 
     pub async fn by_identifier(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
         identifier: &str,
     ) -> Result<Option<Self>> {
-        let query = sqlx::query!(
+        let user = sqlx::query_as!(
+            User,
             r#"
             SELECT
-                user_id::uuid as "user_id: Ulid", identifier, secret
-            FROM 
+                user_id as "user_id!: Ulid", identifier, secret, email, admin, disabled, tokens_valid_after
+            FROM
                 users
-            WHERE 
+            WHERE
                 identifier = $1
             "#,
             identifier,
-        );
-
-        let user = query.fetch_one(pool).await?;
-
-        let user = match user.user_id {
-            Some(user_id) => Some(User {
-                user_id,
-                identifier: user.identifier,
-                secret: user.secret,
-            }),
-            None => None,
-        };
+        )
+        .fetch_optional(pool)
+        .await?;
 
         Ok(user)
     }
@@ -69,16 +70,17 @@ impl User {
         sqlx::query!(
             r#"
             INSERT INTO 
-                users(user_id, identifier, secret)
+                users(user_id, identifier, secret, email)
             SELECT 
-                user_id::uuid, identifier, secret
+                user_id::uuid, identifier, secret, email
             FROM(
-                VALUES($1, $2, $3)
-            ) AS data(user_id, identifier, secret)
+                VALUES($1, $2, $3, $4)
+            ) AS data(user_id, identifier, secret, email)
             "#,
             self.user_id.queryable(),
             self.identifier,
             self.secret,
+            self.email,
         )
         .execute(pool)
         .await?;
@@ -86,40 +88,110 @@ impl User {
         Ok(())
     }
 
-    pub async fn query(
+    /// Finds users whose identifier or email contains the search term, one page at a time.
+    pub async fn search(
         pool: &sqlx::pool::Pool<sqlx::Postgres>,
-        _pagination: crate::Pagination,
+        term: &str,
+        pagination: crate::Pagination,
     ) -> Result<(Vec<Self>, crate::Pagination)> {
-        // TODO: Implement pagination for querying
-        let query = sqlx::query!(
+        let pattern = format!(
+            "%{}%",
+            term.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+
+        let users = sqlx::query_as!(
+            User,
             r#"
             SELECT
-                user_id::uuid as "user_id: Ulid", identifier, secret
-            FROM 
+                user_id::uuid as "user_id!: Ulid", identifier, secret, email, admin, disabled, tokens_valid_after
+            FROM
                 users
+            WHERE
+                (identifier ILIKE $1 OR email ILIKE $1)
+                AND ($2::uuid IS NULL OR user_id::uuid > $2)
+            ORDER BY
+                user_id::uuid
+            LIMIT $3
             "#,
-        );
+            pattern,
+            pagination.after(),
+            pagination.fetch_limit(),
+        )
+        .fetch_all(pool)
+        .await?;
 
-        let users = query.fetch_all(pool).await?;
-
-        let users = users
-            .into_iter()
-            .filter_map(|user| match user.user_id {
-                Some(user_id) => Some(User {
-                    user_id,
-                    identifier: user.identifier,
-                    secret: user.secret,
-                }),
-                None => None,
-            })
-            .collect::<Vec<_>>();
-
-        let pagination = crate::Pagination {
-            last_key: users.last().map(|user| user.user_id),
-            count: users.len(),
-        };
-
-        Ok((users, pagination))
+        Ok(pagination.page(users, |user| user.user_id))
+    }
+
+    /// Saves changes to everything but the user's id and identifier.
+    pub async fn update(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE
+                users
+            SET
+                secret = $2,
+                email = $3,
+                admin = $4,
+                disabled = $5
+            WHERE
+                user_id::uuid = $1
+            "#,
+            self.user_id.to_sqlx_uuid(),
+            self.secret,
+            self.email,
+            self.admin,
+            self.disabled,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Refuses every token issued to the user until now.
+    pub async fn revoke_tokens(pool: &sqlx::pool::Pool<sqlx::Postgres>, id: &Ulid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE
+                users
+            SET
+                tokens_valid_after = now()
+            WHERE
+                user_id = $1
+            "#,
+            id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the user along with everything they own.
+    pub async fn delete(&self, pool: &sqlx::pool::Pool<sqlx::Postgres>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                users
+            WHERE
+                user_id::uuid = $1
+            "#,
+            self.user_id.to_sqlx_uuid(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn query(
+        pool: &sqlx::pool::Pool<sqlx::Postgres>,
+        pagination: crate::Pagination,
+    ) -> Result<(Vec<Self>, crate::Pagination)> {
+        Self::search(pool, "", pagination).await
     }
 }
 
@@ -127,6 +199,7 @@ impl User {
 pub struct Builder {
     identifier: Option<String>,
     secret: Option<String>,
+    email: Option<String>,
 }
 
 impl Builder {
@@ -139,6 +212,11 @@ impl Builder {
         self.secret = Some(secret);
         self
     }
+
+    pub fn email(mut self, email: String) -> Self {
+        self.email = Some(email);
+        self
+    }
 }
 
 impl crate::entity::Builder for Builder {
@@ -156,6 +234,10 @@ impl crate::entity::Builder for Builder {
             user_id: Ulid::generate(),
             identifier,
             secret,
+            email: self.email,
+            admin: false,
+            disabled: false,
+            tokens_valid_after: None,
         })
     }
 }
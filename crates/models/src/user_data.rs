@@ -0,0 +1,243 @@
+//! Everything stored about a user, for answering data access requests and erasing accounts.
+
+use crate::{
+    api_key::ApiKey,
+    application::Application,
+    audit_event::{AuditEvent, AuditFilter},
+    error::Result,
+    grant::Grant,
+    mfa_factor::MfaFactor,
+    repository::Repositories,
+    session::Session,
+    user::User,
+    Pagination,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, future::Future};
+use time::OffsetDateTime;
+
+/// The number of rows fetched at a time while collecting a user's data.
+const BATCH_SIZE: usize = 500;
+
+/// A copy of everything stored about a user, without password or token hashes and the secrets of their authenticator apps.
+#[derive(Debug, Serialize)]
+pub struct UserData {
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub user: User,
+    pub applications: Vec<Application>,
+    pub api_keys: Vec<ApiKey>,
+    pub sessions: Vec<Session>,
+    pub grants: Vec<Grant>,
+    pub mfa_factors: Vec<MfaFactor>,
+    /// Events the user performed or that were performed on them, oldest first.
+    /// The address and user agent are left out of events performed by someone else.
+    pub audit_events: Vec<AuditEvent>,
+}
+
+impl UserData {
+    pub async fn collect(repositories: &Repositories, user: User) -> Result<Self> {
+        let user_id = user.user_id;
+
+        let applications =
+            all_pages(|pagination| repositories.applications.query(user_id, pagination)).await?;
+        let api_keys =
+            all_pages(|pagination| repositories.api_keys.query(user_id, pagination)).await?;
+        let sessions = repositories.sessions.by_user_id(&user_id).await?;
+        let grants = repositories.grants.by_user_id(&user_id).await?;
+        let mfa_factors = repositories.mfa_factors.by_user_id(&user_id).await?;
+
+        let mut audit_events = BTreeMap::new();
+        for filter in [
+            AuditFilter {
+                actor_id: Some(user_id),
+                ..Default::default()
+            },
+            AuditFilter {
+                target_id: Some(user_id),
+                ..Default::default()
+            },
+        ] {
+            let events =
+                all_pages(|pagination| repositories.audit_events.query(&filter, pagination))
+                    .await?;
+            for mut event in events {
+                if event.actor_id != Some(user_id) {
+                    event.ip_address = None;
+                    event.user_agent = None;
+                }
+                audit_events.insert(event.audit_event_id, event);
+            }
+        }
+
+        Ok(Self {
+            exported_at: OffsetDateTime::now_utc(),
+            user,
+            applications,
+            api_keys,
+            sessions,
+            grants,
+            mfa_factors,
+            audit_events: audit_events.into_values().collect(),
+        })
+    }
+}
+
+/// What was deleted along with a user.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Erased {
+    pub applications: usize,
+    pub api_keys: usize,
+    pub sessions: usize,
+    pub grants: usize,
+    pub mfa_factors: usize,
+}
+
+/// Formats the counts for the detail of the tombstone recording the deletion.
+impl fmt::Display for Erased {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "applications={},api_keys={},sessions={},grants={},mfa_factors={}",
+            self.applications, self.api_keys, self.sessions, self.grants, self.mfa_factors
+        )
+    }
+}
+
+/// Deletes the user along with their applications, api keys, sessions, grants and authenticator apps.
+/// Their audit events are kept without the addresses, user agents and identifier that would tie them to the person,
+/// callers record a tombstone holding only the user's id and what was deleted.
+pub async fn erase(repositories: &Repositories, user: &User) -> Result<Erased> {
+    let data = UserData::collect(repositories, user.clone()).await?;
+    repositories.users.delete(user).await?;
+    repositories
+        .audit_events
+        .redact_user(&user.user_id, &user.identifier)
+        .await?;
+
+    Ok(Erased {
+        applications: data.applications.len(),
+        api_keys: data.api_keys.len(),
+        sessions: data.sessions.len(),
+        grants: data.grants.len(),
+        mfa_factors: data.mfa_factors.len(),
+    })
+}
+
+/// Fetches every page of a listing, [`BATCH_SIZE`] rows at a time.
+pub async fn all_pages<T, F, Fut>(mut fetch: F) -> Result<Vec<T>>
+where
+    F: FnMut(Pagination) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, Pagination)>>,
+{
+    let mut items = Vec::new();
+    let mut pagination = Pagination::first(BATCH_SIZE);
+
+    loop {
+        let (page, next) = fetch(pagination).await?;
+        items.extend(page);
+
+        if next.last_key.is_none() {
+            return Ok(items);
+        }
+        pagination = Pagination {
+            count: BATCH_SIZE,
+            ..next
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audit_event::AuditAction, entity::Builder};
+    use lockpad_ulid::Ulid;
+
+    #[tokio::test]
+    async fn collect_and_erase() -> Result<()> {
+        let repositories = Repositories::memory();
+        let user = User::builder()
+            .identifier("alice".to_string())
+            .secret("hash".to_string())
+            .build()?;
+        repositories.users.create(&user).await?;
+        let application = Application::builder()
+            .owner_id(user.user_id)
+            .name("app".to_string())
+            .allowed_origins(vec![])
+            .allowed_callback_urls(vec![])
+            .build()?;
+        repositories.applications.create(&application).await?;
+        let api_key = ApiKey::builder()
+            .owner_id(user.user_id)
+            .name("key".to_string())
+            .secret("key hash".to_string())
+            .build()?;
+        repositories.api_keys.create(&api_key).await?;
+        let factor = MfaFactor::new(
+            user.user_id,
+            "phone".to_string(),
+            "JBSWY3DPEHPK3PXP".to_string(),
+            0,
+        );
+        repositories.mfa_factors.create(&factor).await?;
+        let admin_id = Ulid::generate();
+        for (actor_id, action) in [
+            (Some(user.user_id), AuditAction::Login),
+            (Some(admin_id), AuditAction::UserUpdate),
+        ] {
+            let event = AuditEvent::builder()
+                .actor_id(actor_id)
+                .action(action)
+                .target_id(Some(user.user_id))
+                .ip_address(Some("192.0.2.1".to_string()))
+                .build()?;
+            repositories.audit_events.create(&event).await?;
+        }
+
+        let data = UserData::collect(&repositories, user.clone()).await?;
+        assert_eq!(data.applications.len(), 1);
+        assert_eq!(data.api_keys.len(), 1);
+        assert_eq!(data.audit_events.len(), 2);
+        for event in &data.audit_events {
+            let ip_address = (event.action == AuditAction::Login).then_some("192.0.2.1");
+            assert_eq!(event.ip_address.as_deref(), ip_address);
+        }
+        let json = serde_json::to_string(&data).unwrap();
+        assert!(!json.contains("hash"));
+        assert!(json.contains("phone"));
+        assert!(!json.contains("JBSWY3DPEHPK3PXP"));
+
+        let erased = erase(&repositories, &user).await?;
+        assert_eq!(
+            erased,
+            Erased {
+                applications: 1,
+                api_keys: 1,
+                mfa_factors: 1,
+                ..Default::default()
+            }
+        );
+        assert!(repositories.users.by_id(&user.user_id).await?.is_none());
+        assert!(repositories
+            .applications
+            .by_id(&application.application_id)
+            .await?
+            .is_none());
+        let filter = AuditFilter {
+            target_id: Some(user.user_id),
+            ..Default::default()
+        };
+        let (events, _) = repositories
+            .audit_events
+            .query(&filter, Pagination::first(10))
+            .await?;
+        assert_eq!(events.len(), 2);
+        for event in &events {
+            let ip_address = (event.actor_id == Some(admin_id)).then_some("192.0.2.1");
+            assert_eq!(event.ip_address.as_deref(), ip_address);
+        }
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::{
     encode::IsNull,
     postgres::{PgHasArrayType, PgValueFormat},
-    Decode, Encode,
+    Decode, Encode, TypeInfo,
 };
 use std::str::FromStr;
 pub mod error;
@@ -50,21 +50,21 @@ impl std::fmt::Display for Ulid {
     }
 }
 
+/// Ids are stored as native uuids, which hold the same 16 bytes and sort in the same order.
+/// Columns using the type from the ulid extension are read as well.
 impl sqlx::Type<sqlx::Postgres> for Ulid {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
-        sqlx::postgres::PgTypeInfo::with_name("ulid")
+        <sqlx::types::Uuid as sqlx::Type<sqlx::Postgres>>::type_info()
     }
 
     fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {
-        // *ty == Self::type_info()
-        *ty == Self::type_info()
-            || <sqlx::types::Uuid as sqlx::Type<sqlx::Postgres>>::compatible(ty)
+        <sqlx::types::Uuid as sqlx::Type<sqlx::Postgres>>::compatible(ty) || ty.name() == "ulid"
     }
 }
 
 impl PgHasArrayType for Ulid {
     fn array_type_info() -> sqlx::postgres::PgTypeInfo {
-        sqlx::postgres::PgTypeInfo::with_name("_ulid")
+        <sqlx::types::Uuid as PgHasArrayType>::array_type_info()
     }
 }
 
@@ -88,8 +88,10 @@ impl Decode<'_, sqlx::Postgres> for Ulid {
             }
             PgValueFormat::Text => {
                 let s = value.as_str()?;
-                let ulid = rusty_ulid::Ulid::from_str(s)?;
-                Ulid(ulid)
+                match sqlx::types::Uuid::parse_str(s) {
+                    Ok(uuid) => Ulid::from(uuid),
+                    Err(_) => Ulid(rusty_ulid::Ulid::from_str(s)?),
+                }
             }
         })
     }
@@ -115,3 +117,34 @@ impl std::convert::From<sqlx::types::Uuid> for Ulid {
         Ulid(ulid)
     }
 }
+
+#[cfg(feature = "sqlite")]
+impl sqlx::Type<sqlx::Sqlite> for Ulid {
+    fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+        <Vec<u8> as sqlx::Type<sqlx::Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+        <Vec<u8> as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+    }
+}
+
+/// Sqlite has no uuid type, ids are stored as their 16 big-endian bytes so blobs sort in ulid order.
+#[cfg(feature = "sqlite")]
+impl<'q> Encode<'q, sqlx::Sqlite> for Ulid {
+    fn encode_by_ref(&self, buf: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>) -> IsNull {
+        let bytes: [u8; 16] = self.0.into();
+        <Vec<u8> as Encode<sqlx::Sqlite>>::encode(bytes.to_vec(), buf)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Decode<'_, sqlx::Sqlite> for Ulid {
+    fn decode(
+        value: sqlx::sqlite::SqliteValueRef<'_>,
+    ) -> std::result::Result<Self, sqlx::error::BoxDynError> {
+        let bytes = <&[u8] as Decode<sqlx::Sqlite>>::decode(value)?;
+        let ulid = rusty_ulid::Ulid::try_from(bytes)?;
+        Ok(Ulid(ulid))
+    }
+}
@@ -5,7 +5,7 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use lockpad_auth::{Claims, PublicKey};
+use lockpad_auth::{Audience, Claims, PublicKey};
 use lockpad_http::error::Result;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
@@ -13,6 +13,8 @@ use tokio::net::TcpListener;
 #[tokio::main]
 async fn main() -> Result<()> {
     let auth_url = std::env::var("AUTH_URL").unwrap_or_else(|_| panic!("AUTH_URL must be set"));
+    // only tokens issued to this application are accepted
+    let client_id = std::env::var("CLIENT_ID").unwrap_or_else(|_| panic!("CLIENT_ID must be set"));
 
     // load the public key from the auth server
     let client = reqwest::Client::new();
@@ -26,7 +28,10 @@ async fn main() -> Result<()> {
     let key_set = PublicKey::parse_from_jwks(&jwks_str)?;
     let public_key = key_set[0].clone();
 
-    let state = ServerState { public_key };
+    let state = ServerState {
+        public_key,
+        audience: Audience::application(client_id),
+    };
 
     let app = Router::new()
         .route("/unprotected", get(unprotected))
@@ -66,6 +71,7 @@ impl<S> FromRequestParts<S> for User
 where
     S: Send + Sync,
     lockpad_auth::PublicKey: axum::extract::FromRef<S>,
+    lockpad_auth::Audience: axum::extract::FromRef<S>,
 {
     type Rejection = Response;
 
@@ -87,6 +93,7 @@ where
 #[derive(Clone)]
 struct ServerState {
     public_key: PublicKey,
+    audience: Audience,
 }
 
 /// This is needed for the implementation of [FromRequestParts](axum::extract::FromRequestParts) on [Claims](lockpad_auth::Claims)
@@ -95,3 +102,10 @@ impl axum::extract::FromRef<ServerState> for PublicKey {
         state.public_key.clone()
     }
 }
+
+/// This is needed for the implementation of [FromRequestParts](axum::extract::FromRequestParts) on [Claims](lockpad_auth::Claims)
+impl axum::extract::FromRef<ServerState> for Audience {
+    fn from_ref(state: &ServerState) -> Self {
+        state.audience.clone()
+    }
+}
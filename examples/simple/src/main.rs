@@ -2,7 +2,10 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use lockpad_auth::PublicKey;
+use lockpad_auth::{
+    introspection::{ApiKeyIntrospector, IntrospectedApiKey},
+    Audience, PublicKey,
+};
 use lockpad_http::error::Result;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
@@ -10,6 +13,8 @@ use tokio::net::TcpListener;
 #[tokio::main]
 async fn main() -> Result<()> {
     let auth_url = std::env::var("AUTH_URL").unwrap_or_else(|_| panic!("AUTH_URL must be set"));
+    // only tokens issued to this application are accepted
+    let client_id = std::env::var("CLIENT_ID").unwrap_or_else(|_| panic!("CLIENT_ID must be set"));
 
     // load the public key from the auth server
     let client = reqwest::Client::new();
@@ -23,11 +28,19 @@ async fn main() -> Result<()> {
     let key_set = PublicKey::parse_from_jwks(&jwks_str)?;
     let public_key = key_set[0].clone();
 
-    let state = ServerState { public_key };
+    // api keys are verified by asking the auth server, answers are cached for a minute
+    let introspector = ApiKeyIntrospector::new(format!("{auth_url}/api/introspect"));
+
+    let state = ServerState {
+        public_key,
+        audience: Audience::application(client_id),
+        introspector,
+    };
 
     let app = Router::new()
         .route("/unprotected", get(unprotected))
         .route("/protected", post(protected_claims))
+        .route("/service", get(protected_api_key))
         .with_state(state)
         .layer(tower_http::cors::CorsLayer::permissive());
 
@@ -47,9 +60,18 @@ async fn protected_claims(claims: lockpad_auth::Claims) -> String {
     format!("Hello, {}!", claims.sub)
 }
 
+async fn protected_api_key(IntrospectedApiKey(api_key): IntrospectedApiKey) -> String {
+    format!(
+        "Hello, {} using key {}!",
+        api_key.owner_id, api_key.api_key_id
+    )
+}
+
 #[derive(Clone)]
 struct ServerState {
     public_key: PublicKey,
+    audience: Audience,
+    introspector: ApiKeyIntrospector,
 }
 
 /// This is needed for the implementation of [FromRequestParts](axum::extract::FromRequestParts) on [Claims](lockpad_auth::Claims)
@@ -58,3 +80,17 @@ impl axum::extract::FromRef<ServerState> for PublicKey {
         state.public_key.clone()
     }
 }
+
+/// This is needed for the implementation of [FromRequestParts](axum::extract::FromRequestParts) on [Claims](lockpad_auth::Claims)
+impl axum::extract::FromRef<ServerState> for Audience {
+    fn from_ref(state: &ServerState) -> Self {
+        state.audience.clone()
+    }
+}
+
+/// This is needed for the implementation of [FromRequestParts](axum::extract::FromRequestParts) on [IntrospectedApiKey]
+impl axum::extract::FromRef<ServerState> for ApiKeyIntrospector {
+    fn from_ref(state: &ServerState) -> Self {
+        state.introspector.clone()
+    }
+}
@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE sessions (
+    session_id uuid NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL,
+    token_hash text NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    authenticated_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE session_applications;
+
+ALTER TABLE applications
+    DROP COLUMN allowed_logout_urls,
+    DROP COLUMN backchannel_logout_uri;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+ALTER TABLE applications
+    ADD COLUMN allowed_logout_urls text[] NOT NULL DEFAULT '{}',
+    ADD COLUMN backchannel_logout_uri text;
+
+CREATE TABLE session_applications (
+    session_id uuid NOT NULL,
+    application_id uuid NOT NULL,
+    PRIMARY KEY (session_id, application_id),
+    FOREIGN KEY (session_id) REFERENCES sessions (session_id) ON DELETE CASCADE,
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE
+);
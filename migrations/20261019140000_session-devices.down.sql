@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE sessions
+    DROP COLUMN last_seen_at,
+    DROP COLUMN ip_address,
+    DROP COLUMN user_agent;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE sessions
+    ADD COLUMN last_seen_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN ip_address text,
+    ADD COLUMN user_agent text;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE grants (
+    user_id uuid NOT NULL,
+    application_id uuid NOT NULL,
+    scopes text[] NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, application_id),
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE,
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE users
+    ADD COLUMN admin boolean NOT NULL DEFAULT false,
+    ADD COLUMN disabled boolean NOT NULL DEFAULT false;
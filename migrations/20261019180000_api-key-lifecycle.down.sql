@@ -0,0 +1,8 @@
+-- Add down migration script here
+ALTER TABLE api_keys
+    DROP COLUMN scopes,
+    DROP COLUMN created_at,
+    DROP COLUMN expires_at,
+    DROP COLUMN revoked_at,
+    DROP COLUMN last_used_at,
+    DROP COLUMN use_count;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE api_keys
+    ADD COLUMN scopes text[] NOT NULL DEFAULT '{}',
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz,
+    ADD COLUMN revoked_at timestamptz,
+    ADD COLUMN last_used_at timestamptz,
+    ADD COLUMN use_count bigint NOT NULL DEFAULT 0;
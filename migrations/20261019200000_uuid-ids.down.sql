@@ -0,0 +1,40 @@
+-- Add down migration script here
+-- Turns the ids of the first tables back into the ulid extension's type, the later tables keep their uuid columns.
+
+ALTER TABLE applications DROP CONSTRAINT applications_owner_id_fkey;
+ALTER TABLE api_keys DROP CONSTRAINT api_keys_owner_id_fkey;
+ALTER TABLE sessions DROP CONSTRAINT sessions_user_id_fkey;
+ALTER TABLE session_applications DROP CONSTRAINT session_applications_application_id_fkey;
+ALTER TABLE grants
+    DROP CONSTRAINT grants_user_id_fkey,
+    DROP CONSTRAINT grants_application_id_fkey;
+
+ALTER TABLE users
+    ALTER COLUMN user_id TYPE ulid USING user_id::ulid,
+    ALTER COLUMN user_id SET DEFAULT gen_ulid();
+ALTER TABLE applications
+    ALTER COLUMN application_id TYPE ulid USING application_id::ulid,
+    ALTER COLUMN application_id SET DEFAULT gen_ulid(),
+    ALTER COLUMN owner_id TYPE ulid USING owner_id::ulid;
+ALTER TABLE api_keys
+    ALTER COLUMN api_key_id TYPE ulid USING api_key_id::ulid,
+    ALTER COLUMN api_key_id SET DEFAULT gen_ulid(),
+    ALTER COLUMN owner_id TYPE ulid USING owner_id::ulid;
+
+ALTER TABLE applications
+    ADD CONSTRAINT applications_owner_id_fkey
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE api_keys
+    ADD CONSTRAINT api_keys_owner_id_fkey
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE sessions
+    ADD CONSTRAINT sessions_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE session_applications
+    ADD CONSTRAINT session_applications_application_id_fkey
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE;
+ALTER TABLE grants
+    ADD CONSTRAINT grants_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE,
+    ADD CONSTRAINT grants_application_id_fkey
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE;
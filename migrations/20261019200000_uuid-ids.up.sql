@@ -0,0 +1,44 @@
+-- Add up migration script here
+-- Ids were stored with the type from the ulid extension, which stock Postgres doesn't have.
+-- They are converted to uuid columns, which hold the same 16 bytes and sort the same.
+-- The tables added since already use uuid, only the foreign keys pointing at the converted columns are recreated.
+
+ALTER TABLE applications DROP CONSTRAINT applications_owner_id_fkey;
+ALTER TABLE api_keys DROP CONSTRAINT api_keys_owner_id_fkey;
+ALTER TABLE sessions DROP CONSTRAINT sessions_user_id_fkey;
+ALTER TABLE session_applications DROP CONSTRAINT session_applications_application_id_fkey;
+ALTER TABLE grants
+    DROP CONSTRAINT grants_user_id_fkey,
+    DROP CONSTRAINT grants_application_id_fkey;
+
+ALTER TABLE users
+    ALTER COLUMN user_id DROP DEFAULT,
+    ALTER COLUMN user_id TYPE uuid USING user_id::uuid;
+ALTER TABLE applications
+    ALTER COLUMN application_id DROP DEFAULT,
+    ALTER COLUMN application_id TYPE uuid USING application_id::uuid,
+    ALTER COLUMN owner_id TYPE uuid USING owner_id::uuid;
+ALTER TABLE api_keys
+    ALTER COLUMN api_key_id DROP DEFAULT,
+    ALTER COLUMN api_key_id TYPE uuid USING api_key_id::uuid,
+    ALTER COLUMN owner_id TYPE uuid USING owner_id::uuid;
+
+ALTER TABLE applications
+    ADD CONSTRAINT applications_owner_id_fkey
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE api_keys
+    ADD CONSTRAINT api_keys_owner_id_fkey
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE sessions
+    ADD CONSTRAINT sessions_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
+ALTER TABLE session_applications
+    ADD CONSTRAINT session_applications_application_id_fkey
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE;
+ALTER TABLE grants
+    ADD CONSTRAINT grants_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE,
+    ADD CONSTRAINT grants_application_id_fkey
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE;
+
+-- the extension is left installed, the first migration's rollback drops it
@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- token buckets of the rate limiter, shared between servers using the database
+-- a bucket is stored as the time it will be full again, it holds fewer tokens the further away that is
+CREATE UNLOGGED TABLE rate_limit_buckets (
+    key text NOT NULL PRIMARY KEY,
+    full_at timestamptz NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_full_at_idx ON rate_limit_buckets (full_at);
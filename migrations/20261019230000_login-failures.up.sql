@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- failed logins per identifier and per address, shared between servers using the database
+-- a count is forgotten once the lockout has passed since its last failure
+CREATE TABLE login_failures (
+    key text NOT NULL PRIMARY KEY,
+    count integer NOT NULL,
+    last_failure timestamptz NOT NULL
+);
+
+CREATE INDEX login_failures_last_failure_idx ON login_failures (last_failure);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- authenticator apps users prove on login besides their password
+CREATE TABLE mfa_factors (
+    mfa_factor_id uuid NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL,
+    name text NOT NULL,
+    secret text NOT NULL,
+    last_step bigint,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX mfa_factors_user_id_idx ON mfa_factors (user_id);
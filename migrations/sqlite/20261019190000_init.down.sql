@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP TABLE grants;
+DROP TABLE session_applications;
+DROP TABLE sessions;
+DROP TABLE api_keys;
+DROP TABLE applications;
+DROP TABLE users;
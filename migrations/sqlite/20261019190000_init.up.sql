@@ -0,0 +1,67 @@
+-- Add up migration script here
+-- ids are 16 byte ulids, lists are json arrays and timestamps are rfc3339 text
+CREATE TABLE users (
+    user_id blob NOT NULL PRIMARY KEY,
+    identifier text NOT NULL UNIQUE,
+    secret text NOT NULL,
+    email text,
+    admin boolean NOT NULL DEFAULT false,
+    disabled boolean NOT NULL DEFAULT false
+);
+
+CREATE TABLE applications (
+    application_id blob NOT NULL PRIMARY KEY,
+    owner_id blob NOT NULL,
+    name text NOT NULL,
+    allowed_origins text NOT NULL,
+    allowed_callback_urls text NOT NULL,
+    allowed_logout_urls text NOT NULL DEFAULT '[]',
+    backchannel_logout_uri text,
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE api_keys (
+    api_key_id blob NOT NULL PRIMARY KEY,
+    owner_id blob NOT NULL,
+    name text NOT NULL,
+    secret text NOT NULL,
+    scopes text NOT NULL DEFAULT '[]',
+    created_at text NOT NULL,
+    expires_at text,
+    revoked_at text,
+    last_used_at text,
+    use_count integer NOT NULL DEFAULT 0,
+    FOREIGN KEY (owner_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE sessions (
+    session_id blob NOT NULL PRIMARY KEY,
+    user_id blob NOT NULL,
+    token_hash text NOT NULL UNIQUE,
+    created_at text NOT NULL,
+    authenticated_at text NOT NULL,
+    expires_at text NOT NULL,
+    last_seen_at text NOT NULL,
+    ip_address text,
+    user_agent text,
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE session_applications (
+    session_id blob NOT NULL,
+    application_id blob NOT NULL,
+    PRIMARY KEY (session_id, application_id),
+    FOREIGN KEY (session_id) REFERENCES sessions (session_id) ON DELETE CASCADE,
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE
+);
+
+CREATE TABLE grants (
+    user_id blob NOT NULL,
+    application_id blob NOT NULL,
+    scopes text NOT NULL,
+    created_at text NOT NULL,
+    updated_at text NOT NULL,
+    PRIMARY KEY (user_id, application_id),
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE,
+    FOREIGN KEY (application_id) REFERENCES applications (application_id) ON DELETE CASCADE
+);
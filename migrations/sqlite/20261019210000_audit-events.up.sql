@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- the actor and target are not foreign keys, events outlive what they refer to
+CREATE TABLE audit_events (
+    audit_event_id blob NOT NULL PRIMARY KEY,
+    occurred_at text NOT NULL,
+    actor_id blob,
+    action text NOT NULL,
+    target_id blob,
+    ip_address text,
+    user_agent text,
+    outcome text NOT NULL,
+    detail text
+);
+
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id);
+CREATE INDEX audit_events_target_id_idx ON audit_events (target_id);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- authenticator apps users prove on login besides their password
+CREATE TABLE mfa_factors (
+    mfa_factor_id blob NOT NULL PRIMARY KEY,
+    user_id blob NOT NULL,
+    name text NOT NULL,
+    secret text NOT NULL,
+    last_step integer,
+    created_at text NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX mfa_factors_user_id_idx ON mfa_factors (user_id);
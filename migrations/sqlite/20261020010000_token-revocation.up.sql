@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- tokens issued to the user up to this time are refused, set when they sign out everywhere or their password changes
+ALTER TABLE users
+    ADD COLUMN tokens_valid_after text;